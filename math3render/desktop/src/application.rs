@@ -4,7 +4,7 @@ use pollster::block_on;
 use render::{
     application::{AppCommand, Application, WasmCanvas},
     camera::camera_controller::{self, CameraController, IsCameraController},
-    scene::{MaterialInfo, Model, ShaderId, ShaderInfo},
+    scene::{MaterialInfo, Model, ModelSource, ShaderId, ShaderInfo},
     transform::Transform,
 };
 use shaders::HEART_SPHERE;
@@ -64,7 +64,7 @@ pub fn run() -> anyhow::Result<()> {
             diffuse_texture: None,
             texture_scale: Vec2::ONE,
         },
-        shader_id,
+        source: ModelSource::Shader(shader_id),
         instance_count: 5,
     }]);
 
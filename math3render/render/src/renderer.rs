@@ -77,7 +77,7 @@ impl GpuApplication {
                 continue;
             }
 
-            if model_info.shader_id != game_model.shader_id
+            if model_info.source != game_model.source
                 || model_info.material_info.diffuse_texture
                     != game_model.material_info.diffuse_texture
             {
@@ -162,21 +162,28 @@ impl GpuApplication {
     }
 
     pub fn set_texture(&mut self, id: TextureId, info: &TextureInfo) {
-        use std::collections::hash_map::Entry;
         let texture = Texture::new_rgba(&self.context.device, &self.context.queue, info);
-
-        match self.parametric_renderer.textures.entry(id) {
-            Entry::Occupied(mut entry) => {
-                entry.get_mut().update(texture);
-            }
-            Entry::Vacant(entry) => {
-                entry.insert(ArcShift::new(texture));
-            }
-        };
+        // Replaced instead of updated in place, since the new texture can have another format than the old one
+        self.parametric_renderer
+            .textures
+            .insert(id.clone(), ArcShift::new(texture));
+        self.recreate_models_using(&id);
     }
 
     pub fn remove_texture(&mut self, id: &TextureId) {
         self.parametric_renderer.textures.remove(id);
+        self.recreate_models_using(id);
+    }
+
+    /// Models look up their textures when they are created, so they have to be recreated to see a texture that was
+    /// set or removed later
+    fn recreate_models_using(&mut self, id: &TextureId) {
+        for (model_info, parametric_model) in self.models.iter_mut() {
+            if model_info.uses_texture(id) {
+                *parametric_model =
+                    ParametricModel::new(&self.context, &self.parametric_renderer, model_info);
+            }
+        }
     }
 
     pub fn render(
@@ -281,8 +288,7 @@ impl GpuApplication {
                 parametric_model.lod_stage(
                     context,
                     &self.scene_data,
-                    &self.parametric_renderer.compute_patches,
-                    &self.parametric_renderer.copy_patches_pipeline,
+                    &self.parametric_renderer,
                     model_info.instance_count,
                     &mut commands,
                 );
@@ -326,7 +332,7 @@ impl GpuApplication {
                         context,
                         &mut render_pass,
                         &self.scene_data.scene_bind_group,
-                        &self.parametric_renderer.quad_meshes,
+                        &self.parametric_renderer,
                     );
                 }
 
@@ -3,11 +3,11 @@ use crate::{
     mesh::Mesh,
     renderer::{
         FrameData,
-        parametric_renderer::{ComputePatches, MAX_PATCH_COUNT, PATCH_SIZES, ParametricRenderer},
+        parametric_renderer::{MAX_PATCH_COUNT, PATCH_SIZES, ParametricRenderer},
         scene::SceneData,
         virtual_model::ShaderPipelines,
     },
-    scene::{MaterialInfo, Model},
+    scene::{GeometryImageInfo, MaterialInfo, Model, ModelSource},
     texture::Texture,
     transform::Transform,
     wgpu_context::WgpuContext,
@@ -15,6 +15,7 @@ use crate::{
 use arcshift::ArcShift;
 use encase::ShaderType;
 use glam::UVec2;
+use log::error;
 use shaders::{compute_patches, copy_patches, render_patches, uniforms_model, utils};
 use wgpu::Queue;
 
@@ -22,6 +23,8 @@ pub struct ParametricModel {
     model: TypedBuffer<render_patches::Model>,
     material: TypedBuffer<uniforms_model::Material>,
     t_diffuse: ArcShift<Texture>,
+    geometry_image: TypedBuffer<uniforms_model::GeometryImage>,
+    t_geometry: ArcShift<Texture>,
     shader: ArcShift<ShaderPipelines>,
     render: ParametricModelRender,
     lod: ParametricModelLod,
@@ -46,6 +49,49 @@ impl ParametricModel {
     pub fn new(context: &WgpuContext, renderer: &ParametricRenderer, model: &Model) -> Self {
         let device = &context.device;
 
+        let no_geometry_image = || uniforms_model::GeometryImage {
+            bounds_min: glam::Vec3::ZERO,
+            bounds_max: glam::Vec3::ZERO,
+        };
+        let (shader, geometry_image, t_geometry) = match &model.source {
+            ModelSource::Shader(shader_id) => (
+                renderer
+                    .shaders
+                    .get(shader_id)
+                    .cloned()
+                    .unwrap_or_else(|| renderer.missing_shader.clone()),
+                no_geometry_image(),
+                renderer.empty_geometry_image.clone(),
+            ),
+            ModelSource::GeometryImage(info) if !info.is_renderable() => {
+                error!(
+                    "Geometry image {} has the {} layout, only {:?} can be rendered",
+                    info.texture.0,
+                    info.layout,
+                    GeometryImageInfo::RENDERABLE_LAYOUTS
+                );
+                (
+                    renderer.missing_shader.clone(),
+                    no_geometry_image(),
+                    renderer.empty_geometry_image.clone(),
+                )
+            }
+            ModelSource::GeometryImage(info) => (
+                renderer.geometry_image_shader.clone(),
+                uniforms_model::GeometryImage {
+                    bounds_min: info.bounds_min,
+                    bounds_max: info.bounds_max,
+                },
+                // Binding a texture with a different format would fail the validation
+                renderer
+                    .textures
+                    .get(&info.texture)
+                    .cloned()
+                    .and_then(|mut texture| texture.get().is_geometry_image().then_some(texture))
+                    .unwrap_or_else(|| renderer.empty_geometry_image.clone()),
+            ),
+        };
+
         Self {
             model: device.uniform_buffer(
                 "Model Buffer",
@@ -65,12 +111,15 @@ impl ParametricModel {
                 .diffuse_texture
                 .as_ref()
                 .and_then(|id| renderer.textures.get(id).cloned())
+                .and_then(|mut texture| (!texture.get().is_geometry_image()).then_some(texture))
                 .unwrap_or_else(|| renderer.empty_texture.clone()),
-            shader: renderer
-                .shaders
-                .get(&model.shader_id)
-                .cloned()
-                .unwrap_or_else(|| renderer.missing_shader.clone()),
+            geometry_image: device.uniform_buffer(
+                "Geometry Image Buffer",
+                &geometry_image,
+                wgpu::BufferUsages::empty(),
+            ),
+            t_geometry,
+            shader,
 
             lod: ParametricModelLod::new(context),
             render: ParametricModelRender::new(context, &renderer.quad_meshes),
@@ -107,16 +156,29 @@ impl ParametricModel {
         );
     }
 
+    /// Binding a texture with another format than the binding expects would fail the validation. Models are
+    /// recreated when a texture is replaced, but a mismatch must never reach a bind group, so it falls back to the
+    /// empty textures.
+    fn check_texture_formats(&mut self, renderer: &ParametricRenderer) {
+        if self.t_diffuse.get().is_geometry_image() {
+            self.t_diffuse = renderer.empty_texture.clone();
+        }
+        if !self.t_geometry.get().is_geometry_image() {
+            self.t_geometry = renderer.empty_geometry_image.clone();
+        }
+    }
+
     pub fn lod_stage(
         &mut self,
         context: &WgpuContext,
         scene_data: &SceneData,
-        compute_patches: &ComputePatches,
-        copy_patches_pipeline: &wgpu::ComputePipeline,
+        renderer: &ParametricRenderer,
         instance_count: u32,
 
         commands: &mut wgpu_profiler::Scope<'_, wgpu::CommandEncoder>,
     ) {
+        self.check_texture_formats(renderer);
+        let compute_patches = &renderer.compute_patches;
         let queue = &context.queue;
         self.lod
             .force_render_uniform
@@ -167,6 +229,8 @@ impl ParametricModel {
                 render_buffer_32: self.render.render_buffer[4].as_buffer_binding(),
                 material: self.material.as_buffer_binding(),
                 t_diffuse: &self.t_diffuse.get().view,
+                geometry_image: self.geometry_image.as_buffer_binding(),
+                t_geometry: &self.t_geometry.get().view,
             },
         );
 
@@ -231,7 +295,7 @@ impl ParametricModel {
         }
         {
             let mut compute_pass = commands.scoped_compute_pass("Copy Patch Sizes Pass");
-            compute_pass.set_pipeline(copy_patches_pipeline);
+            compute_pass.set_pipeline(&renderer.copy_patches_pipeline);
             copy_patches::set_bind_groups(
                 &mut compute_pass.recorder,
                 &self.render.copy_patches_bind_group_0,
@@ -245,8 +309,9 @@ impl ParametricModel {
         context: &WgpuContext,
         render_pass: &mut wgpu_profiler::OwningScope<'_, wgpu::RenderPass<'_>>,
         scene_bind_group: &render_patches::bind_groups::BindGroup0,
-        quad_meshes: &[Mesh],
+        renderer: &ParametricRenderer,
     ) {
+        self.check_texture_formats(renderer);
         render_pass.set_pipeline(&self.shader.get().render);
         let model_bind_group = render_patches::bind_groups::BindGroup1::from_bindings(
             &context.device,
@@ -254,6 +319,8 @@ impl ParametricModel {
                 model: self.model.as_buffer_binding(),
                 material: self.material.as_buffer_binding(),
                 t_diffuse: &self.t_diffuse.get().view,
+                geometry_image: self.geometry_image.as_buffer_binding(),
+                t_geometry: &self.t_geometry.get().view,
             },
         );
 
@@ -261,7 +328,7 @@ impl ParametricModel {
             .render
             .render_bind_group_2
             .iter()
-            .zip(quad_meshes.iter())
+            .zip(renderer.quad_meshes.iter())
             .enumerate()
         {
             let buffer_offset = (i as u64)
@@ -17,7 +17,10 @@ pub struct ParametricRenderer {
     /// size/2 - 1 == one quad per four pixels
     pub quad_meshes: Vec<Mesh>,
     pub missing_shader: ArcShift<ShaderPipelines>,
+    /// Samples the geometry image of [`ModelSource::GeometryImage`](crate::scene::ModelSource::GeometryImage) models
+    pub geometry_image_shader: ArcShift<ShaderPipelines>,
     pub empty_texture: ArcShift<Texture>,
+    pub empty_geometry_image: ArcShift<Texture>,
     pub shaders: HashMap<ShaderId, ArcShift<ShaderPipelines>>,
     pub textures: HashMap<TextureId, ArcShift<Texture>>,
 
@@ -38,6 +41,11 @@ impl ParametricRenderer {
                     .unwrap()
                     .into(),
             ),
+            geometry_image_shader: ArcShift::new(
+                ShaderPipelines::new("Geometry Image", shaders::GEOMETRY_IMAGE, context)
+                    .unwrap()
+                    .into(),
+            ),
             empty_texture: ArcShift::new(
                 Texture::new_rgba(
                     &context.device,
@@ -50,6 +58,18 @@ impl ParametricRenderer {
                 )
                 .into(),
             ),
+            empty_geometry_image: ArcShift::new(
+                Texture::new_rgba(
+                    &context.device,
+                    &context.queue,
+                    &TextureInfo {
+                        width: 1,
+                        height: 1,
+                        data: TextureData::Rgba16(vec![0, 0, 0, 0]),
+                    },
+                )
+                .into(),
+            ),
             shaders: Default::default(),
             textures: Default::default(),
             copy_patches_pipeline: context.device.create_compute_pipeline(
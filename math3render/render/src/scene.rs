@@ -7,10 +7,51 @@ pub struct Model {
     pub name: String,
     pub transform: Transform,
     pub material_info: MaterialInfo,
-    pub shader_id: ShaderId,
+    pub source: ModelSource,
     pub instance_count: u32,
 }
 
+impl Model {
+    /// Whether the model samples the texture, either as its diffuse texture or as its geometry image
+    pub fn uses_texture(&self, id: &TextureId) -> bool {
+        self.material_info.diffuse_texture.as_ref() == Some(id)
+            || matches!(&self.source, ModelSource::GeometryImage(info) if &info.texture == id)
+    }
+}
+
+/// Where the surface of a model comes from
+#[derive(Clone, PartialEq, DeJson, SerJson)]
+pub enum ModelSource {
+    /// A shader with a `sampleObject` function
+    Shader(ShaderId),
+    /// A geometry image, sampled by a built-in shader
+    GeometryImage(GeometryImageInfo),
+}
+
+/// A geometry image like the ones from mesh2gim
+#[derive(Clone, PartialEq, DeJson, SerJson)]
+pub struct GeometryImageInfo {
+    /// Has to be uploaded with [`TextureData::Rgba16`]
+    pub texture: TextureId,
+    /// The name of the mesh2gim layout. Only [`GeometryImageInfo::RENDERABLE_LAYOUTS`] can be rendered.
+    pub layout: String,
+    /// The position that the texel value 0 stands for
+    #[nserde(proxy = "Vec3Nano")]
+    pub bounds_min: Vec3,
+    /// The position that the texel value 65535 stands for
+    #[nserde(proxy = "Vec3Nano")]
+    pub bounds_max: Vec3,
+}
+
+impl GeometryImageInfo {
+    /// The layouts whose borders fold like an unfolded octahedron, which is what the shader expects
+    pub const RENDERABLE_LAYOUTS: [&str; 2] = ["octahedral", "equal_area_octahedral"];
+
+    pub fn is_renderable(&self) -> bool {
+        Self::RENDERABLE_LAYOUTS.contains(&self.layout.as_str())
+    }
+}
+
 #[derive(Clone, PartialEq, DeJson, SerJson)]
 pub struct MaterialInfo {
     #[nserde(proxy = "Vec3Nano")]
@@ -45,6 +86,8 @@ pub struct TextureInfo {
 
 pub enum TextureData {
     Bytes(Vec<u8>),
+    /// Unfiltered 16 bit values, for geometry images
+    Rgba16(Vec<u16>),
     #[cfg(target_arch = "wasm32")]
     Image(web_sys::ImageBitmap),
 }
@@ -9,6 +9,8 @@ pub struct Texture {
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    /// Integer texels, so the positions keep all 16 bits. The shader does the filtering.
+    pub const GEOMETRY_IMAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Uint;
 
     pub fn new_rgba(device: &wgpu::Device, queue: &wgpu::Queue, info: &TextureInfo) -> Self {
         let size = wgpu::Extent3d {
@@ -22,7 +24,10 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format: match &info.data {
+                crate::scene::TextureData::Rgba16(_) => Self::GEOMETRY_IMAGE_FORMAT,
+                _ => wgpu::TextureFormat::Rgba8UnormSrgb,
+            },
             usage: wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_DST
                 | wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -48,6 +53,16 @@ impl Texture {
                 },
                 size,
             ),
+            crate::scene::TextureData::Rgba16(data) => queue.write_texture(
+                copy_texture,
+                bytemuck::cast_slice(data),
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(8 * size.width),
+                    rows_per_image: Some(size.height),
+                },
+                size,
+            ),
             #[cfg(target_arch = "wasm32")]
             crate::scene::TextureData::Image(image_bitmap) => {
                 queue.copy_external_image_to_texture(
@@ -66,6 +81,10 @@ impl Texture {
         Self { texture, view }
     }
 
+    pub fn is_geometry_image(&self) -> bool {
+        self.texture.format() == Self::GEOMETRY_IMAGE_FORMAT
+    }
+
     pub fn create_depth_texture(device: &wgpu::Device, size: UVec2, label: &str) -> Self {
         let desc = wgpu::TextureDescriptor {
             label: Some(label),
@@ -2,6 +2,8 @@
 use wesl::{Pkg, PkgModule};
 pub const DEFAULT_PARAMETRIC: &str = include_str!("../wgsl/samples/DefaultParametric.wgsl");
 pub const HEART_SPHERE: &str = include_str!("../wgsl/samples/HeartSphere.wgsl");
+/// Renders the geometry image bound to `t_geometry`
+pub const GEOMETRY_IMAGE: &str = include_str!("../wgsl/samples/GeometryImage.wgsl");
 
 include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
 include!(concat!(env!("OUT_DIR"), "/my_package.rs"));
@@ -0,0 +1,51 @@
+import package::uniforms_model::{geometry_image, t_geometry};
+
+// Folds a point outside of the image back in. The octahedral layout mirrors the left and right columns top to
+// bottom, and the top and bottom rows left to right, so the image continues on the other side of the fold.
+fn foldOctahedral(input: vec2f) -> vec2f {
+    var p = input;
+    if p.x < 0.0 {
+        p = vec2(-p.x, 1.0 - p.y);
+    } else if p.x > 1.0 {
+        p = vec2(2.0 - p.x, 1.0 - p.y);
+    }
+    if p.y < 0.0 {
+        p = vec2(1.0 - p.x, -p.y);
+    } else if p.y > 1.0 {
+        p = vec2(1.0 - p.x, 2.0 - p.y);
+    }
+    return clamp(p, vec2(0.0), vec2(1.0));
+}
+
+// Texels that were filled in for a hole have an alpha of 0. They keep a tiny weight, so they do not pull the surface
+// towards them next to valid texels, but still give a position when every nearby texel is a filled in one.
+const HOLE_WEIGHT = 0.001;
+
+// The dequantized position of one texel times its weight, and the weight
+fn weightedPosition(texel: vec2i, bilinear_weight: f32) -> vec4f {
+    let value = textureLoad(t_geometry, texel, 0);
+    let position = mix(geometry_image.bounds_min, geometry_image.bounds_max, vec3f(value.rgb) / 65535.0);
+    let weight = bilinear_weight * max(f32(value.a) / 65535.0, HOLE_WEIGHT);
+    return vec4(position * weight, weight);
+}
+
+fn sampleObject(input: vec2f) -> vec3f {
+    let size = vec2i(textureDimensions(t_geometry));
+    // The first and last texels are right on the edges of the image, so 0 and 1 land exactly on them
+    let texel = foldOctahedral(input) * vec2f(size - 1);
+    let lower = min(vec2i(floor(texel)), max(size - 2, vec2i(0)));
+    let upper = min(lower + 1, size - 1);
+    let t = texel - vec2f(lower);
+
+    // Bilinear filtering by hand, integer textures can not be filtered by the sampler
+    let sum = weightedPosition(lower, (1.0 - t.x) * (1.0 - t.y))
+        + weightedPosition(vec2(upper.x, lower.y), t.x * (1.0 - t.y))
+        + weightedPosition(vec2(lower.x, upper.y), (1.0 - t.x) * t.y)
+        + weightedPosition(upper, t.x * t.y);
+    // The bilinear weights add up to 1, so the sum is at least HOLE_WEIGHT
+    return sum.xyz / sum.w;
+}
+
+fn getColor(input: vec2f, base_color: vec3f) -> vec3f {
+    return base_color;
+}
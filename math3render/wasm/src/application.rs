@@ -1,6 +1,10 @@
-use crate::wasm_abi::{WasmCompilationMessage, WasmModelInfo, WasmPosition, WasmShaderInfo};
+use crate::wasm_abi::{
+    WasmCompilationMessage, WasmGeometryImage, WasmMeshImportOptions, WasmModelInfo, WasmPosition,
+    WasmShaderInfo,
+};
 use glam::Vec3;
 use log::error;
+use mesh2gim::encoding::rgba16_from_png;
 use render::{
     application::{
         AppCommand, AppCommands, Application, ShaderCompiledCallback, WasmCanvas, run_on_main,
@@ -10,7 +14,7 @@ use render::{
         camera_controller::{self, CameraController, IsCameraController},
         orbitcam_controller::LogarithmicDistance,
     },
-    scene::{Model, ShaderId, ShaderInfo, TextureData, TextureId, TextureInfo},
+    scene::{Model, ModelSource, ShaderId, ShaderInfo, TextureData, TextureId, TextureInfo},
 };
 use std::sync::Arc;
 use tsify::JsValueSerdeExt;
@@ -79,7 +83,10 @@ impl WasmApplication {
                 name: v.id,
                 transform: v.transform.into(),
                 material_info: v.material_info.into(),
-                shader_id: ShaderId(v.shader_id),
+                source: match v.geometry_image {
+                    Some(geometry_image) => ModelSource::GeometryImage(geometry_image.into()),
+                    None => ModelSource::Shader(ShaderId(v.shader_id)),
+                },
                 instance_count: v.instance_count,
             })
             .collect::<Vec<_>>();
@@ -137,6 +144,36 @@ impl WasmApplication {
         });
     }
 
+    /// Turns a mesh file into a geometry image with mesh2gim, see [`crate::mesh_import::import_mesh`]. This runs on
+    /// the thread of the application, so the page freezes for big meshes. The frontend calls the free function
+    /// from a worker instead.
+    pub fn import_mesh(
+        &self,
+        file_name: String,
+        bytes: &[u8],
+        options: WasmMeshImportOptions,
+        on_progress: Option<web_sys::js_sys::Function>,
+    ) -> Result<WasmGeometryImage, JsError> {
+        crate::mesh_import::import_mesh(file_name, bytes, options, on_progress)
+    }
+
+    /// Uploads a 16 bit `.png` file, like the one from `import_mesh`, for models with a `geometry_image`.
+    /// An 8 bit browser image would lose most of the precision of the positions.
+    pub fn update_geometry_image(&self, texture_id: String, png: &[u8]) -> Result<(), JsError> {
+        let id = TextureId(texture_id);
+        let (width, height, data) = rgba16_from_png(png)?;
+        let info = TextureInfo {
+            width,
+            height,
+            data: TextureData::Rgba16(data),
+        };
+
+        run_on_main(self.event_loop_proxy.clone(), move |app| {
+            app.renderer.set_texture(id, &info);
+        });
+        Ok(())
+    }
+
     pub fn remove_texture(&self, id: String) {
         run_on_main(self.event_loop_proxy.clone(), |app| {
             app.renderer.remove_texture(&TextureId(id));
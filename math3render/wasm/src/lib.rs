@@ -1,4 +1,5 @@
 mod application;
+mod mesh_import;
 pub mod wasm_abi;
 
 use log::Level;
@@ -0,0 +1,162 @@
+use crate::wasm_abi::{WasmGeometryImage, WasmMeshImportOptions};
+use log::error;
+use mesh2gim::{
+    GimOptions, Progress, decimate_for_parametrization,
+    encoding::{Quantization, encode_rgba16, rgba16_to_png},
+    loaders::{LoadError, MeshFormat, parse_mesh},
+    metadata::{METADATA_VERSION, Metadata, ParameterMetadata, RectMetadata, SourceMetadata},
+    parametrization::{HoleFilling, Initialization, LaplacianWeights, check_size, layout_by_name},
+    parametrize_mesh,
+    repair::{RepairOptions, repair_mesh},
+    sample_full_mesh, sample_mesh,
+};
+use std::path::Path;
+use wasm_bindgen::{JsError, JsValue, prelude::wasm_bindgen};
+use web_sys::js_sys::Function;
+
+/// Calls the `on_progress` function of [`import_mesh`] with the name of a phase and how much of it is done
+struct ProgressCallback(Function);
+
+// SAFETY: Without atomics, wasm has no threads, so the function is never shared between them. mesh2gim only asks for
+// `Sync` because its progress callback could be called from a thread pool.
+#[cfg(not(target_feature = "atomics"))]
+unsafe impl Sync for ProgressCallback {}
+
+#[cfg(target_feature = "atomics")]
+compile_error!("the progress callback of import_mesh is not thread safe, it can not be built with atomics");
+
+impl ProgressCallback {
+    fn report(&self, phase: &str, fraction: f64) {
+        let this = JsValue::NULL;
+        if let Err(e) = self
+            .0
+            .call2(&this, &JsValue::from_str(phase), &JsValue::from_f64(fraction))
+        {
+            error!("Error calling on_progress: {e:?}");
+        }
+    }
+
+    fn report_gim(&self, progress: Progress) {
+        let fraction = |done: u32, total: u32| done as f64 / total.max(1) as f64;
+        match progress {
+            Progress::Decimation => self.report("decimation", 0.0),
+            Progress::Adjacency => self.report("adjacency", 0.0),
+            Progress::Smoothing {
+                iteration,
+                max_iterations,
+            } => self.report("smoothing", fraction(iteration, max_iterations)),
+            Progress::Resampling {
+                iteration,
+                max_iterations,
+            } => self.report("resampling", fraction(iteration, max_iterations)),
+            Progress::Grouping => self.report("grouping", 0.0),
+            Progress::Rasterization { row, rows } => {
+                self.report("rasterization", fraction(row, rows))
+            }
+            Progress::Projection => self.report("projection", 0.0),
+        }
+    }
+}
+
+/// Turns a mesh file into a geometry image with mesh2gim, so importing a mesh needs no command line. The format
+/// is detected from the file name, or else from the bytes.
+///
+/// This takes a while for big meshes, and does not need the renderer, so the frontend runs it in a worker.
+/// `on_progress` is called with the name of the running phase and the fraction of it that is done.
+#[wasm_bindgen]
+pub fn import_mesh(
+    file_name: String,
+    bytes: &[u8],
+    options: WasmMeshImportOptions,
+    on_progress: Option<Function>,
+) -> Result<WasmGeometryImage, JsError> {
+    let on_progress = on_progress.map(ProgressCallback);
+    let report_phase = |phase: &str| {
+        if let Some(on_progress) = &on_progress {
+            on_progress.report(phase, 0.0);
+        }
+    };
+
+    let layout = layout_by_name(&options.layout)
+        .ok_or_else(|| JsError::new(&format!("unknown layout {}", options.layout)))?;
+    let initialization = options
+        .initialization
+        .parse::<Initialization>()
+        .map_err(|e| JsError::new(&e))?;
+    let quantization = options
+        .quantization
+        .parse::<Quantization>()
+        .map_err(|e| JsError::new(&e))?;
+    let size = (options.width, options.height);
+    check_size(size, layout)?;
+    let mut gim_options = GimOptions::new(size)
+        .layout(layout)
+        .initialization(initialization)
+        .iterations(options.iterations)
+        .decimate(options.decimate as usize);
+    if let Some(on_progress) = &on_progress {
+        gim_options = gim_options.progress(|progress| on_progress.report_gim(progress));
+    }
+
+    report_phase("loading");
+    let format = MeshFormat::from_extension(Path::new(&file_name))
+        .or_else(|| MeshFormat::from_magic_bytes(bytes))
+        .ok_or(LoadError::UnknownFormat)?;
+    let repair_options = RepairOptions::default();
+    let (mesh, report) = repair_mesh(&parse_mesh(bytes, format)?, &repair_options)?;
+
+    // The browser has no threads, so this runs on one thread like the mesh2gim tool with `--threads 1`
+    let decimated = decimate_for_parametrization(&mesh, &gim_options)?;
+    let surface = decimated.as_ref().unwrap_or(&mesh);
+    let parametrization = parametrize_mesh(surface, &gim_options)?;
+    let mut grid = sample_mesh(surface, &parametrization.vertices, &gim_options)?;
+    if let Some(decimated) = &decimated {
+        grid = sample_full_mesh(&grid, decimated, &mesh, &gim_options)?;
+    }
+
+    report_phase("encoding");
+    let image = grid.to_image(&mesh.positions);
+    let (data, range) = encode_rgba16(&image, quantization);
+    let png = rgba16_to_png(image.width, image.height, data.clone())?;
+
+    let stem = Path::new(&file_name)
+        .file_stem()
+        .map_or("mesh".into(), |stem| stem.to_string_lossy());
+    let metadata = Metadata {
+        version: METADATA_VERSION,
+        layout: layout.name().to_string(),
+        width: image.width,
+        height: image.height,
+        // The frontend saves the image under this name, and this document next to it as `{stem}.gim.json`
+        file: format!("{stem}.gim.png"),
+        bit_depth: 16,
+        quantization: quantization.to_string(),
+        min: range.min.into(),
+        max: range.max.into(),
+        attributes: vec![],
+        charts: layout.charts(size).iter().map(RectMetadata::from).collect(),
+        mipmaps: vec![],
+        frames: None,
+        source: SourceMetadata::new(file_name.clone(), &mesh, &report),
+        parameters: ParameterMetadata {
+            weld_tolerance: repair_options.weld_tolerance,
+            initialization: Some(parametrization.initialization.to_string()),
+            weights: Some(LaplacianWeights::Uniform.to_string()),
+            max_iterations: options.iterations,
+            iterations: Some(parametrization.iterations),
+            converged: Some(parametrization.converged),
+            hole_filling: Some(HoleFilling::default().to_string()),
+            area_resampling: Some(0),
+            decimate: Some(options.decimate as usize),
+        },
+    };
+    Ok(WasmGeometryImage::new(
+        image.width,
+        image.height,
+        data,
+        png,
+        range.min.into(),
+        range.max.into(),
+        metadata.to_json(),
+    ))
+}
@@ -3,6 +3,7 @@
 use render::scene::TextureId;
 use serde::{Deserialize, Serialize};
 use tsify::Tsify;
+use wasm_bindgen::prelude::wasm_bindgen;
 
 #[derive(Tsify, Serialize, Deserialize)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -11,9 +12,35 @@ pub struct WasmModelInfo {
     pub transform: WasmTransform,
     pub material_info: WasmMaterialInfo,
     pub shader_id: String,
+    /// Renders this geometry image instead of the shader
+    #[serde(default)]
+    #[tsify(optional)]
+    pub geometry_image: Option<WasmGeometryImageSource>,
     pub instance_count: u32,
 }
 
+/// A geometry image texture from `update_geometry_image`, with the range from its metadata
+#[derive(Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct WasmGeometryImageSource {
+    pub texture: String,
+    /// The layout from the metadata. Only "octahedral" and "equal_area_octahedral" can be rendered.
+    pub layout: String,
+    pub bounds_min: [f32; 3],
+    pub bounds_max: [f32; 3],
+}
+
+impl From<WasmGeometryImageSource> for render::scene::GeometryImageInfo {
+    fn from(v: WasmGeometryImageSource) -> Self {
+        render::scene::GeometryImageInfo {
+            texture: TextureId(v.texture),
+            layout: v.layout,
+            bounds_min: v.bounds_min.into(),
+            bounds_max: v.bounds_max.into(),
+        }
+    }
+}
+
 #[derive(Tsify, Serialize, Deserialize)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct WasmTransform {
@@ -139,3 +166,97 @@ impl From<wgpu::SourceLocation> for WasmSourceLocation {
         }
     }
 }
+
+/// How an imported mesh is turned into a geometry image
+#[derive(Tsify, Serialize, Deserialize)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct WasmMeshImportOptions {
+    pub width: u32,
+    pub height: u32,
+    /// "octahedral", "equal_area_octahedral" or "cube_map"
+    pub layout: String,
+    /// "centroid", "tutte" or "spectral"
+    pub initialization: String,
+    pub iterations: u32,
+    /// Meshes with more triangles are decimated before the parametrization, 0 never decimates
+    pub decimate: u32,
+    /// "bounds" or "uniform"
+    pub quantization: String,
+}
+
+/// A geometry image made from an imported mesh
+#[wasm_bindgen]
+pub struct WasmGeometryImage {
+    width: u32,
+    height: u32,
+    data: Vec<u16>,
+    png: Vec<u8>,
+    bounds_min: [f32; 3],
+    bounds_max: [f32; 3],
+    metadata: String,
+}
+
+impl WasmGeometryImage {
+    pub fn new(
+        width: u32,
+        height: u32,
+        data: Vec<u16>,
+        png: Vec<u8>,
+        bounds_min: [f32; 3],
+        bounds_max: [f32; 3],
+        metadata: String,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            data,
+            png,
+            bounds_min,
+            bounds_max,
+            metadata,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl WasmGeometryImage {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The quantized positions as RGBA16, row by row from the top. Alpha is 0 where the mesh had a hole.
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Vec<u16> {
+        self.data.clone()
+    }
+
+    /// The same pixels as [`Self::data`], as a 16 bit `.png` file that can be saved with the project
+    #[wasm_bindgen(getter)]
+    pub fn png(&self) -> Vec<u8> {
+        self.png.clone()
+    }
+
+    /// The position that a quantized 0 stands for
+    #[wasm_bindgen(getter)]
+    pub fn bounds_min(&self) -> Vec<f32> {
+        self.bounds_min.to_vec()
+    }
+
+    /// The position that a quantized 65535 stands for
+    #[wasm_bindgen(getter)]
+    pub fn bounds_max(&self) -> Vec<f32> {
+        self.bounds_max.to_vec()
+    }
+
+    /// The mesh2gim metadata document, with the range that dequantizes the positions
+    #[wasm_bindgen(getter)]
+    pub fn metadata(&self) -> String {
+        self.metadata.clone()
+    }
+}
@@ -0,0 +1,93 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use glam::Vec3;
+use mesh2gim::{
+    parametrization::{
+        spherical_parametrization, to_image, HoleFilling, Octahedral, ParametrizationOptions,
+    },
+    Mesh,
+};
+
+/// A closed UV sphere with `rings * segments + 2` vertices
+fn uv_sphere(rings: u32, segments: u32) -> Mesh {
+    let mut positions = vec![Vec3::Y, Vec3::NEG_Y];
+    for ring in 1..=rings {
+        let theta = std::f32::consts::PI * ring as f32 / (rings + 1) as f32;
+        for segment in 0..segments {
+            let phi = std::f32::consts::TAU * segment as f32 / segments as f32;
+            positions.push(Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            ));
+        }
+    }
+    let vertex = |ring: u32, segment: u32| 2 + (ring - 1) * segments + segment % segments;
+    let mut indices = Vec::new();
+    for segment in 0..segments {
+        indices.extend_from_slice(&[0, vertex(1, segment + 1), vertex(1, segment)]);
+        indices.extend_from_slice(&[1, vertex(rings, segment), vertex(rings, segment + 1)]);
+    }
+    for ring in 1..rings {
+        for segment in 0..segments {
+            let a = vertex(ring, segment);
+            let b = vertex(ring, segment + 1);
+            let c = vertex(ring + 1, segment);
+            let d = vertex(ring + 1, segment + 1);
+            indices.extend_from_slice(&[a, b, c, b, d, c]);
+        }
+    }
+    Mesh {
+        positions,
+        attributes: vec![],
+        indices,
+    }
+}
+
+fn bench_spherical_parametrization(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spherical_parametrization");
+    group.sample_size(10);
+    for (rings, segments) in [(50, 100), (100, 200), (200, 400)] {
+        let mesh = uv_sphere(rings, segments);
+        let options = ParametrizationOptions {
+            tolerance: 0.0,
+            max_iterations: 100,
+            ..Default::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::from_parameter(mesh.positions.len()),
+            &mesh,
+            |b, mesh| b.iter(|| spherical_parametrization(black_box(mesh), &options)),
+        );
+    }
+    group.finish();
+}
+
+fn bench_to_image(c: &mut Criterion) {
+    let mut group = c.benchmark_group("to_image");
+    group.sample_size(10);
+    // About 100k triangles
+    let mesh = uv_sphere(160, 320);
+    let options = ParametrizationOptions {
+        max_iterations: 10,
+        ..Default::default()
+    };
+    let parametrization = spherical_parametrization(&mesh, &options);
+    for size in [255, 1023] {
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter(|| {
+                to_image(
+                    &mesh,
+                    &parametrization.vertices,
+                    (size, size),
+                    HoleFilling::default(),
+                    &Octahedral,
+                )
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_spherical_parametrization, bench_to_image);
+criterion_main!(benches);
@@ -0,0 +1,116 @@
+use std::ops::Range;
+
+use glam::UVec2;
+
+/// Vertex adjacency of a mesh, stored in the compressed sparse row format.
+/// The neighbors of vertex `i` are `neighbors[offsets[i]..offsets[i + 1]]`, sorted and without duplicates.
+pub struct Adjacency {
+    offsets: Vec<u32>,
+    neighbors: Vec<u32>,
+}
+
+impl Adjacency {
+    /// Builds a symmetric adjacency from a list of edges. Every edge is inserted in both directions.
+    pub fn from_edges(vertex_count: usize, edges: &[UVec2]) -> Self {
+        // Count how many (possibly duplicated) neighbors every vertex has
+        let mut offsets = vec![0u32; vertex_count + 1];
+        for edge in edges {
+            offsets[edge.x as usize + 1] += 1;
+            offsets[edge.y as usize + 1] += 1;
+        }
+        for i in 0..vertex_count {
+            offsets[i + 1] += offsets[i];
+        }
+
+        // Scatter the edges into their rows
+        let mut cursor = offsets.clone();
+        let mut neighbors = vec![0u32; offsets[vertex_count] as usize];
+        for edge in edges {
+            neighbors[cursor[edge.x as usize] as usize] = edge.y;
+            cursor[edge.x as usize] += 1;
+            neighbors[cursor[edge.y as usize] as usize] = edge.x;
+            cursor[edge.y as usize] += 1;
+        }
+
+        // Sort every row and remove the duplicates, compacting the rows as we go
+        let mut write = 0;
+        for i in 0..vertex_count {
+            let start = offsets[i] as usize;
+            let end = offsets[i + 1] as usize;
+            neighbors[start..end].sort_unstable();
+            offsets[i] = write as u32;
+            let mut previous = None;
+            for read in start..end {
+                let neighbor = neighbors[read];
+                if previous != Some(neighbor) {
+                    neighbors[write] = neighbor;
+                    write += 1;
+                    previous = Some(neighbor);
+                }
+            }
+        }
+        offsets[vertex_count] = write as u32;
+        neighbors.truncate(write);
+
+        Self { offsets, neighbors }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Number of directed edges, which is twice the number of undirected edges
+    pub fn edge_count(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    pub fn neighbors(&self, vertex: usize) -> &[u32] {
+        &self.neighbors[self.edge_range(vertex)]
+    }
+
+    /// The indices of the directed edges starting at `vertex`. Useful for storing per-edge data next to the adjacency.
+    pub fn edge_range(&self, vertex: usize) -> Range<usize> {
+        self.offsets[vertex] as usize..self.offsets[vertex + 1] as usize
+    }
+
+    /// The index of the directed edge `from -> to`, if it exists
+    pub fn edge_index(&self, from: usize, to: usize) -> Option<usize> {
+        let range = self.edge_range(from);
+        self.neighbors[range.clone()]
+            .binary_search(&(to as u32))
+            .ok()
+            .map(|i| range.start + i)
+    }
+
+    pub fn degree(&self, vertex: usize) -> usize {
+        (self.offsets[vertex + 1] - self.offsets[vertex]) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_edges() {
+        // Two triangles sharing the edge 1-2, with a duplicated edge
+        let edges = [
+            UVec2::new(0, 1),
+            UVec2::new(1, 2),
+            UVec2::new(2, 0),
+            UVec2::new(2, 1),
+            UVec2::new(1, 3),
+            UVec2::new(3, 2),
+        ];
+        let adjacency = Adjacency::from_edges(5, &edges);
+        assert_eq!(adjacency.vertex_count(), 5);
+        assert_eq!(adjacency.neighbors(0), &[1, 2]);
+        assert_eq!(adjacency.neighbors(1), &[0, 2, 3]);
+        assert_eq!(adjacency.neighbors(2), &[0, 1, 3]);
+        assert_eq!(adjacency.neighbors(3), &[1, 2]);
+        assert_eq!(adjacency.degree(4), 0);
+        assert_eq!(adjacency.edge_count(), 10);
+        assert_eq!(adjacency.edge_index(2, 3), Some(7));
+        assert_eq!(adjacency.edge_index(0, 3), None);
+    }
+}
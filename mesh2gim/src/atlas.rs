@@ -0,0 +1,580 @@
+//! Geometry image atlases. The mesh is cut into disk-like charts, every chart is mapped to a square, and the squares
+//! are packed into one image. Unlike the spherical parametrization, this works for meshes of any genus.
+
+use std::collections::{HashMap, HashSet};
+
+use glam::{UVec3, Vec2, Vec3};
+
+use crate::{
+    adjacency::Adjacency,
+    check_mesh,
+    parametrization::{laplacian_weights, LaplacianWeights, SampleGrid},
+    repair::Topology,
+    sampling::PlanarSampler,
+    GeometryImage, Mesh, Mesh2GimError,
+};
+
+/// Empty pixels around every chart, so that filtering does not bleed between charts
+const GUTTER: u32 = 1;
+/// Charts need at least two pixels per side to have their corners on different pixels
+const MIN_CHART_SIZE: u32 = 2;
+/// Pixels closer than this to a chart triangle, in the unit square of the chart, count as covered
+const COVERAGE_TOLERANCE: f32 = 1e-5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasOptions {
+    /// Charts with more triangles than this are split further. Smaller charts have less distortion, and are faster
+    /// to parametrize, but add more seams.
+    pub max_chart_faces: usize,
+    /// Stop relaxing a chart once no vertex moves further than this in the unit square
+    pub tolerance: f32,
+    pub max_iterations: u32,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            max_chart_faces: 10_000,
+            tolerance: 1e-6,
+            max_iterations: 10_000,
+        }
+    }
+}
+
+/// Where a chart is in the atlas, in pixels. The corners of the chart's square are at the centers of the corner
+/// pixels of the rectangle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChartRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct Atlas {
+    /// Pixels outside of the charts are marked as invalid. The gutter around every chart repeats its border pixels.
+    pub image: GeometryImage,
+    pub charts: Vec<ChartRect>,
+    /// Triangles that could not be mapped to a square even on their own, like triangles with a repeated corner.
+    /// They are left out of the image.
+    pub dropped_triangles: usize,
+}
+
+/// A part of the mesh that is mapped to the unit square
+struct Chart {
+    /// Triangles of the chart, indexing `vertices`
+    triangles: Vec<UVec3>,
+    /// The mesh vertex of every chart vertex
+    vertices: Vec<u32>,
+    /// Position of every chart vertex in the unit square
+    uvs: Vec<Vec2>,
+    /// Surface area of the chart on the mesh, which decides how many pixels it gets
+    area: f32,
+}
+
+pub fn make_geometry_image_atlas(
+    mesh: &Mesh,
+    size: (u32, u32),
+    options: &AtlasOptions,
+) -> Result<Atlas, Mesh2GimError> {
+    check_mesh(mesh)?;
+    let min_size = MIN_CHART_SIZE + 2 * GUTTER;
+    if size.0 < min_size || size.1 < min_size {
+        return Err(Mesh2GimError::InvalidSize {
+            width: size.0,
+            height: size.1,
+            min_width: min_size,
+            min_height: min_size,
+        });
+    }
+    let (charts, dropped_triangles) = make_charts(mesh, options);
+    let areas: Vec<f32> = charts.iter().map(|chart| chart.area).collect();
+    let rects = pack_charts(&areas, size)?;
+
+    let pixel_count = (size.0 * size.1) as usize;
+    let mut samples = vec![None; pixel_count];
+    let mut hits = vec![false; pixel_count];
+    for (chart, rect) in charts.iter().zip(&rects) {
+        let sampler = PlanarSampler::new(chart.triangles.clone(), &chart.uvs);
+        for y in 0..rect.height {
+            for x in 0..rect.width {
+                let uv = Vec2::new(x as f32, y as f32)
+                    / Vec2::new(rect.width as f32 - 1.0, rect.height as f32 - 1.0);
+                let Some((mut sample, distance)) = sampler.nearest(uv) else {
+                    continue;
+                };
+                sample.triangle = UVec3::from_array(
+                    sample
+                        .triangle
+                        .to_array()
+                        .map(|i| chart.vertices[i as usize]),
+                );
+                let index = ((rect.y + y) * size.0 + rect.x + x) as usize;
+                samples[index] = Some(sample);
+                hits[index] = distance < COVERAGE_TOLERANCE;
+            }
+        }
+
+        // Repeat the border pixels into the gutter, but keep them marked as invalid
+        for y in rect.y - GUTTER..rect.y + rect.height + GUTTER {
+            for x in rect.x - GUTTER..rect.x + rect.width + GUTTER {
+                let source_x = x.clamp(rect.x, rect.x + rect.width - 1);
+                let source_y = y.clamp(rect.y, rect.y + rect.height - 1);
+                if (source_x, source_y) != (x, y) {
+                    samples[(y * size.0 + x) as usize] =
+                        samples[(source_y * size.0 + source_x) as usize];
+                }
+            }
+        }
+    }
+
+    let grid = SampleGrid::from_samples(size, samples, hits);
+    Ok(Atlas {
+        image: GeometryImage {
+            positions: grid.to_image(&mesh.positions),
+            attributes: mesh
+                .attributes
+                .iter()
+                .map(|attribute| grid.to_attribute_image(attribute))
+                .collect(),
+            resampling: None,
+        },
+        charts: rects,
+        dropped_triangles,
+    })
+}
+
+/// Cuts the mesh into charts and parametrizes them. Triangles that face the same axis direction start out in the
+/// same chart, charts that are not disks or that are too large are split in half until they are. Single triangles
+/// that are still not disks can not be split further, and are dropped. Returns the charts and how many triangles
+/// were dropped.
+fn make_charts(mesh: &Mesh, options: &AtlasOptions) -> (Vec<Chart>, usize) {
+    let triangles: Vec<UVec3> = mesh.triangles().collect();
+    let corners = |triangle: UVec3| triangle.to_array().map(|i| mesh.positions[i as usize]);
+    let centroids: Vec<Vec3> = triangles
+        .iter()
+        .map(|&triangle| corners(triangle).iter().sum::<Vec3>() / 3.0)
+        .collect();
+    let directions: Vec<usize> = triangles
+        .iter()
+        .map(|&triangle| {
+            let [a, b, c] = corners(triangle);
+            let normal = (b - a).cross(c - a);
+            let axis = largest_axis(normal.abs());
+            axis * 2 + usize::from(normal[axis] < 0.0)
+        })
+        .collect();
+
+    let mut edge_triangles: HashMap<(u32, u32), Vec<u32>> = HashMap::new();
+    for (i, triangle) in triangles.iter().enumerate() {
+        for (a, b) in [
+            (triangle.x, triangle.y),
+            (triangle.y, triangle.z),
+            (triangle.z, triangle.x),
+        ] {
+            edge_triangles
+                .entry((a.min(b), a.max(b)))
+                .or_default()
+                .push(i as u32);
+        }
+    }
+    let components = |subset: &[u32], same_chart: &dyn Fn(u32, u32) -> bool| {
+        let mut remaining: HashSet<u32> = subset.iter().copied().collect();
+        let mut components = Vec::new();
+        for &start in subset {
+            if !remaining.remove(&start) {
+                continue;
+            }
+            let mut component = vec![start];
+            let mut next = 0;
+            while next < component.len() {
+                let triangle = triangles[component[next] as usize];
+                next += 1;
+                for (a, b) in [
+                    (triangle.x, triangle.y),
+                    (triangle.y, triangle.z),
+                    (triangle.z, triangle.x),
+                ] {
+                    for &neighbor in &edge_triangles[&(a.min(b), a.max(b))] {
+                        if same_chart(start, neighbor) && remaining.remove(&neighbor) {
+                            component.push(neighbor);
+                        }
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components
+    };
+
+    let all: Vec<u32> = (0..triangles.len() as u32).collect();
+    let mut pending = components(&all, &|a, b| {
+        directions[a as usize] == directions[b as usize]
+    });
+    let mut charts = Vec::new();
+    let mut dropped = 0;
+    while let Some(subset) = pending.pop() {
+        if subset.len() <= options.max_chart_faces {
+            let chart_triangles: Vec<UVec3> =
+                subset.iter().map(|&i| triangles[i as usize]).collect();
+            if let Some(chart) = parametrize_chart(mesh, &chart_triangles, options) {
+                charts.push(chart);
+                continue;
+            }
+        }
+        if subset.len() <= 1 {
+            dropped += subset.len();
+            continue;
+        }
+        // Split at the median of the triangle centroids, along the longest side of the chart
+        let (min, max) = subset.iter().fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(min, max), &i| {
+                (
+                    min.min(centroids[i as usize]),
+                    max.max(centroids[i as usize]),
+                )
+            },
+        );
+        let axis = largest_axis(max - min);
+        let mut sorted = subset;
+        sorted
+            .sort_by(|&a, &b| centroids[a as usize][axis].total_cmp(&centroids[b as usize][axis]));
+        let (first, second) = sorted.split_at(sorted.len() / 2);
+        for half in [first, second] {
+            pending.extend(components(half, &|_, _| true));
+        }
+    }
+    (charts, dropped)
+}
+
+fn largest_axis(v: Vec3) -> usize {
+    if v.x >= v.y && v.x >= v.z {
+        0
+    } else if v.y >= v.z {
+        1
+    } else {
+        2
+    }
+}
+
+/// Maps the boundary of the chart to the border of the unit square, and places the inner vertices with Floater's
+/// mean value weights, which can not fold over. Triangles with all corners on one side of the square collapse to a
+/// line, the pixels there sample the neighboring triangles. Returns `None` if the chart is not a disk.
+fn parametrize_chart(mesh: &Mesh, triangles: &[UVec3], options: &AtlasOptions) -> Option<Chart> {
+    let mut local_indices: HashMap<u32, u32> = HashMap::new();
+    let mut vertices = Vec::new();
+    let local_triangles: Vec<UVec3> = triangles
+        .iter()
+        .map(|triangle| {
+            UVec3::from_array(triangle.to_array().map(|vertex| {
+                *local_indices.entry(vertex).or_insert_with(|| {
+                    vertices.push(vertex);
+                    vertices.len() as u32 - 1
+                })
+            }))
+        })
+        .collect();
+    let local_mesh = Mesh {
+        positions: vertices
+            .iter()
+            .map(|&i| mesh.positions[i as usize])
+            .collect(),
+        attributes: vec![],
+        indices: local_triangles.iter().flat_map(|t| t.to_array()).collect(),
+    };
+    let topology = Topology::of(&local_mesh);
+    if topology.components != 1 || topology.boundary_loops != 1 || topology.genus != Some(0) {
+        return None;
+    }
+
+    let boundary = boundary_loop(&local_triangles)?;
+    let mut uvs = vec![Vec2::splat(0.5); vertices.len()];
+    let mut fixed = vec![false; vertices.len()];
+    for (&vertex, uv) in boundary
+        .iter()
+        .zip(square_border(&local_mesh.positions, &boundary))
+    {
+        uvs[vertex as usize] = uv;
+        fixed[vertex as usize] = true;
+    }
+
+    // Gauss-Seidel relaxation of the inner vertices
+    let adjacency = Adjacency::from_edges(vertices.len(), &local_mesh.edges());
+    let weights = laplacian_weights(&local_mesh, &adjacency, LaplacianWeights::MeanValue);
+    for _ in 0..options.max_iterations {
+        let mut max_movement = 0.0f32;
+        for i in 0..vertices.len() {
+            if fixed[i] {
+                continue;
+            }
+            let relaxed: Vec2 = adjacency
+                .neighbors(i)
+                .iter()
+                .zip(&weights[adjacency.edge_range(i)])
+                .map(|(&j, &weight)| uvs[j as usize] * weight)
+                .sum();
+            max_movement = max_movement.max(relaxed.distance(uvs[i]));
+            uvs[i] = relaxed;
+        }
+        if max_movement < options.tolerance {
+            break;
+        }
+    }
+
+    let area = local_mesh
+        .triangles()
+        .map(|triangle| {
+            let [a, b, c] = triangle
+                .to_array()
+                .map(|i| local_mesh.positions[i as usize]);
+            (b - a).cross(c - a).length() * 0.5
+        })
+        .sum();
+    Some(Chart {
+        triangles: local_triangles,
+        vertices,
+        uvs,
+        area,
+    })
+}
+
+/// The boundary vertices in order, following the orientation of the triangles.
+/// Returns `None` if the triangles are not consistently oriented.
+fn boundary_loop(triangles: &[UVec3]) -> Option<Vec<u32>> {
+    let half_edges: HashSet<(u32, u32)> = triangles
+        .iter()
+        .flat_map(|t| [(t.x, t.y), (t.y, t.z), (t.z, t.x)])
+        .collect();
+    let mut next = HashMap::new();
+    for &(a, b) in &half_edges {
+        if !half_edges.contains(&(b, a)) && next.insert(a, b).is_some() {
+            return None;
+        }
+    }
+    let start = *next.keys().min()?;
+    let mut boundary = vec![start];
+    let mut vertex = next[&start];
+    while vertex != start {
+        boundary.push(vertex);
+        vertex = *next.get(&vertex)?;
+        if boundary.len() > next.len() {
+            return None;
+        }
+    }
+    (boundary.len() == next.len()).then_some(boundary)
+}
+
+/// Spreads the boundary over the border of the unit square by arc length. The four vertices closest to the corners
+/// are snapped onto them, so the corners of the square are covered.
+fn square_border(positions: &[Vec3], boundary: &[u32]) -> Vec<Vec2> {
+    let count = boundary.len();
+    let mut lengths = vec![0.0f32; count + 1];
+    for i in 0..count {
+        let a = positions[boundary[i] as usize];
+        let b = positions[boundary[(i + 1) % count] as usize];
+        lengths[i + 1] = lengths[i] + a.distance(b);
+    }
+    let total = lengths[count].max(f32::MIN_POSITIVE);
+
+    // Boundary index of every corner, strictly increasing if there are enough vertices
+    let mut corners = [0, 0, 0, 0, count];
+    for side in 1..4 {
+        let target = total * side as f32 / 4.0;
+        let closest = (0..count)
+            .min_by(|&a, &b| {
+                (lengths[a] - target)
+                    .abs()
+                    .total_cmp(&(lengths[b] - target).abs())
+            })
+            .unwrap_or(0);
+        corners[side] = closest
+            .max(corners[side - 1] + 1)
+            .min(count.saturating_sub(4 - side));
+    }
+    let square = [Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y, Vec2::ZERO];
+
+    let mut uvs = vec![Vec2::ZERO; count];
+    for side in 0..4 {
+        let (start, end) = (corners[side], corners[side + 1]);
+        let side_length = (lengths[end] - lengths[start]).max(f32::MIN_POSITIVE);
+        for i in start..end {
+            let t = (lengths[i] - lengths[start]) / side_length;
+            uvs[i] = square[side].lerp(square[side + 1], t);
+        }
+    }
+    uvs
+}
+
+/// Gives every chart a square whose side grows with the square root of its area, as large as still fits
+fn pack_charts(areas: &[f32], size: (u32, u32)) -> Result<Vec<ChartRect>, Mesh2GimError> {
+    let sides = |scale: f32| -> Vec<u32> {
+        areas
+            .iter()
+            .map(|area| ((area.sqrt() * scale) as u32).max(MIN_CHART_SIZE))
+            .collect()
+    };
+    let too_small = Mesh2GimError::AtlasTooSmall {
+        charts: areas.len(),
+        width: size.0,
+        height: size.1,
+    };
+    let mut rects = pack_shelves(&sides(0.0), size).ok_or(too_small)?;
+    let largest = areas.iter().fold(0.0f32, |a, &b| a.max(b)).sqrt();
+    if largest <= 0.0 {
+        return Ok(rects);
+    }
+    let (mut low, mut high) = (0.0, size.0.max(size.1) as f32 / largest);
+    for _ in 0..32 {
+        let scale = (low + high) * 0.5;
+        match pack_shelves(&sides(scale), size) {
+            Some(packed) => {
+                rects = packed;
+                low = scale;
+            }
+            None => high = scale,
+        }
+    }
+    Ok(rects)
+}
+
+/// Places the squares in rows, from the largest to the smallest. Returns `None` if they do not fit.
+fn pack_shelves(sides: &[u32], size: (u32, u32)) -> Option<Vec<ChartRect>> {
+    let mut order: Vec<usize> = (0..sides.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sides[i]));
+    let mut rects = vec![
+        ChartRect {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        };
+        sides.len()
+    ];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for i in order {
+        let padded = sides[i] + 2 * GUTTER;
+        if x + padded > size.0 {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        if x + padded > size.0 || y + padded > size.1 {
+            return None;
+        }
+        rects[i] = ChartRect {
+            x: x + GUTTER,
+            y: y + GUTTER,
+            width: sides[i],
+            height: sides[i],
+        };
+        x += padded;
+        shelf_height = shelf_height.max(padded);
+    }
+    Some(rects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_meshes::{subdivided_octahedron, torus},
+        Attribute, AttributeValues,
+    };
+
+    #[test]
+    fn test_torus_atlas() {
+        let mut mesh = torus(24, 12);
+        mesh.attributes.push(Attribute {
+            name: Attribute::NORMAL.to_string(),
+            values: AttributeValues::Vec3s(mesh.positions.clone()),
+        });
+        let size = (128, 96);
+        let atlas = make_geometry_image_atlas(&mesh, size, &Default::default()).unwrap();
+        assert!(atlas.charts.len() > 1);
+        let image = &atlas.image.positions;
+        assert_eq!((image.width, image.height), size);
+
+        for (i, a) in atlas.charts.iter().enumerate() {
+            assert!(a.x >= GUTTER && a.x + a.width + GUTTER <= size.0);
+            assert!(a.y >= GUTTER && a.y + a.height + GUTTER <= size.1);
+            for b in &atlas.charts[i + 1..] {
+                let apart_x = a.x + a.width + GUTTER <= b.x - GUTTER
+                    || b.x + b.width + GUTTER <= a.x - GUTTER;
+                let apart_y = a.y + a.height + GUTTER <= b.y - GUTTER
+                    || b.y + b.height + GUTTER <= a.y - GUTTER;
+                assert!(apart_x || apart_y, "{a:?} overlaps {b:?}");
+            }
+            // Every pixel of a chart is on the torus
+            for y in a.y..a.y + a.height {
+                for x in a.x..a.x + a.width {
+                    let index = (y * size.0 + x) as usize;
+                    assert!(image.valid[index]);
+                    let position = image.pixels[index];
+                    let ring_distance = Vec2::new(position.x, position.z).length() - 2.0;
+                    let tube_distance = Vec2::new(ring_distance, position.y).length();
+                    assert!((tube_distance - 1.0).abs() < 0.1, "{position}");
+                }
+            }
+        }
+        // The attributes are aligned with the positions
+        let AttributeValues::Vec3s(normals) = &atlas.image.attributes[0].values else {
+            panic!("Attribute images changed their type");
+        };
+        assert_eq!(normals, &image.pixels);
+    }
+
+    #[test]
+    fn test_chart_parametrization() {
+        let mesh = subdivided_octahedron(3, Vec3::ONE);
+        let options = AtlasOptions {
+            max_chart_faces: 100,
+            ..Default::default()
+        };
+        let (charts, dropped) = make_charts(&mesh, &options);
+        assert_eq!(dropped, 0);
+        assert_eq!(
+            charts
+                .iter()
+                .map(|chart| chart.triangles.len())
+                .sum::<usize>(),
+            mesh.faces_count()
+        );
+        for chart in &charts {
+            assert!(chart.triangles.len() <= 100);
+            // No triangle folds over. Triangles with all corners on one side of the square collapse to a line.
+            let areas: Vec<f32> = chart
+                .triangles
+                .iter()
+                .map(|triangle| {
+                    let [a, b, c] = triangle.to_array().map(|i| chart.uvs[i as usize]);
+                    (b - a).perp_dot(c - a)
+                })
+                .collect();
+            assert!(areas.iter().all(|&area| area >= 0.0));
+            assert!(chart
+                .uvs
+                .iter()
+                .all(|uv| uv.cmpge(Vec2::ZERO).all() && uv.cmple(Vec2::ONE).all()));
+        }
+    }
+
+    #[test]
+    fn test_degenerate_triangle_is_dropped() {
+        let mut mesh = subdivided_octahedron(1, Vec3::ONE);
+        // A triangle with a repeated corner is not a disk, and can not be split
+        mesh.indices.extend([0, 0, 1]);
+        let atlas = make_geometry_image_atlas(&mesh, (64, 64), &Default::default()).unwrap();
+        assert_eq!(atlas.dropped_triangles, 1);
+        assert!(!atlas.charts.is_empty());
+    }
+
+    #[test]
+    fn test_atlas_too_small() {
+        let mesh = torus(24, 12);
+        assert!(matches!(
+            make_geometry_image_atlas(&mesh, (8, 8), &Default::default()),
+            Err(Mesh2GimError::AtlasTooSmall { .. })
+        ));
+    }
+}
@@ -0,0 +1,586 @@
+//! Converts many meshes at several sizes in one run, and skips the outputs that are up to date.
+//!
+//! The jobs either come from a directory, which converts every mesh in it with the same options, or from a JSON
+//! manifest:
+//!
+//! ```json
+//! {
+//!     "output_dir": "gims",
+//!     "sizes": ["255", "513x257"],
+//!     "format": "exr",
+//!     "options": ["--iterations", "1000"],
+//!     "jobs": [
+//!         { "input": "meshes/bunny.obj" },
+//!         { "input": "meshes/dragon.ply", "sizes": ["1025"], "options": ["--layout", "cube_map"] }
+//!     ]
+//! }
+//! ```
+//!
+//! Every field but `jobs` and `input` is optional. A job falls back to the values of the manifest, and the manifest to
+//! the command line. The options of the command line, the manifest and the job are all passed to the conversion, in
+//! that order. Relative paths in the manifest are relative to the manifest.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use clap::{Args, Parser};
+use mesh2gim::loaders::{read_mesh_files, LoadError, MeshFormat};
+use miniserde::{json, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{ConvertArgs, GimSize};
+
+/// Remembers which job wrote each output, in the output directory
+const STATE_FILE: &str = "mesh2gim-batch.json";
+
+#[derive(Args)]
+pub struct BatchArgs {
+    /// A directory of meshes to convert, or a JSON manifest that lists the jobs
+    source: PathBuf,
+
+    /// Where the geometry images are written, as <mesh name>_<width>x<height>.<format>
+    #[clap(short, long, default_value = ".")]
+    output_dir: PathBuf,
+
+    /// Size of the geometry images, repeat it to convert every mesh at several sizes
+    #[clap(short, long = "size", default_value = "255")]
+    sizes: Vec<GimSize>,
+
+    /// The file format of the geometry images: png, exr or f32
+    #[clap(short, long, default_value = "png")]
+    format: String,
+
+    /// Convert every job, even if its output is up to date
+    #[clap(long)]
+    force: bool,
+
+    /// Options for every conversion, after a --. For example: -- --layout cube_map --bit-depth 8
+    #[clap(last = true)]
+    options: Vec<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum BatchError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("invalid manifest {path}: {message}")]
+    Manifest { path: String, message: String },
+    #[error(
+        "{first} and {second} would both be written to {output}, rename one of them or give it its own output_dir"
+    )]
+    DuplicateOutput {
+        output: String,
+        first: String,
+        second: String,
+    },
+    #[error("{failed} of {total} jobs failed")]
+    Failed { failed: usize, total: usize },
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    output_dir: Option<String>,
+    sizes: Option<Vec<String>>,
+    format: Option<String>,
+    options: Option<Vec<String>>,
+    jobs: Vec<ManifestJob>,
+}
+
+#[derive(Deserialize)]
+struct ManifestJob {
+    input: String,
+    output_dir: Option<String>,
+    sizes: Option<Vec<String>>,
+    format: Option<String>,
+    options: Option<Vec<String>>,
+}
+
+/// The hash of the job that last wrote each output
+#[derive(Serialize, Deserialize)]
+struct BatchState {
+    outputs: BTreeMap<String, String>,
+}
+
+/// One mesh converted at one size
+struct Job {
+    input: PathBuf,
+    size: GimSize,
+    output: PathBuf,
+    options: Vec<String>,
+}
+
+enum Outcome {
+    Converted(Duration),
+    UpToDate,
+    Failed(String),
+}
+
+/// Runs every job, and prints a table of the results
+pub fn run_batch(args: &BatchArgs) -> Result<(), BatchError> {
+    let (output_dir, jobs) = if args.source.is_dir() {
+        (args.output_dir.clone(), directory_jobs(args)?)
+    } else {
+        manifest_jobs(args)?
+    };
+    check_outputs(&jobs)?;
+    fs::create_dir_all(&output_dir)?;
+    let state_path = output_dir.join(STATE_FILE);
+    let mut state = read_state(&state_path);
+
+    let mut outcomes = Vec::with_capacity(jobs.len());
+    for (i, job) in jobs.iter().enumerate() {
+        println!(
+            "[{}/{}] {} at {}",
+            i + 1,
+            jobs.len(),
+            job.input.display(),
+            job.size
+        );
+        let key = job.output.to_string_lossy().into_owned();
+        let outcome = match fingerprint(job) {
+            Ok(hash) if !args.force && is_up_to_date(job, state.outputs.get(&key), &hash) => {
+                Outcome::UpToDate
+            }
+            Ok(hash) => {
+                let start = Instant::now();
+                match convert(job) {
+                    Ok(()) => {
+                        state.outputs.insert(key, hash);
+                        Outcome::Converted(start.elapsed())
+                    }
+                    Err(error) => {
+                        state.outputs.remove(&key);
+                        Outcome::Failed(error)
+                    }
+                }
+            }
+            Err(error) => Outcome::Failed(error.to_string()),
+        };
+        if let Outcome::Failed(error) = &outcome {
+            println!("Error: {error}");
+        }
+        // Save after every job, so an interrupted batch does not start over
+        fs::write(&state_path, json::to_string(&state))?;
+        outcomes.push(outcome);
+    }
+
+    print_summary(&jobs, &outcomes);
+    let failed = outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome, Outcome::Failed(_)))
+        .count();
+    if failed > 0 {
+        return Err(BatchError::Failed {
+            failed,
+            total: jobs.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Every mesh file in the directory, sorted by name, at every size
+fn directory_jobs(args: &BatchArgs) -> Result<Vec<Job>, BatchError> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(&args.source)? {
+        let path = entry?.path();
+        if path.is_file() && MeshFormat::from_extension(&path).is_some() {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+    Ok(inputs
+        .iter()
+        .flat_map(|input| {
+            args.sizes.iter().map(|&size| {
+                Job::new(
+                    input,
+                    size,
+                    &args.output_dir,
+                    &args.format,
+                    args.options.clone(),
+                )
+            })
+        })
+        .collect())
+}
+
+/// Outputs are named after the file stem of the mesh, so `bunny.obj` and `bunny.ply` would overwrite each other,
+/// and convert again in every batch
+fn check_outputs(jobs: &[Job]) -> Result<(), BatchError> {
+    let mut inputs: BTreeMap<&Path, &Path> = BTreeMap::new();
+    for job in jobs {
+        if let Some(first) = inputs.insert(&job.output, &job.input) {
+            if first != job.input {
+                return Err(BatchError::DuplicateOutput {
+                    output: job.output.display().to_string(),
+                    first: first.display().to_string(),
+                    second: job.input.display().to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The jobs of a manifest, and the directory of the batch state
+fn manifest_jobs(args: &BatchArgs) -> Result<(PathBuf, Vec<Job>), BatchError> {
+    let path = &args.source;
+    let invalid = |message: String| BatchError::Manifest {
+        path: path.display().to_string(),
+        message,
+    };
+    let manifest: Manifest = json::from_str(&fs::read_to_string(path)?)
+        .map_err(|_| invalid("not a valid manifest".to_string()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let parse_sizes = |sizes: &Option<Vec<String>>| -> Result<Option<Vec<GimSize>>, BatchError> {
+        sizes
+            .as_ref()
+            .map(|sizes| {
+                sizes
+                    .iter()
+                    .map(|size| GimSize::from_str(size).map_err(invalid))
+                    .collect()
+            })
+            .transpose()
+    };
+
+    let output_dir = manifest
+        .output_dir
+        .as_ref()
+        .map_or(args.output_dir.clone(), |dir| base.join(dir));
+    let sizes = parse_sizes(&manifest.sizes)?.unwrap_or(args.sizes.clone());
+    let format = manifest.format.as_ref().unwrap_or(&args.format);
+    let options: Vec<String> = args
+        .options
+        .iter()
+        .chain(manifest.options.iter().flatten())
+        .cloned()
+        .collect();
+
+    let mut jobs = Vec::new();
+    for job in &manifest.jobs {
+        let input = base.join(&job.input);
+        let job_output_dir = job
+            .output_dir
+            .as_ref()
+            .map_or(output_dir.clone(), |dir| base.join(dir));
+        let job_options: Vec<String> = options
+            .iter()
+            .chain(job.options.iter().flatten())
+            .cloned()
+            .collect();
+        for size in parse_sizes(&job.sizes)?.unwrap_or(sizes.clone()) {
+            jobs.push(Job::new(
+                &input,
+                size,
+                &job_output_dir,
+                job.format.as_ref().unwrap_or(format),
+                job_options.clone(),
+            ));
+        }
+    }
+    Ok((output_dir, jobs))
+}
+
+impl Job {
+    fn new(
+        input: &Path,
+        size: GimSize,
+        output_dir: &Path,
+        format: &str,
+        options: Vec<String>,
+    ) -> Self {
+        let name = input.file_stem().unwrap_or_default().to_string_lossy();
+        Job {
+            input: input.to_path_buf(),
+            size,
+            output: output_dir.join(format!("{name}_{size}.{format}")),
+            options,
+        }
+    }
+
+    /// The command line of the conversion, without the program name
+    fn arguments(&self) -> Vec<String> {
+        let mut arguments = vec![
+            self.input.to_string_lossy().into_owned(),
+            "--size".to_string(),
+            self.size.to_string(),
+            "--output".to_string(),
+            self.output.to_string_lossy().into_owned(),
+        ];
+        arguments.extend(self.options.iter().cloned());
+        arguments
+    }
+}
+
+fn convert(job: &Job) -> Result<(), String> {
+    let args =
+        ConvertArgs::try_parse_from(std::iter::once("mesh2gim".to_string()).chain(job.arguments()))
+            .map_err(|error| error.to_string().trim().to_string())?;
+    if let Some(parent) = job.output.parent() {
+        fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+    }
+    crate::run(&args).map_err(|error| error.to_string())
+}
+
+/// A hash of the input file with the buffers it loads, the options and the version of mesh2gim. Any change to them
+/// changes the output.
+fn fingerprint(job: &Job) -> Result<String, LoadError> {
+    let mut hash = fnv1a(FNV_OFFSET, env!("CARGO_PKG_VERSION").as_bytes());
+    for argument in job.arguments() {
+        hash = fnv1a(hash, argument.as_bytes());
+        hash = fnv1a(hash, &[0]);
+    }
+    for file in read_mesh_files(&job.input)? {
+        hash = fnv1a(hash, &(file.len() as u64).to_le_bytes());
+        hash = fnv1a(hash, &file);
+    }
+    Ok(format!("{hash:016x}"))
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// The 64-bit FNV-1a hash, which stays the same across platforms and Rust versions
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Whether the output was written by the same job, and has not been deleted since
+fn is_up_to_date(job: &Job, previous: Option<&String>, hash: &str) -> bool {
+    previous.is_some_and(|previous| previous == hash)
+        && job.output.is_file()
+        && job.output.with_extension("json").is_file()
+}
+
+/// A missing or unreadable state converts everything again
+fn read_state(path: &Path) -> BatchState {
+    match fs::read_to_string(path) {
+        Ok(contents) => json::from_str(&contents).unwrap_or_else(|_| {
+            println!(
+                "Warning: {} is not a valid batch state, converting everything",
+                path.display()
+            );
+            BatchState {
+                outputs: BTreeMap::new(),
+            }
+        }),
+        Err(_) => BatchState {
+            outputs: BTreeMap::new(),
+        },
+    }
+}
+
+fn print_summary(jobs: &[Job], outcomes: &[Outcome]) {
+    let rows: Vec<[String; 4]> = jobs
+        .iter()
+        .zip(outcomes)
+        .map(|(job, outcome)| {
+            let (result, details) = match outcome {
+                Outcome::Converted(time) => ("converted", format!("{:.1}s", time.as_secs_f32())),
+                Outcome::UpToDate => ("up to date", String::new()),
+                Outcome::Failed(error) => {
+                    ("failed", error.lines().next().unwrap_or("").to_string())
+                }
+            };
+            [
+                job.input.display().to_string(),
+                job.size.to_string(),
+                result.to_string(),
+                details,
+            ]
+        })
+        .collect();
+    let header = ["Input", "Size", "Result", ""].map(String::from);
+    let mut widths = [0; 3];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    println!();
+    for [input, size, result, details] in std::iter::once(&header).chain(&rows) {
+        let line = format!(
+            "{input:<0$}  {size:<1$}  {result:<2$}  {details}",
+            widths[0], widths[1], widths[2]
+        );
+        println!("{}", line.trim_end());
+    }
+
+    let count = |f: fn(&Outcome) -> bool| outcomes.iter().filter(|outcome| f(outcome)).count();
+    println!(
+        "{} jobs: {} converted, {} up to date, {} failed",
+        jobs.len(),
+        count(|outcome| matches!(outcome, Outcome::Converted(_))),
+        count(|outcome| matches!(outcome, Outcome::UpToDate)),
+        count(|outcome| matches!(outcome, Outcome::Failed(_)))
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OCTAHEDRON: &str = "v 1 0 0\nv -1 0 0\nv 0 1 0\nv 0 -1 0\nv 0 0 1\nv 0 0 -1\n\
+        f 1 3 5\nf 3 2 5\nf 2 4 5\nf 4 1 5\nf 3 1 6\nf 2 3 6\nf 4 2 6\nf 1 4 6\n";
+
+    fn batch_args(source: PathBuf, output_dir: PathBuf) -> BatchArgs {
+        BatchArgs {
+            source,
+            output_dir,
+            sizes: vec![GimSize::from_str("17").unwrap()],
+            format: "png".to_string(),
+            force: false,
+            options: vec!["--iterations".to_string(), "20".to_string()],
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mesh2gim-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_manifest_jobs() {
+        let dir = temp_dir("manifest");
+        let manifest = r#"{
+            "output_dir": "gims",
+            "sizes": ["33", "64x32"],
+            "options": ["--layout", "cube_map"],
+            "jobs": [
+                { "input": "meshes/bunny.obj" },
+                { "input": "dragon.ply", "sizes": ["9"], "format": "exr", "options": ["-b", "32"] }
+            ]
+        }"#;
+        fs::write(dir.join("jobs.json"), manifest).unwrap();
+        let args = batch_args(dir.join("jobs.json"), PathBuf::from("unused"));
+        let (output_dir, jobs) = manifest_jobs(&args).unwrap();
+        assert_eq!(output_dir, dir.join("gims"));
+        let outputs: Vec<_> = jobs.iter().map(|job| job.output.clone()).collect();
+        assert_eq!(
+            outputs,
+            [
+                dir.join("gims/bunny_33x33.png"),
+                dir.join("gims/bunny_64x32.png"),
+                dir.join("gims/dragon_9x9.exr"),
+            ]
+        );
+        assert_eq!(jobs[0].input, dir.join("meshes/bunny.obj"));
+        assert_eq!(
+            jobs[2].options,
+            ["--iterations", "20", "--layout", "cube_map", "-b", "32"]
+        );
+
+        fs::write(dir.join("bad.json"), r#"{ "sizes": ["33"] }"#).unwrap();
+        fs::write(
+            dir.join("bad_size.json"),
+            r#"{ "sizes": ["big"], "jobs": [] }"#,
+        )
+        .unwrap();
+        for bad in ["bad.json", "bad_size.json"] {
+            let args = batch_args(dir.join(bad), dir.clone());
+            assert!(matches!(
+                manifest_jobs(&args),
+                Err(BatchError::Manifest { .. })
+            ));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_skips_up_to_date_outputs() {
+        let dir = temp_dir("skip");
+        fs::create_dir(dir.join("meshes")).unwrap();
+        fs::write(dir.join("meshes/octahedron.obj"), OCTAHEDRON).unwrap();
+        fs::write(dir.join("meshes/broken.obj"), "f 1 2 3\n").unwrap();
+        fs::write(dir.join("meshes/notes.txt"), "not a mesh").unwrap();
+        let mut args = batch_args(dir.join("meshes"), dir.join("gims"));
+
+        let jobs = directory_jobs(&args).unwrap();
+        assert_eq!(jobs.len(), 2);
+        let output = dir.join("gims/octahedron_17x17.png");
+        let modified = || fs::metadata(&output).ok()?.modified().ok();
+        // The broken mesh fails every time, and the octahedron is only converted when something changed
+        let converts = |args: &BatchArgs| {
+            let before = modified();
+            assert!(matches!(
+                run_batch(args),
+                Err(BatchError::Failed {
+                    failed: 1,
+                    total: 2
+                })
+            ));
+            assert!(dir.join("gims/octahedron_17x17.json").is_file());
+            modified() != before
+        };
+        assert!(converts(&args));
+        assert!(!converts(&args));
+        let state: BatchState =
+            json::from_str(&fs::read_to_string(dir.join("gims").join(STATE_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(state.outputs.len(), 1);
+
+        args.options = vec!["--iterations".to_string(), "21".to_string()];
+        assert!(converts(&args));
+        assert!(!converts(&args));
+        fs::write(
+            dir.join("meshes/octahedron.obj"),
+            OCTAHEDRON.replace("v 1 0 0", "v 2 0 0"),
+        )
+        .unwrap();
+        assert!(converts(&args));
+        fs::remove_file(dir.join("gims/octahedron_17x17.json")).unwrap();
+        assert!(converts(&args));
+        args.force = true;
+        assert!(converts(&args));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_fingerprint_includes_gltf_buffers() {
+        let dir = temp_dir("buffers");
+        let gltf = r#"{ "asset": { "version": "2.0" }, "buffers": [{ "byteLength": 4, "uri": "mesh.bin" }] }"#;
+        fs::write(dir.join("mesh.gltf"), gltf).unwrap();
+        fs::write(dir.join("mesh.bin"), [0, 0, 0, 0]).unwrap();
+        let job = Job {
+            input: dir.join("mesh.gltf"),
+            size: GimSize::from_str("17").unwrap(),
+            output: dir.join("mesh_17x17.png"),
+            options: vec![],
+        };
+        let before = fingerprint(&job).unwrap();
+        assert_eq!(fingerprint(&job).unwrap(), before);
+        fs::write(dir.join("mesh.bin"), [1, 0, 0, 0]).unwrap();
+        assert_ne!(fingerprint(&job).unwrap(), before);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_outputs() {
+        let dir = temp_dir("duplicates");
+        fs::create_dir(dir.join("meshes")).unwrap();
+        fs::write(dir.join("meshes/bunny.obj"), OCTAHEDRON).unwrap();
+        fs::write(
+            dir.join("meshes/bunny.stl"),
+            "solid bunny\nendsolid bunny\n",
+        )
+        .unwrap();
+        let args = batch_args(dir.join("meshes"), dir.join("gims"));
+        assert!(matches!(
+            run_batch(&args),
+            Err(BatchError::DuplicateOutput { .. })
+        ));
+        // Nothing was converted
+        assert!(!dir.join("gims").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
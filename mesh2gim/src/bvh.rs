@@ -0,0 +1,270 @@
+use glam::Vec3;
+
+/// Bounding volume hierarchy over a set of triangles, for fast ray casting
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// Triangle indices, ordered so that every leaf references a contiguous range
+    triangles: Vec<u32>,
+}
+
+struct BvhNode {
+    min: Vec3,
+    max: Vec3,
+    /// For leaves, the first triangle. For inner nodes, the index of the second child.
+    /// The first child always directly follows its parent.
+    offset: u32,
+    /// Number of triangles in a leaf, zero for inner nodes
+    count: u32,
+}
+
+const MAX_LEAF_SIZE: usize = 4;
+
+impl Bvh {
+    pub fn new(triangles: &[[Vec3; 3]]) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(triangles.len() * 2),
+            triangles: (0..triangles.len() as u32).collect(),
+        };
+        if triangles.is_empty() {
+            return bvh;
+        }
+        let centroids: Vec<Vec3> = triangles
+            .iter()
+            .map(|[a, b, c]| (*a + *b + *c) / 3.0)
+            .collect();
+        bvh.build(triangles, &centroids, 0, triangles.len());
+        bvh
+    }
+
+    fn build(&mut self, triangles: &[[Vec3; 3]], centroids: &[Vec3], start: usize, end: usize) {
+        let node_index = self.nodes.len();
+        let mut min = Vec3::splat(f32::INFINITY);
+        let mut max = Vec3::splat(f32::NEG_INFINITY);
+        let mut centroid_min = Vec3::splat(f32::INFINITY);
+        let mut centroid_max = Vec3::splat(f32::NEG_INFINITY);
+        for &triangle in &self.triangles[start..end] {
+            for vertex in triangles[triangle as usize] {
+                min = min.min(vertex);
+                max = max.max(vertex);
+            }
+            centroid_min = centroid_min.min(centroids[triangle as usize]);
+            centroid_max = centroid_max.max(centroids[triangle as usize]);
+        }
+        self.nodes.push(BvhNode {
+            min,
+            max,
+            offset: start as u32,
+            count: (end - start) as u32,
+        });
+        if end - start <= MAX_LEAF_SIZE {
+            return;
+        }
+
+        // Split at the median along the axis where the centroids are spread out the most
+        let extent = centroid_max - centroid_min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let middle = (start + end) / 2;
+        self.triangles[start..end].select_nth_unstable_by(middle - start, |a, b| {
+            centroids[*a as usize][axis].total_cmp(&centroids[*b as usize][axis])
+        });
+
+        self.build(triangles, centroids, start, middle);
+        let second_child = self.nodes.len() as u32;
+        self.build(triangles, centroids, middle, end);
+        let node = &mut self.nodes[node_index];
+        node.offset = second_child;
+        node.count = 0;
+    }
+
+    /// Finds the closest triangle along the ray. `intersect` tests a single triangle and returns the distance along the ray.
+    pub fn closest_hit(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        mut intersect: impl FnMut(u32) -> Option<f32>,
+    ) -> Option<(u32, f32)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let inverse_direction = direction.recip();
+        let mut closest: Option<(u32, f32)> = None;
+        let mut stack = vec![0u32];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            let Some(entry) = intersect_aabb(origin, inverse_direction, node.min, node.max) else {
+                continue;
+            };
+            if closest.is_some_and(|(_, t)| t < entry) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.offset);
+                stack.push(node_index + 1);
+                continue;
+            }
+            let start = node.offset as usize;
+            for &triangle in &self.triangles[start..start + node.count as usize] {
+                if let Some(t) = intersect(triangle) {
+                    if closest.is_none_or(|(_, closest_t)| t < closest_t) {
+                        closest = Some((triangle, t));
+                    }
+                }
+            }
+        }
+        closest
+    }
+
+    /// Finds the triangle closest to the point. `distance_squared` measures the squared distance to a single triangle.
+    pub fn nearest(
+        &self,
+        point: Vec3,
+        mut distance_squared: impl FnMut(u32) -> f32,
+    ) -> Option<(u32, f32)> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let node_distance_squared =
+            |node: &BvhNode| point.clamp(node.min, node.max).distance_squared(point);
+        let mut nearest: Option<(u32, f32)> = None;
+        let mut stack = vec![0u32];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if nearest.is_some_and(|(_, d)| d < node_distance_squared(node)) {
+                continue;
+            }
+            if node.count == 0 {
+                // Visit the closer child first
+                let first = node_index + 1;
+                let second = node.offset;
+                if node_distance_squared(&self.nodes[first as usize])
+                    <= node_distance_squared(&self.nodes[second as usize])
+                {
+                    stack.push(second);
+                    stack.push(first);
+                } else {
+                    stack.push(first);
+                    stack.push(second);
+                }
+                continue;
+            }
+            let start = node.offset as usize;
+            for &triangle in &self.triangles[start..start + node.count as usize] {
+                let d = distance_squared(triangle);
+                if nearest.is_none_or(|(_, nearest_d)| d < nearest_d) {
+                    nearest = Some((triangle, d));
+                }
+            }
+        }
+        nearest
+    }
+}
+
+/// Slab test, returns the distance at which the ray enters the box
+fn intersect_aabb(origin: Vec3, inverse_direction: Vec3, min: Vec3, max: Vec3) -> Option<f32> {
+    let t1 = (min - origin) * inverse_direction;
+    let t2 = (max - origin) * inverse_direction;
+    let t_entry = t1.min(t2).max_element().max(0.0);
+    let t_exit = t1.max(t2).min_element();
+    if t_entry <= t_exit {
+        Some(t_entry)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::subdivided_octahedron;
+
+    #[test]
+    fn test_closest_hit_matches_brute_force() {
+        let mesh = subdivided_octahedron(3, Vec3::new(1.0, 2.0, 0.5));
+        let triangles: Vec<[Vec3; 3]> = mesh
+            .triangles()
+            .map(|t| {
+                [
+                    mesh.positions[t.x as usize],
+                    mesh.positions[t.y as usize],
+                    mesh.positions[t.z as usize],
+                ]
+            })
+            .collect();
+        let bvh = Bvh::new(&triangles);
+
+        // Rays through points scattered on a Fibonacci sphere
+        let intersect = |direction: Vec3, triangle: u32| {
+            let [a, b, c] = triangles[triangle as usize];
+            let normal = (b - a).cross(c - a);
+            let t = normal.dot(a) / normal.dot(direction);
+            let point = direction * t;
+            let inside = (b - a).cross(point - a).dot(normal) >= 0.0
+                && (c - b).cross(point - b).dot(normal) >= 0.0
+                && (a - c).cross(point - c).dot(normal) >= 0.0;
+            (t > 0.0 && inside).then_some(t)
+        };
+        let count = 500;
+        for i in 0..count {
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+            let phi = i as f32 * 2.399_963;
+            let radius = (1.0 - y * y).sqrt();
+            let direction = Vec3::new(radius * phi.cos(), y, radius * phi.sin());
+
+            let expected = (0..triangles.len() as u32)
+                .filter_map(|triangle| intersect(direction, triangle).map(|t| (triangle, t)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let actual = bvh.closest_hit(Vec3::ZERO, direction, |triangle| {
+                intersect(direction, triangle)
+            });
+            assert_eq!(actual.map(|v| v.1), expected.map(|v| v.1));
+        }
+    }
+
+    #[test]
+    fn test_nearest_matches_brute_force() {
+        let mesh = subdivided_octahedron(2, Vec3::ONE);
+        let triangles: Vec<[Vec3; 3]> = mesh
+            .triangles()
+            .map(|t| {
+                [
+                    mesh.positions[t.x as usize],
+                    mesh.positions[t.y as usize],
+                    mesh.positions[t.z as usize],
+                ]
+            })
+            .collect();
+        let bvh = Bvh::new(&triangles);
+        // Distance to the centroid is good enough for checking the traversal
+        let distance_squared = |point: Vec3, triangle: u32| {
+            let [a, b, c] = triangles[triangle as usize];
+            ((a + b + c) / 3.0).distance_squared(point)
+        };
+        for i in 0..100 {
+            let point = Vec3::new(
+                (i as f32 * 0.37).sin(),
+                (i as f32 * 0.71).cos(),
+                (i as f32 * 0.13).sin(),
+            ) * 2.0;
+            let expected = (0..triangles.len() as u32)
+                .map(|triangle| distance_squared(point, triangle))
+                .min_by(|a, b| a.total_cmp(b));
+            let actual = bvh.nearest(point, |triangle| distance_squared(point, triangle));
+            assert_eq!(actual.map(|v| v.1), expected);
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let bvh = Bvh::new(&[]);
+        assert!(bvh
+            .closest_hit(Vec3::ZERO, Vec3::X, |_| Some(1.0))
+            .is_none());
+        assert!(bvh.nearest(Vec3::ZERO, |_| 1.0).is_none());
+    }
+}
@@ -0,0 +1,466 @@
+//! Quadric error metric simplification, after Garland and Heckbert.
+//!
+//! Scanned meshes can have millions of triangles, far more than a geometry image has pixels, and the parametrization
+//! takes time for every vertex. Decimating the mesh first makes the parametrization cheap. The pixels are then moved
+//! to the closest point of the full mesh, so the image keeps the detail of the scan.
+//!
+//! Every vertex keeps a quadric that sums the squared distances to the planes of the triangles it replaced. Edges are
+//! collapsed in order of the error of their merged vertex, as long as the surface keeps its shape: collapses that
+//! would pinch the surface, close a hole or turn a triangle over are skipped.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use glam::{DMat3, DVec3};
+
+use crate::Mesh;
+
+/// The fewest triangles a closed mesh can have
+const MIN_TRIANGLES: usize = 4;
+/// How much more the distance to the planes along the boundary counts than the distance to the triangles
+const BOUNDARY_WEIGHT: f64 = 100.0;
+/// The optimal position of a merged vertex is ignored if it is further than this many edge lengths from the middle
+/// of the edge, which happens when the quadric is nearly flat
+const MAX_OPTIMAL_DISTANCE: f64 = 2.0;
+/// Quadrics whose determinant is below this, relative to the cube of their Frobenius norm, are too close to
+/// singular to solve. This bounds the condition number and does not change with the scale of the mesh.
+const MIN_RELATIVE_DETERMINANT: f64 = 1e-9;
+
+/// Collapses edges until the mesh has at most `target_triangles` triangles, or no edge can be collapsed without
+/// changing the shape of the surface. The result has no attributes: it is only meant for the parametrization.
+pub fn decimate_mesh(mesh: &Mesh, target_triangles: usize) -> Mesh {
+    if mesh.faces_count() <= target_triangles {
+        return Mesh {
+            positions: mesh.positions.clone(),
+            attributes: vec![],
+            indices: mesh.indices.clone(),
+        };
+    }
+    let mut decimation = Decimation::new(mesh);
+    decimation.run(target_triangles.max(MIN_TRIANGLES));
+    decimation.into_mesh()
+}
+
+/// The error of a position, as `xᵀ A x + 2 bᵀ x + c`
+#[derive(Clone, Copy)]
+struct Quadric {
+    a: DMat3,
+    b: DVec3,
+    c: f64,
+}
+
+impl Quadric {
+    const ZERO: Quadric = Quadric {
+        a: DMat3::ZERO,
+        b: DVec3::ZERO,
+        c: 0.0,
+    };
+
+    /// The squared distance to the plane through `point` with the unit `normal`, times `weight`
+    fn plane(normal: DVec3, point: DVec3, weight: f64) -> Self {
+        let d = -normal.dot(point);
+        Quadric {
+            a: DMat3::from_cols(normal * normal.x, normal * normal.y, normal * normal.z) * weight,
+            b: normal * d * weight,
+            c: d * d * weight,
+        }
+    }
+
+    fn error(&self, x: DVec3) -> f64 {
+        x.dot(self.a * x) + 2.0 * self.b.dot(x) + self.c
+    }
+
+    /// The position with the least error, if there is a single one
+    fn minimum(&self) -> Option<DVec3> {
+        let norm = (self.a.x_axis.length_squared()
+            + self.a.y_axis.length_squared()
+            + self.a.z_axis.length_squared())
+        .sqrt();
+        if self.a.determinant().abs() <= MIN_RELATIVE_DETERMINANT * norm.powi(3) {
+            return None;
+        }
+        let x = self.a.inverse() * -self.b;
+        x.is_finite().then_some(x)
+    }
+}
+
+impl std::ops::Add for Quadric {
+    type Output = Quadric;
+
+    fn add(self, other: Quadric) -> Quadric {
+        Quadric {
+            a: self.a + other.a,
+            b: self.b + other.b,
+            c: self.c + other.c,
+        }
+    }
+}
+
+/// A candidate edge collapse in the queue. Entries go stale when either vertex changes, and are then skipped.
+struct Collapse {
+    cost: f64,
+    kept: u32,
+    removed: u32,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    /// The cheapest collapse is the greatest, so it comes first out of the max-heap
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Decimation {
+    positions: Vec<DVec3>,
+    quadrics: Vec<Quadric>,
+    /// Bumped every time a vertex moves, so the stale collapses in the queue can be told apart
+    versions: Vec<u32>,
+    removed_vertices: Vec<bool>,
+    boundary_vertices: Vec<bool>,
+    triangles: Vec<[u32; 3]>,
+    removed_triangles: Vec<bool>,
+    vertex_triangles: Vec<Vec<u32>>,
+    triangle_count: usize,
+    queue: BinaryHeap<Collapse>,
+}
+
+impl Decimation {
+    fn new(mesh: &Mesh) -> Self {
+        let positions: Vec<DVec3> = mesh.positions.iter().map(|p| p.as_dvec3()).collect();
+        let triangles: Vec<[u32; 3]> = mesh.triangles().map(|t| t.to_array()).collect();
+        let mut vertex_triangles = vec![Vec::new(); positions.len()];
+        for (t, triangle) in triangles.iter().enumerate() {
+            for &vertex in triangle {
+                vertex_triangles[vertex as usize].push(t as u32);
+            }
+        }
+
+        let mut quadrics = vec![Quadric::ZERO; positions.len()];
+        for triangle in &triangles {
+            let [a, b, c] = triangle.map(|i| positions[i as usize]);
+            let normal = (b - a).cross(c - a);
+            let double_area = normal.length();
+            if double_area == 0.0 {
+                continue;
+            }
+            let quadric = Quadric::plane(normal / double_area, a, double_area * 0.5);
+            for &vertex in triangle {
+                quadrics[vertex as usize] = quadrics[vertex as usize] + quadric;
+            }
+        }
+
+        let mut decimation = Decimation {
+            versions: vec![0; positions.len()],
+            removed_vertices: vec![false; positions.len()],
+            boundary_vertices: vec![false; positions.len()],
+            removed_triangles: vec![false; triangles.len()],
+            triangle_count: triangles.len(),
+            positions,
+            quadrics,
+            triangles,
+            vertex_triangles,
+            queue: BinaryHeap::new(),
+        };
+        decimation.add_boundary_quadrics();
+        for vertex in 0..decimation.positions.len() as u32 {
+            for neighbor in decimation.neighbors(vertex) {
+                if vertex < neighbor {
+                    decimation.push_collapse(vertex, neighbor);
+                }
+            }
+        }
+        decimation
+    }
+
+    /// Holds the boundary in place with planes through every boundary edge, at right angles to its triangle
+    fn add_boundary_quadrics(&mut self) {
+        for t in 0..self.triangles.len() {
+            let triangle = self.triangles[t];
+            let [a, b, c] = triangle.map(|i| self.positions[i as usize]);
+            let normal = (b - a).cross(c - a);
+            for (from, to) in [(0, 1), (1, 2), (2, 0)] {
+                let (u, v) = (triangle[from], triangle[to]);
+                if self.shared_triangles(u, v) != 1 {
+                    continue;
+                }
+                let (p, q) = (self.positions[u as usize], self.positions[v as usize]);
+                let Some(side) = (q - p).cross(normal).try_normalize() else {
+                    continue;
+                };
+                let quadric = Quadric::plane(side, p, BOUNDARY_WEIGHT * p.distance_squared(q));
+                for vertex in [u, v] {
+                    self.quadrics[vertex as usize] = self.quadrics[vertex as usize] + quadric;
+                    self.boundary_vertices[vertex as usize] = true;
+                }
+            }
+        }
+    }
+
+    fn run(&mut self, target_triangles: usize) {
+        while self.triangle_count > target_triangles {
+            let Some(collapse) = self.queue.pop() else {
+                break;
+            };
+            let (kept, removed) = (collapse.kept as usize, collapse.removed as usize);
+            if self.removed_vertices[kept]
+                || self.removed_vertices[removed]
+                || (self.versions[kept], self.versions[removed]) != collapse.versions
+            {
+                continue;
+            }
+            let target = self.collapse_position(collapse.kept, collapse.removed).0;
+            if self.can_collapse(collapse.kept, collapse.removed, target) {
+                self.collapse(collapse.kept, collapse.removed, target);
+            }
+        }
+    }
+
+    /// Where the merged vertex goes, and its error there
+    fn collapse_position(&self, u: u32, v: u32) -> (DVec3, f64) {
+        let quadric = self.quadrics[u as usize] + self.quadrics[v as usize];
+        let (p, q) = (self.positions[u as usize], self.positions[v as usize]);
+        let middle = (p + q) * 0.5;
+        let optimal = quadric
+            .minimum()
+            .filter(|x| x.distance(middle) <= MAX_OPTIMAL_DISTANCE * p.distance(q));
+        optimal
+            .into_iter()
+            .chain([p, q, middle])
+            .map(|x| (x, quadric.error(x)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap()
+    }
+
+    fn push_collapse(&mut self, u: u32, v: u32) {
+        let cost = self.collapse_position(u, v).1;
+        self.queue.push(Collapse {
+            cost,
+            kept: u,
+            removed: v,
+            versions: (self.versions[u as usize], self.versions[v as usize]),
+        });
+    }
+
+    /// The vertices that share a triangle with the vertex, sorted
+    fn neighbors(&self, vertex: u32) -> Vec<u32> {
+        let mut neighbors: Vec<u32> = self.vertex_triangles[vertex as usize]
+            .iter()
+            .flat_map(|&t| self.triangles[t as usize])
+            .filter(|&other| other != vertex)
+            .collect();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+
+    /// How many triangles have the edge `u-v`
+    fn shared_triangles(&self, u: u32, v: u32) -> usize {
+        self.vertex_triangles[u as usize]
+            .iter()
+            .filter(|&&t| self.triangles[t as usize].contains(&v))
+            .count()
+    }
+
+    /// Whether merging `v` into `u` at `target` keeps the surface a manifold with the same holes, and turns no
+    /// triangle over
+    fn can_collapse(&self, u: u32, v: u32, target: DVec3) -> bool {
+        // The link condition: the only vertices next to both ends are the tips of the triangles on the edge
+        let shared = self.shared_triangles(u, v);
+        let u_neighbors = self.neighbors(u);
+        let common = self
+            .neighbors(v)
+            .into_iter()
+            .filter(|vertex| u_neighbors.binary_search(vertex).is_ok())
+            .count();
+        if common != shared || shared == 0 || shared > 2 {
+            return false;
+        }
+        // An edge across the mesh between two boundaries would pinch the surface together
+        if shared == 2 && self.boundary_vertices[u as usize] && self.boundary_vertices[v as usize] {
+            return false;
+        }
+
+        [u, v].into_iter().all(|moved| {
+            self.vertex_triangles[moved as usize].iter().all(|&t| {
+                let triangle = self.triangles[t as usize];
+                if triangle.contains(&u) && triangle.contains(&v) {
+                    return true;
+                }
+                let before = triangle.map(|i| self.positions[i as usize]);
+                let after = triangle.map(|i| {
+                    if i == moved {
+                        target
+                    } else {
+                        self.positions[i as usize]
+                    }
+                });
+                let normal_before = (before[1] - before[0]).cross(before[2] - before[0]);
+                let normal_after = (after[1] - after[0]).cross(after[2] - after[0]);
+                normal_after.dot(normal_before) > 0.0
+            })
+        })
+    }
+
+    /// Merges `v` into `u`, and moves `u` to `target`
+    fn collapse(&mut self, u: u32, v: u32, target: DVec3) {
+        for t in std::mem::take(&mut self.vertex_triangles[v as usize]) {
+            let triangle = &mut self.triangles[t as usize];
+            if triangle.contains(&u) {
+                self.removed_triangles[t as usize] = true;
+                self.triangle_count -= 1;
+                for other in *triangle {
+                    self.vertex_triangles[other as usize].retain(|&other_t| other_t != t);
+                }
+            } else {
+                for vertex in triangle.iter_mut().filter(|vertex| **vertex == v) {
+                    *vertex = u;
+                }
+                self.vertex_triangles[u as usize].push(t);
+            }
+        }
+        self.removed_vertices[v as usize] = true;
+        self.boundary_vertices[u as usize] |= self.boundary_vertices[v as usize];
+        self.positions[u as usize] = target;
+        self.quadrics[u as usize] = self.quadrics[u as usize] + self.quadrics[v as usize];
+        self.versions[u as usize] += 1;
+        for neighbor in self.neighbors(u) {
+            self.push_collapse(u, neighbor);
+        }
+    }
+
+    /// The remaining triangles, with the unused vertices left out
+    fn into_mesh(self) -> Mesh {
+        let mut new_index = vec![u32::MAX; self.positions.len()];
+        let mut positions = Vec::new();
+        let mut indices = Vec::with_capacity(self.triangle_count * 3);
+        for (triangle, _) in self
+            .triangles
+            .iter()
+            .zip(&self.removed_triangles)
+            .filter(|(_, &removed)| !removed)
+        {
+            for &vertex in triangle {
+                if new_index[vertex as usize] == u32::MAX {
+                    new_index[vertex as usize] = positions.len() as u32;
+                    positions.push(self.positions[vertex as usize].as_vec3());
+                }
+                indices.push(new_index[vertex as usize]);
+            }
+        }
+        Mesh {
+            positions,
+            attributes: vec![],
+            indices,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        reconstruct::compare_meshes, repair::Topology, test_meshes::subdivided_octahedron,
+    };
+    use glam::Vec3;
+
+    #[test]
+    fn test_decimate_sphere() {
+        let sphere = subdivided_octahedron(5, Vec3::ONE);
+        let decimated = decimate_mesh(&sphere, 500);
+        assert!(decimated.faces_count() <= 500);
+        assert!(decimated.faces_count() >= 490);
+        let topology = Topology::of(&decimated);
+        assert_eq!(topology.genus, Some(0));
+        assert_eq!(topology.boundary_edges, 0);
+        assert_eq!(topology.components, 1);
+        let distance = compare_meshes(&sphere, &decimated, 10_000);
+        assert!(distance.hausdorff < 0.05, "{}", distance.hausdorff);
+    }
+
+    #[test]
+    fn test_keeps_the_boundary() {
+        // Half of a sphere, which has a boundary along the equator
+        let sphere = subdivided_octahedron(4, Vec3::ONE);
+        let indices: Vec<u32> = sphere
+            .triangles()
+            .filter(|triangle| {
+                triangle
+                    .to_array()
+                    .iter()
+                    .all(|&i| sphere.positions[i as usize].z >= 0.0)
+            })
+            .flat_map(|triangle| triangle.to_array())
+            .collect();
+        let half = Mesh { indices, ..sphere };
+        let decimated = decimate_mesh(&half, 100);
+        assert!(decimated.faces_count() <= 100);
+        let topology = Topology::of(&decimated);
+        assert_eq!(topology.boundary_loops, 1);
+        assert_eq!(topology.genus, Some(0));
+        // The equator stays round, and in its plane
+        let equator: Vec<&Vec3> = decimated
+            .positions
+            .iter()
+            .filter(|position| position.z.abs() < 1e-3)
+            .collect();
+        assert!(equator.len() >= 8);
+        for position in equator {
+            assert!((position.length() - 1.0).abs() < 0.02, "{position}");
+        }
+    }
+
+    #[test]
+    fn test_scale_invariance() {
+        let sphere = subdivided_octahedron(4, Vec3::ONE);
+        let distance = |scale: f32| {
+            let scaled = Mesh {
+                positions: sphere.positions.iter().map(|p| *p * scale).collect(),
+                attributes: vec![],
+                indices: sphere.indices.clone(),
+            };
+            let decimated = decimate_mesh(&scaled, 200);
+            compare_meshes(&scaled, &decimated, 10_000).hausdorff / scale
+        };
+        let unit = distance(1.0);
+        for scale in [1e-3, 1e3] {
+            assert!((distance(scale) - unit).abs() < 0.1 * unit, "{scale}");
+        }
+    }
+
+    #[test]
+    fn test_quadric_minimum() {
+        // Three planes through a corner have a single minimum at any scale, parallel planes have none
+        for scale in [1e-4, 1.0, 1e4] {
+            let corner = DVec3::splat(scale);
+            let planes = [DVec3::X, DVec3::Y, DVec3::Z]
+                .map(|normal| Quadric::plane(normal, corner, scale * scale));
+            let quadric = planes[0] + planes[1] + planes[2];
+            let minimum = quadric.minimum().unwrap();
+            assert!((minimum - corner).length() < 1e-9 * scale, "{minimum}");
+            let flat = planes[2] + Quadric::plane(DVec3::Z, corner * 2.0, scale * scale);
+            assert!(flat.minimum().is_none());
+        }
+    }
+
+    #[test]
+    fn test_small_mesh_is_kept() {
+        let sphere = subdivided_octahedron(1, Vec3::ONE);
+        let decimated = decimate_mesh(&sphere, 1000);
+        assert_eq!(decimated.indices, sphere.indices);
+        assert_eq!(decimated.positions, sphere.positions);
+    }
+}
@@ -0,0 +1,268 @@
+//! Measures how much a spherical parametrization distorts the mesh
+
+use std::path::Path;
+
+use glam::Vec3;
+use image::{DynamicImage, ImageBuffer, Rgb};
+
+use crate::{
+    encoding::EncodingError,
+    parametrization::{find_flipped_triangles, octant_group},
+    Mesh,
+};
+
+/// How far the parametrization is from mapping every triangle onto the sphere with its shape and relative size
+pub struct DistortionReport {
+    /// Per triangle, its share of the sphere divided by its share of the mesh surface. Above 1 the triangle gets
+    /// more pixels than its size deserves, below 1 it gets fewer.
+    pub area_distortion: Vec<f32>,
+    /// Per triangle, the mean difference between its angles on the mesh and on the sphere, in radians
+    pub angle_distortion: Vec<f32>,
+    /// Indices of the triangles that are turned over on the sphere
+    pub flipped_triangles: Vec<u32>,
+    /// The share of the sphere area in each octant, which is one of the 8 triangles of the octahedral geometry
+    /// image. Numbered like the triangles of the image: 4 for a negative x, plus 2 for a negative y, plus 1 for a
+    /// negative z. Triangles that cross an axis plane are split between the octants.
+    pub octant_coverage: [f32; 8],
+    /// The mean of `max(area, 1 / area)` over the mesh surface, 1 is perfect
+    pub mean_area_stretch: f32,
+    pub max_area_stretch: f32,
+    /// The mean angle distortion over the mesh surface, in radians
+    pub mean_angle_distortion: f32,
+    pub max_angle_distortion: f32,
+}
+
+/// Compares every triangle of the mesh with its image on the sphere
+pub fn analyze_distortion(mesh: &Mesh, parametrization: &[Vec3]) -> DistortionReport {
+    let triangles: Vec<([Vec3; 3], [Vec3; 3])> = mesh
+        .triangles()
+        .map(|triangle| {
+            (
+                triangle.to_array().map(|i| mesh.positions[i as usize]),
+                triangle.to_array().map(|i| parametrization[i as usize]),
+            )
+        })
+        .collect();
+    let mesh_areas: Vec<f32> = triangles.iter().map(|(mesh, _)| area(mesh)).collect();
+    let sphere_areas: Vec<f32> = triangles.iter().map(|(_, sphere)| area(sphere)).collect();
+    let mesh_total: f32 = mesh_areas.iter().sum();
+    let sphere_total: f32 = sphere_areas.iter().sum();
+
+    let area_distortion: Vec<f32> = mesh_areas
+        .iter()
+        .zip(&sphere_areas)
+        .map(|(&mesh_area, &sphere_area)| {
+            let mesh_share = mesh_area / mesh_total;
+            let sphere_share = sphere_area / sphere_total;
+            if mesh_share > 0.0 && sphere_share.is_finite() {
+                sphere_share / mesh_share
+            } else {
+                // Degenerate triangles have no size to distort
+                1.0
+            }
+        })
+        .collect();
+    let angle_distortion: Vec<f32> = triangles
+        .iter()
+        .map(|(mesh, sphere)| {
+            let difference: f32 = angles(mesh)
+                .iter()
+                .zip(angles(sphere))
+                .map(|(a, b)| (a - b).abs())
+                .sum();
+            difference / 3.0
+        })
+        .collect();
+
+    let mut octant_coverage = [0.0; 8];
+    for ((_, sphere), sphere_area) in triangles.iter().zip(&sphere_areas) {
+        let groups = sphere.map(octant_group);
+        if groups[1..].iter().all(|&group| group == groups[0]) {
+            octant_coverage[groups[0]] += sphere_area / sphere_total;
+            continue;
+        }
+        for (group, coverage) in octant_coverage.iter_mut().enumerate() {
+            *coverage += area_in_octant(sphere, group) / sphere_total;
+        }
+    }
+
+    let stretch: Vec<f32> = area_distortion
+        .iter()
+        .map(|&ratio| {
+            if ratio > 0.0 {
+                ratio.max(1.0 / ratio)
+            } else {
+                f32::INFINITY
+            }
+        })
+        .collect();
+    let weighted_mean = |values: &[f32]| -> f32 {
+        values
+            .iter()
+            .zip(&mesh_areas)
+            .map(|(value, area)| value * area / mesh_total)
+            .sum()
+    };
+    let max = |values: &[f32]| values.iter().copied().fold(0.0f32, f32::max);
+
+    DistortionReport {
+        mean_area_stretch: weighted_mean(&stretch),
+        max_area_stretch: max(&stretch),
+        mean_angle_distortion: weighted_mean(&angle_distortion),
+        max_angle_distortion: max(&angle_distortion),
+        flipped_triangles: find_flipped_triangles(mesh, parametrization),
+        area_distortion,
+        angle_distortion,
+        octant_coverage,
+    }
+}
+
+impl DistortionReport {
+    /// The base 2 logarithm of the area distortion, averaged around every vertex. Bake it with a
+    /// [`SampleGrid`](crate::parametrization::SampleGrid) to get a heat map in the layout of the geometry image.
+    pub fn vertex_area_distortion(&self, mesh: &Mesh) -> Vec<f32> {
+        let mut sums = vec![0.0f32; mesh.positions.len()];
+        let mut counts = vec![0u32; mesh.positions.len()];
+        for (triangle, ratio) in mesh.triangles().zip(&self.area_distortion) {
+            for vertex in triangle.to_array() {
+                sums[vertex as usize] += ratio.log2();
+                counts[vertex as usize] += 1;
+            }
+        }
+        sums.iter()
+            .zip(&counts)
+            .map(|(&sum, &count)| if count > 0 { sum / count as f32 } else { 0.0 })
+            .collect()
+    }
+}
+
+/// Writes the values as a blue-white-red image. Values of `-limit` and below are blue, zero is white, and `limit`
+/// and above are red.
+pub fn write_heat_map(
+    values: &[f32],
+    width: u32,
+    height: u32,
+    limit: f32,
+    path: impl AsRef<Path>,
+) -> Result<(), EncodingError> {
+    let image = ImageBuffer::from_fn(width, height, |x, y| {
+        let value = (values[(y * width + x) as usize] / limit).clamp(-1.0, 1.0);
+        let fade = (255.0 * (1.0 - value.abs())).round() as u8;
+        if value.is_nan() {
+            Rgb([0, 0, 0])
+        } else if value > 0.0 {
+            Rgb([u8::MAX, fade, fade])
+        } else {
+            Rgb([fade, fade, u8::MAX])
+        }
+    });
+    DynamicImage::ImageRgb8(image).save(path)?;
+    Ok(())
+}
+
+fn area([a, b, c]: &[Vec3; 3]) -> f32 {
+    (*b - *a).cross(*c - *a).length() * 0.5
+}
+
+/// The inner angles of a triangle, at each of its corners
+fn angles([a, b, c]: &[Vec3; 3]) -> [f32; 3] {
+    [
+        (*b - *a).angle_between(*c - *a),
+        (*c - *b).angle_between(*a - *b),
+        (*a - *c).angle_between(*b - *c),
+    ]
+    .map(|angle| if angle.is_finite() { angle } else { 0.0 })
+}
+
+/// The area of the part of a triangle that lies in an octant, numbered like the triangles of the octahedral image
+fn area_in_octant(triangle: &[Vec3; 3], group: usize) -> f32 {
+    let signs = [4, 2, 1].map(|bit| if group & bit != 0 { -1.0 } else { 1.0 });
+    let mut polygon = triangle.to_vec();
+    for (axis, sign) in signs.into_iter().enumerate() {
+        // Sutherland-Hodgman, keeping the side of the plane where the coordinate has the sign of the octant
+        let distance = |point: Vec3| sign * point[axis];
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for (i, &point) in polygon.iter().enumerate() {
+            let next = polygon[(i + 1) % polygon.len()];
+            let (d, d_next) = (distance(point), distance(next));
+            if d >= 0.0 {
+                clipped.push(point);
+            }
+            if (d > 0.0 && d_next < 0.0) || (d < 0.0 && d_next > 0.0) {
+                clipped.push(point.lerp(next, d / (d - d_next)));
+            }
+        }
+        polygon = clipped;
+        if polygon.len() < 3 {
+            return 0.0;
+        }
+    }
+    polygon[1..]
+        .windows(2)
+        .map(|edge| (edge[0] - polygon[0]).cross(edge[1] - polygon[0]))
+        .sum::<Vec3>()
+        .length()
+        * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::subdivided_octahedron;
+
+    #[test]
+    fn test_sphere_has_no_distortion() {
+        let sphere = subdivided_octahedron(2, Vec3::ONE);
+        let report = analyze_distortion(&sphere, &sphere.positions);
+        assert_eq!(report.area_distortion.len(), sphere.faces_count());
+        assert!(report
+            .area_distortion
+            .iter()
+            .all(|&d| (d - 1.0).abs() < 1e-4));
+        assert!(report.max_angle_distortion < 1e-4);
+        assert!((report.mean_area_stretch - 1.0).abs() < 1e-4);
+        assert!(report.flipped_triangles.is_empty());
+        for coverage in report.octant_coverage {
+            assert!((coverage - 0.125).abs() < 1e-4);
+        }
+        let per_vertex = report.vertex_area_distortion(&sphere);
+        assert!(per_vertex.iter().all(|d| d.abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_stretched_mesh() {
+        let sphere = subdivided_octahedron(2, Vec3::ONE);
+        let ellipsoid = subdivided_octahedron(2, Vec3::new(4.0, 1.0, 1.0));
+        let mut parametrization = sphere.positions.clone();
+        // Turn a vertex inside out, which flips the triangles around it
+        let vertex = sphere.indices[0] as usize;
+        parametrization[vertex] = -parametrization[vertex];
+        let report = analyze_distortion(&ellipsoid, &parametrization);
+        assert!(report.mean_area_stretch > 1.1);
+        assert!(report.mean_angle_distortion > 0.1);
+        assert!(!report.flipped_triangles.is_empty());
+        let total: f32 = report.octant_coverage.iter().sum();
+        assert!((total - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_octant_coverage() {
+        // A triangle on the sphere that straddles the planes x = 0 and z = 0
+        let triangle = [
+            Vec3::new(-0.5, 0.5, 0.5).normalize(),
+            Vec3::new(0.5, 0.5, -0.5).normalize(),
+            Vec3::new(0.5, 0.8, 0.5).normalize(),
+        ];
+        let areas: Vec<f32> = (0..8)
+            .map(|group| area_in_octant(&triangle, group))
+            .collect();
+        let total: f32 = areas.iter().sum();
+        assert!((total - area(&triangle)).abs() < 1e-5);
+        // It reaches +x+y+z, +x+y-z and -x+y+z, but its edge from -x to -z passes through x = z = 0
+        let covered: Vec<usize> = (0..8).filter(|&group| areas[group] > 0.0).collect();
+        assert_eq!(covered, [0, 1, 4]);
+        let center = triangle[0] + triangle[1] + triangle[2];
+        let group = octant_group(center);
+        assert_eq!(areas.iter().copied().fold(0.0, f32::max), areas[group]);
+    }
+}
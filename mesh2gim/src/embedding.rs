@@ -0,0 +1,508 @@
+//! Initial embeddings of a mesh on the sphere, which the Laplacian smoothing starts from.
+//!
+//! Projecting the vertices from the centroid only works for star-shaped meshes. Bent shapes like a horseshoe or a
+//! hand fold over themselves, and the smoothing then collapses the folds. The embeddings here do not fold, so the
+//! smoothing starts from a valid mapping.
+
+use glam::{Vec2, Vec3};
+use rayon::prelude::*;
+
+use crate::{adjacency::Adjacency, parametrization::Initialization, Mesh};
+
+/// The residual, relative to the right hand side, at which the linear solves stop
+const SOLVER_TOLERANCE: f64 = 1e-6;
+/// The most conjugate gradient iterations of one linear solve
+const SOLVER_ITERATIONS: usize = 2000;
+/// The inverse iterations that find the low eigenvectors of the Laplacian
+const SPECTRAL_ITERATIONS: usize = 8;
+
+/// The starting point of every vertex on the unit sphere, and the initialization it came from. Open and
+/// non-manifold meshes can make the requested initialization fold, then the other one is tried, and the embedding
+/// with the fewest folded triangles is used if all of them fold.
+pub(crate) fn initial_embedding(
+    mesh: &Mesh,
+    adjacency: &Adjacency,
+    initialization: Initialization,
+) -> (Vec<Vec3>, Initialization) {
+    let tutte = || {
+        farthest_vertices(adjacency)
+            .and_then(|(north, south)| tutte_embedding(mesh, adjacency, north, south))
+            .map(|sphere| (sphere, Initialization::Tutte))
+    };
+    let spectral = || spectral_embedding(mesh, adjacency);
+    let centroid = || Some((centroid_embedding(mesh), Initialization::Centroid));
+    type Candidate<'a> = &'a dyn Fn() -> Option<(Vec<Vec3>, Initialization)>;
+    let candidates: [Candidate; 3] = match initialization {
+        Initialization::Centroid => return (centroid_embedding(mesh), Initialization::Centroid),
+        Initialization::Tutte => [&tutte, &spectral, &centroid],
+        Initialization::Spectral => [&spectral, &tutte, &centroid],
+    };
+
+    let mut best: Option<(Vec<Vec3>, Initialization, usize)> = None;
+    for candidate in candidates {
+        let Some((sphere, used)) = candidate() else {
+            continue;
+        };
+        let folded = folded_triangles(mesh, &sphere);
+        if folded == 0 {
+            return (sphere, used);
+        }
+        if best.as_ref().is_none_or(|(_, _, fewest)| folded < *fewest) {
+            best = Some((sphere, used, folded));
+        }
+    }
+    best.map_or_else(
+        || (centroid_embedding(mesh), Initialization::Centroid),
+        |(sphere, used, _)| (sphere, used),
+    )
+}
+
+/// The direction of every vertex from the centroid of the vertices
+fn centroid_embedding(mesh: &Mesh) -> Vec<Vec3> {
+    // vertex1 = vertex1 - repmat( mean(vertex1,2), [1 n] );
+    let mean = mesh.positions.iter().sum::<Vec3>() / (mesh.positions.len() as f32);
+    // vertex1 = vertex1 ./ repmat( sqrt(sum(vertex1.^2,1)), [3 1] );
+    mesh.positions
+        .iter()
+        .map(|&position| (position - mean).normalize())
+        .collect()
+}
+
+/// Two vertices that are about as many edges apart as possible, or none if the mesh is not connected
+fn farthest_vertices(adjacency: &Adjacency) -> Option<(usize, usize)> {
+    let first = farthest_from(adjacency, 0)?;
+    Some((first, farthest_from(adjacency, first)?))
+}
+
+/// The last vertex a breadth first search reaches, if it reaches every vertex. None for meshes without vertices.
+fn farthest_from(adjacency: &Adjacency, start: usize) -> Option<usize> {
+    if start >= adjacency.vertex_count() {
+        return None;
+    }
+    let order = breadth_first_order(adjacency, start);
+    (order.len() == adjacency.vertex_count()).then(|| order[order.len() - 1])
+}
+
+/// The vertices that a breadth first search from `start` reaches, in the order it reaches them
+fn breadth_first_order(adjacency: &Adjacency, start: usize) -> Vec<usize> {
+    let mut visited = vec![false; adjacency.vertex_count()];
+    let mut order = vec![start];
+    visited[start] = true;
+    let mut next = 0;
+    while let Some(&vertex) = order.get(next) {
+        next += 1;
+        for &neighbor in adjacency.neighbors(vertex) {
+            if !visited[neighbor as usize] {
+                visited[neighbor as usize] = true;
+                order.push(neighbor as usize);
+            }
+        }
+    }
+    order
+}
+
+/// Tutte's embedding, on the sphere. The triangles around the north pole are removed, and the rest of the mesh is
+/// embedded into a disk with every vertex at the average of its neighbors. Such an embedding never folds. The disk
+/// is moved so the south pole is in its center, and lifted onto the sphere with the inverse of the stereographic
+/// projection, which keeps it fold free. The removed triangles then cover the cap around the north pole.
+///
+/// A north pole on a hole or at a non-manifold vertex has no ring of neighbors to pin to the circle, so the vertex
+/// farthest from the south pole that has one is used instead. Returns none if no vertex has one.
+fn tutte_embedding(
+    mesh: &Mesh,
+    adjacency: &Adjacency,
+    north: usize,
+    south: usize,
+) -> Option<Vec<Vec3>> {
+    let stars = vertex_stars(mesh);
+    let (north, ring) = match vertex_ring(&stars[north]) {
+        Some(ring) => (north, ring),
+        None => breadth_first_order(adjacency, south)
+            .into_iter()
+            .rev()
+            .find_map(|vertex| Some((vertex, vertex_ring(&stars[vertex])?)))?,
+    };
+    let vertex_count = adjacency.vertex_count();
+    let mut fixed = vec![false; vertex_count];
+    fixed[north] = true;
+    let mut disk = vec![Vec2::ZERO; vertex_count];
+    for (i, &vertex) in ring.iter().enumerate() {
+        let angle = i as f32 / ring.len() as f32 * std::f32::consts::TAU;
+        disk[vertex] = Vec2::from_angle(angle);
+        fixed[vertex] = true;
+    }
+
+    // The free vertices are pulled towards the fixed ones, the north pole is not connected to any of them
+    let solve = |axis: usize| {
+        let rhs: Vec<f64> = (0..vertex_count)
+            .map(|i| {
+                if fixed[i] {
+                    return 0.0;
+                }
+                adjacency
+                    .neighbors(i)
+                    .iter()
+                    .filter(|&&j| fixed[j as usize])
+                    .map(|&j| disk[j as usize][axis] as f64)
+                    .sum()
+            })
+            .collect();
+        let mut solution = vec![0.0; vertex_count];
+        conjugate_gradient(
+            |x, y| apply_laplacian(adjacency, Some(&fixed), x, y),
+            &rhs,
+            &mut solution,
+        );
+        solution
+    };
+    let (x, y) = (solve(0), solve(1));
+    for (i, point) in disk.iter_mut().enumerate() {
+        if !fixed[i] {
+            *point = Vec2::new(x[i] as f32, y[i] as f32);
+        }
+    }
+
+    // A Möbius transformation of the disk moves the south pole to the center, without folding anything
+    if !fixed[south] && disk[south].length() < 1.0 {
+        let center = disk[south];
+        for point in &mut disk {
+            *point = complex_divide(
+                *point - center,
+                Vec2::ONE - complex_multiply(conjugate(center), *point),
+            );
+        }
+    }
+
+    // The ring becomes the circle of latitude that leaves the star of the north pole its share of the sphere
+    let area = |triangle: glam::UVec3| {
+        let [a, b, c] = triangle.to_array().map(|i| mesh.positions[i as usize]);
+        (b - a).cross(c - a).length()
+    };
+    let total_area: f32 = mesh.triangles().map(area).sum();
+    let star_area: f32 = mesh
+        .triangles()
+        .filter(|triangle| triangle.to_array().contains(&(north as u32)))
+        .map(area)
+        .sum();
+    let cap = (star_area / total_area).clamp(1.0 / vertex_count as f32, 0.5);
+    let height = 1.0 - 2.0 * cap;
+    let scale = ((1.0 + height) / (1.0 - height)).sqrt();
+
+    let mut sphere: Vec<Vec3> = disk
+        .iter()
+        .map(|&point| {
+            let point = point * scale;
+            let length_squared = point.length_squared();
+            Vec3::new(2.0 * point.x, 2.0 * point.y, length_squared - 1.0) / (length_squared + 1.0)
+        })
+        .collect();
+    sphere[north] = Vec3::Z;
+    Some(orient_outwards(mesh, sphere))
+}
+
+/// For every vertex, the edges opposite to it in the triangles around it, in the orientation of the triangles
+fn vertex_stars(mesh: &Mesh) -> Vec<Vec<(u32, u32)>> {
+    let mut stars = vec![Vec::new(); mesh.positions.len()];
+    for triangle in mesh.triangles() {
+        let [a, b, c] = triangle.to_array();
+        stars[a as usize].push((b, c));
+        stars[b as usize].push((c, a));
+        stars[c as usize].push((a, b));
+    }
+    stars
+}
+
+/// The neighbors of a vertex in the order they go around it, if the edges of its star form a single closed ring
+fn vertex_ring(star: &[(u32, u32)]) -> Option<Vec<usize>> {
+    let mut next = std::collections::HashMap::new();
+    for &(from, to) in star {
+        if next.insert(from, to).is_some() {
+            return None;
+        }
+    }
+    let &start = next.keys().next()?;
+    let mut ring = vec![start as usize];
+    let mut current = *next.get(&start)?;
+    while current != start {
+        ring.push(current as usize);
+        current = *next.get(&current)?;
+        if ring.len() > next.len() {
+            return None;
+        }
+    }
+    (ring.len() == next.len() && ring.len() >= 3).then_some(ring)
+}
+
+/// The eigenvectors of the graph Laplacian with the smallest non-zero eigenvalues, used as coordinates. They are
+/// the smoothest functions on the mesh, and spread the vertices evenly, but can fold on bent meshes. The Tutte
+/// embedding is used instead when they do, with its poles at the ends of the smoothest eigenvector, and returned
+/// with the initialization that made it.
+fn spectral_embedding(mesh: &Mesh, adjacency: &Adjacency) -> Option<(Vec<Vec3>, Initialization)> {
+    let vertex_count = adjacency.vertex_count();
+    farthest_from(adjacency, 0)?;
+    // The coordinates are already smooth, so they are a good start for the inverse iteration
+    let mean = mesh.positions.iter().sum::<Vec3>() / vertex_count as f32;
+    let mut basis: Vec<Vec<f64>> = (0..3)
+        .map(|axis| {
+            mesh.positions
+                .iter()
+                .map(|position| (*position - mean)[axis] as f64)
+                .collect()
+        })
+        .collect();
+    orthonormalize(&mut basis);
+    for _ in 0..SPECTRAL_ITERATIONS {
+        for vector in &mut basis {
+            let mut solution = vec![0.0; vertex_count];
+            conjugate_gradient(
+                |x, y| apply_laplacian(adjacency, None, x, y),
+                vector,
+                &mut solution,
+            );
+            *vector = solution;
+        }
+        orthonormalize(&mut basis);
+    }
+
+    let sphere: Vec<Vec3> = (0..vertex_count)
+        .map(|i| {
+            Vec3::new(basis[0][i] as f32, basis[1][i] as f32, basis[2][i] as f32)
+                .normalize_or_zero()
+        })
+        .collect();
+    let sphere = orient_outwards(mesh, sphere);
+    if !folds(mesh, &sphere) {
+        return Some((sphere, Initialization::Spectral));
+    }
+    let smoothest = &basis[0];
+    let extreme = |ordering: std::cmp::Ordering| {
+        (0..vertex_count)
+            .reduce(|a, b| {
+                if smoothest[b].total_cmp(&smoothest[a]) == ordering {
+                    b
+                } else {
+                    a
+                }
+            })
+            .unwrap_or(0)
+    };
+    tutte_embedding(
+        mesh,
+        adjacency,
+        extreme(std::cmp::Ordering::Greater),
+        extreme(std::cmp::Ordering::Less),
+    )
+    .map(|sphere| (sphere, Initialization::Tutte))
+}
+
+/// Whether any triangle is turned over, or collapsed to a point
+fn folds(mesh: &Mesh, sphere: &[Vec3]) -> bool {
+    folded_triangles(mesh, sphere) > 0
+}
+
+/// How many triangles are turned over, or collapsed to a point
+fn folded_triangles(mesh: &Mesh, sphere: &[Vec3]) -> usize {
+    mesh.triangles()
+        .filter(|triangle| {
+            let [a, b, c] = triangle.to_array().map(|i| sphere[i as usize]);
+            (b - a).cross(c - a).dot(a + b + c) <= 0.0
+        })
+        .count()
+}
+
+/// Mirrors the embedding if most triangles face inwards
+fn orient_outwards(mesh: &Mesh, mut sphere: Vec<Vec3>) -> Vec<Vec3> {
+    let balance: i64 = mesh
+        .triangles()
+        .map(|triangle| {
+            let [a, b, c] = triangle.to_array().map(|i| sphere[i as usize]);
+            (b - a).cross(c - a).dot(a + b + c).signum() as i64
+        })
+        .sum();
+    if balance < 0 {
+        for point in &mut sphere {
+            point.x = -point.x;
+        }
+    }
+    sphere
+}
+
+/// Multiplies by the graph Laplacian, which has the degree of every vertex on the diagonal and -1 for every
+/// neighbor. Fixed vertices are left out of the system.
+fn apply_laplacian(adjacency: &Adjacency, fixed: Option<&[bool]>, x: &[f64], y: &mut [f64]) {
+    let is_fixed = |i: usize| fixed.is_some_and(|fixed| fixed[i]);
+    y.par_iter_mut().enumerate().for_each(|(i, value)| {
+        *value = if is_fixed(i) {
+            x[i]
+        } else {
+            let neighbors = adjacency.neighbors(i);
+            let sum: f64 = neighbors
+                .iter()
+                .filter(|&&j| !is_fixed(j as usize))
+                .map(|&j| x[j as usize])
+                .sum();
+            neighbors.len() as f64 * x[i] - sum
+        };
+    });
+}
+
+/// Solves `apply(x) = rhs` for a symmetric positive semi-definite operator, starting from `x`
+fn conjugate_gradient(apply: impl Fn(&[f64], &mut [f64]), rhs: &[f64], x: &mut [f64]) {
+    let dot = |a: &[f64], b: &[f64]| -> f64 { a.par_iter().zip(b).map(|(a, b)| a * b).sum() };
+    let mut product = vec![0.0; x.len()];
+    apply(x, &mut product);
+    let mut residual: Vec<f64> = rhs.iter().zip(&product).map(|(b, ax)| b - ax).collect();
+    let mut direction = residual.clone();
+    let mut residual_squared = dot(&residual, &residual);
+    let target = dot(rhs, rhs) * SOLVER_TOLERANCE * SOLVER_TOLERANCE;
+    for _ in 0..SOLVER_ITERATIONS {
+        if residual_squared <= target {
+            break;
+        }
+        apply(&direction, &mut product);
+        let curvature = dot(&direction, &product);
+        if curvature <= 0.0 {
+            break;
+        }
+        let step = residual_squared / curvature;
+        for i in 0..x.len() {
+            x[i] += step * direction[i];
+            residual[i] -= step * product[i];
+        }
+        let next_squared = dot(&residual, &residual);
+        let beta = next_squared / residual_squared;
+        for (direction, residual) in direction.iter_mut().zip(&residual) {
+            *direction = residual + beta * *direction;
+        }
+        residual_squared = next_squared;
+    }
+}
+
+/// Gram-Schmidt, which also removes the constant vector, the eigenvector of the Laplacian with eigenvalue zero
+fn orthonormalize(basis: &mut [Vec<f64>]) {
+    for i in 0..basis.len() {
+        let (previous, rest) = basis.split_at_mut(i);
+        let vector = &mut rest[0];
+        let mean = vector.iter().sum::<f64>() / vector.len() as f64;
+        vector.iter_mut().for_each(|value| *value -= mean);
+        for other in previous.iter() {
+            let projection: f64 = vector.iter().zip(other).map(|(a, b)| a * b).sum();
+            vector
+                .iter_mut()
+                .zip(other)
+                .for_each(|(value, other)| *value -= projection * other);
+        }
+        let length = vector.iter().map(|value| value * value).sum::<f64>().sqrt();
+        if length > 0.0 {
+            vector.iter_mut().for_each(|value| *value /= length);
+        }
+    }
+}
+
+fn complex_multiply(a: Vec2, b: Vec2) -> Vec2 {
+    Vec2::new(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x)
+}
+
+fn complex_divide(a: Vec2, b: Vec2) -> Vec2 {
+    complex_multiply(a, conjugate(b)) / b.length_squared()
+}
+
+fn conjugate(a: Vec2) -> Vec2 {
+    Vec2::new(a.x, -a.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parametrization::find_flipped_triangles,
+        test_meshes::{horseshoe, subdivided_octahedron},
+    };
+
+    #[test]
+    fn test_embeddings_do_not_fold() {
+        let horseshoe = horseshoe(4);
+        let adjacency = Adjacency::from_edges(horseshoe.positions.len(), &horseshoe.edges());
+        let (centroid, used) = initial_embedding(&horseshoe, &adjacency, Initialization::Centroid);
+        assert!(folds(&horseshoe, &centroid));
+        assert_eq!(used, Initialization::Centroid);
+        for initialization in [Initialization::Tutte, Initialization::Spectral] {
+            let (sphere, _) = initial_embedding(&horseshoe, &adjacency, initialization);
+            assert!(!folds(&horseshoe, &sphere), "{initialization}");
+            assert!(sphere
+                .iter()
+                .all(|point| (point.length() - 1.0).abs() < 1e-4));
+        }
+    }
+
+    #[test]
+    fn test_open_mesh_does_not_fold() {
+        // Removing a triangle at the pole that Tutte's embedding would pick leaves it on a hole, without a ring
+        let mut horseshoe = horseshoe(4);
+        let adjacency = Adjacency::from_edges(horseshoe.positions.len(), &horseshoe.edges());
+        let (north, _) = farthest_vertices(&adjacency).unwrap();
+        let triangle = horseshoe
+            .triangles()
+            .position(|triangle| triangle.to_array().contains(&(north as u32)))
+            .unwrap();
+        horseshoe.indices.drain(triangle * 3..triangle * 3 + 3);
+        let adjacency = Adjacency::from_edges(horseshoe.positions.len(), &horseshoe.edges());
+        assert!(vertex_ring(&vertex_stars(&horseshoe)[north]).is_none());
+
+        for initialization in [Initialization::Tutte, Initialization::Spectral] {
+            let (sphere, used) = initial_embedding(&horseshoe, &adjacency, initialization);
+            assert!(!folds(&horseshoe, &sphere), "{initialization}");
+            assert_ne!(used, Initialization::Centroid, "{initialization}");
+        }
+        let sphere = farthest_vertices(&adjacency)
+            .and_then(|(north, south)| tutte_embedding(&horseshoe, &adjacency, north, south))
+            .unwrap();
+        assert!(!folds(&horseshoe, &sphere));
+    }
+
+    #[test]
+    fn test_empty_mesh() {
+        let empty = Mesh {
+            positions: vec![],
+            attributes: vec![],
+            indices: vec![],
+        };
+        let adjacency = Adjacency::from_edges(0, &[]);
+        for initialization in [
+            Initialization::Centroid,
+            Initialization::Tutte,
+            Initialization::Spectral,
+        ] {
+            assert!(initial_embedding(&empty, &adjacency, initialization)
+                .0
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn test_spectral_embedding_of_a_sphere() {
+        // The smoothest functions on a sphere are close to the coordinates, so the vertices stay spread out. Tutte's
+        // embedding squeezes them towards the south pole instead.
+        let sphere = subdivided_octahedron(3, Vec3::ONE);
+        let adjacency = Adjacency::from_edges(sphere.positions.len(), &sphere.edges());
+        let shortest_edge = |initialization| {
+            let (embedding, used) = initial_embedding(&sphere, &adjacency, initialization);
+            assert_eq!(used, initialization);
+            assert!(find_flipped_triangles(&sphere, &embedding).is_empty());
+            let angles: Vec<f32> = sphere
+                .edges()
+                .iter()
+                .map(|edge| embedding[edge.x as usize].angle_between(embedding[edge.y as usize]))
+                .collect();
+            let mean = angles.iter().sum::<f32>() / angles.len() as f32;
+            angles
+                .iter()
+                .fold(f32::INFINITY, |min, &angle| min.min(angle))
+                / mean
+        };
+        let spectral = shortest_edge(Initialization::Spectral);
+        let tutte = shortest_edge(Initialization::Tutte);
+        assert!(spectral > 0.3, "{spectral}");
+        assert!(tutte < spectral, "{tutte}");
+    }
+}
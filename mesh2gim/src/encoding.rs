@@ -0,0 +1,672 @@
+//! Writes geometry images to files, and reads them back into an [`Image`]
+//!
+//! Three file formats are supported, chosen by the extension of the path:
+//! - `.png` quantizes every channel to 8 or 16 bits. The range that was mapped to [0, 1] has to be stored
+//!   next to the image, since it is needed to read the values back.
+//! - `.exr` stores lossless 32 bit floats.
+//! - `.f32` is a raw dump of 32 bit floats with a small header, all numbers little endian:
+//!
+//! | Offset | Type       | Content                                               |
+//! |--------|------------|-------------------------------------------------------|
+//! | 0      | `[u8; 4]`  | Magic bytes `GIMF`                                    |
+//! | 4      | `u32`      | Version, currently 1                                  |
+//! | 8      | `u32`      | Width                                                 |
+//! | 12     | `u32`      | Height                                                |
+//! | 16     | `u32`      | Channels per pixel, 1 to 4                            |
+//! | 20     | `[f32]`    | `width * height * channels` floats, top row first     |
+
+use std::{fmt, path::Path, str::FromStr};
+
+use glam::{Vec3, Vec4};
+use image::{DynamicImage, ImageBuffer};
+use thiserror::Error;
+
+use crate::{AttributeImage, Image, AABB};
+
+/// Magic bytes at the start of raw `.f32` files
+pub const RAW_MAGIC: &[u8; 4] = b"GIMF";
+/// Version of the raw `.f32` header
+pub const RAW_VERSION: u32 = 1;
+const RAW_HEADER_SIZE: usize = 20;
+
+#[derive(Error, Debug)]
+pub enum EncodingError {
+    #[error("could not access the image file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not encode or decode the image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("unknown image format, expected a .png, .exr or .f32 file")]
+    UnknownFormat,
+    #[error("{format} files can not store {bit_depth} bit channels")]
+    UnsupportedBitDepth {
+        format: ImageFormat,
+        bit_depth: BitDepth,
+    },
+    #[error("quantized images can only be read back with the range of their values")]
+    MissingRange,
+    #[error("invalid raw .f32 file: {0}")]
+    Raw(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Exr,
+    /// The raw `.f32` format described in the [module documentation](self)
+    RawF32,
+}
+
+impl ImageFormat {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "exr" => Some(ImageFormat::Exr),
+            "f32" => Some(ImageFormat::RawF32),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Exr => "exr",
+            ImageFormat::RawF32 => "f32",
+        }
+    }
+
+    /// 16 bits for PNG, floats for everything else
+    pub fn default_bit_depth(self) -> BitDepth {
+        match self {
+            ImageFormat::Png => BitDepth::Sixteen,
+            ImageFormat::Exr | ImageFormat::RawF32 => BitDepth::Float32,
+        }
+    }
+
+    pub fn supports(self, bit_depth: BitDepth) -> bool {
+        match self {
+            ImageFormat::Png => bit_depth != BitDepth::Float32,
+            ImageFormat::Exr | ImageFormat::RawF32 => bit_depth == BitDepth::Float32,
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ".{}", self.extension())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    /// Small previews
+    Eight,
+    Sixteen,
+    /// Lossless, only for .exr and .f32 files
+    Float32,
+}
+
+impl BitDepth {
+    pub fn bits(self) -> u32 {
+        match self {
+            BitDepth::Eight => 8,
+            BitDepth::Sixteen => 16,
+            BitDepth::Float32 => 32,
+        }
+    }
+}
+
+impl fmt::Display for BitDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.bits())
+    }
+}
+
+impl FromStr for BitDepth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "8" => Ok(BitDepth::Eight),
+            "16" => Ok(BitDepth::Sixteen),
+            "32" => Ok(BitDepth::Float32),
+            _ => Err(format!("unsupported bit depth {s}, expected 8, 16 or 32")),
+        }
+    }
+}
+
+/// How values are mapped to [0, 1] before they get quantized
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Quantization {
+    /// Every channel is stretched to its own range. Uses all the precision, but distorts the proportions.
+    #[default]
+    Bounds,
+    /// Every channel is scaled by the largest range, so the proportions are kept
+    Uniform,
+}
+
+impl Quantization {
+    /// Returns the minimum and maximum of the range that gets mapped to [0, 1].
+    /// Only the first `components` channels are taken into account.
+    pub fn range(self, values: &[Vec4], components: usize) -> (Vec4, Vec4) {
+        // Unused channels are kept at zero
+        let mask = Vec4::from_array([0, 1, 2, 3].map(|i| if i < components { 1.0 } else { 0.0 }));
+        let mut min = Vec4::splat(f32::INFINITY);
+        let mut max = Vec4::splat(f32::NEG_INFINITY);
+        for value in values {
+            min = min.min(*value * mask);
+            max = max.max(*value * mask);
+        }
+        match self {
+            Quantization::Bounds => (min, max),
+            Quantization::Uniform => {
+                let extent = (max - min).max_element();
+                (min, min + extent * mask)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Quantization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quantization::Bounds => write!(f, "bounds"),
+            Quantization::Uniform => write!(f, "uniform"),
+        }
+    }
+}
+
+impl FromStr for Quantization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bounds" => Ok(Quantization::Bounds),
+            "uniform" => Ok(Quantization::Uniform),
+            _ => Err(format!(
+                "unknown quantization {s}, expected bounds or uniform"
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputOptions {
+    pub bit_depth: BitDepth,
+    /// Ignored by float formats
+    pub quantization: Quantization,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            bit_depth: BitDepth::Sixteen,
+            quantization: Quantization::Bounds,
+        }
+    }
+}
+
+/// Writes the positions of a geometry image. Returns the range that got mapped to [0, 1], which is needed to read
+/// back quantized images. Float formats store the positions unchanged.
+pub fn write_image(
+    image: &Image,
+    path: impl AsRef<Path>,
+    options: &OutputOptions,
+) -> Result<AABB, EncodingError> {
+    let pixels: Vec<Vec4> = image.pixels.iter().map(|pixel| pixel.extend(0.0)).collect();
+    let (min, max) = write_pixels(
+        path.as_ref(),
+        image.width,
+        image.height,
+        3,
+        &pixels,
+        options,
+        None,
+    )?;
+    Ok(AABB {
+        min: min.truncate(),
+        max: max.truncate(),
+    })
+}
+
+/// Writes the positions like [`write_image`], but maps the given range to [0, 1] instead of the range of the image.
+/// Images that share a range, like the frames of a geometry video, can be blended before they are decoded.
+pub fn write_image_in_range(
+    image: &Image,
+    range: &AABB,
+    path: impl AsRef<Path>,
+    options: &OutputOptions,
+) -> Result<(), EncodingError> {
+    let pixels: Vec<Vec4> = image.pixels.iter().map(|pixel| pixel.extend(0.0)).collect();
+    write_pixels(
+        path.as_ref(),
+        image.width,
+        image.height,
+        3,
+        &pixels,
+        options,
+        Some((range.min.extend(0.0), range.max.extend(0.0))),
+    )?;
+    Ok(())
+}
+
+/// Quantizes the positions to 16 bits like a `.png` file, but keeps them in memory as interleaved RGBA, top row
+/// first. The alpha channel is the validity mask: 65535 where the mesh was sampled, 0 where a hole was filled in.
+/// Returns the range that got mapped to [0, 1].
+pub fn encode_rgba16(image: &Image, quantization: Quantization) -> (Vec<u16>, AABB) {
+    let pixels: Vec<Vec4> = image.pixels.iter().map(|pixel| pixel.extend(0.0)).collect();
+    let (min, max) = quantization.range(&pixels, 3);
+    let extent = (max - min).max(Vec4::splat(f32::EPSILON));
+    let data = pixels
+        .iter()
+        .zip(&image.valid)
+        .flat_map(|(pixel, &valid)| {
+            let quantized =
+                ((*pixel - min) / extent).clamp(Vec4::ZERO, Vec4::ONE) * u16::MAX as f32;
+            let alpha = if valid { u16::MAX } else { 0 };
+            [
+                quantized.x.round() as u16,
+                quantized.y.round() as u16,
+                quantized.z.round() as u16,
+                alpha,
+            ]
+        })
+        .collect();
+    let range = AABB {
+        min: min.truncate(),
+        max: max.truncate(),
+    };
+    (data, range)
+}
+
+/// Stores the data of [`encode_rgba16`] as the bytes of a 16 bit RGBA `.png` file, for places without a filesystem
+pub fn rgba16_to_png(width: u32, height: u32, data: Vec<u16>) -> Result<Vec<u8>, EncodingError> {
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    DynamicImage::ImageRgba16(buffer(width, height, data))
+        .write_to(&mut bytes, image::ImageFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+/// Reads the bytes of a `.png` file back into the width, the height and the interleaved RGBA16 data of
+/// [`encode_rgba16`]. Files without an alpha channel are fully valid.
+pub fn rgba16_from_png(bytes: &[u8]) -> Result<(u32, u32, Vec<u16>), EncodingError> {
+    let image = image::load_from_memory_with_format(bytes, image::ImageFormat::Png)?.into_rgba16();
+    Ok((image.width(), image.height(), image.into_raw()))
+}
+
+/// Writes an attribute image, with the same file formats as [`write_image`]. Returns the range of every channel.
+pub fn write_attribute_image(
+    image: &AttributeImage,
+    path: impl AsRef<Path>,
+    options: &OutputOptions,
+) -> Result<(Vec4, Vec4), EncodingError> {
+    let pixels: Vec<Vec4> = (0..image.values.len())
+        .map(|i| image.values.get_vec4(i))
+        .collect();
+    write_pixels(
+        path.as_ref(),
+        image.width,
+        image.height,
+        image.values.components(),
+        &pixels,
+        options,
+        None,
+    )
+}
+
+fn write_pixels(
+    path: &Path,
+    width: u32,
+    height: u32,
+    components: usize,
+    pixels: &[Vec4],
+    options: &OutputOptions,
+    range: Option<(Vec4, Vec4)>,
+) -> Result<(Vec4, Vec4), EncodingError> {
+    let format = ImageFormat::from_extension(path).ok_or(EncodingError::UnknownFormat)?;
+    if !format.supports(options.bit_depth) {
+        return Err(EncodingError::UnsupportedBitDepth {
+            format,
+            bit_depth: options.bit_depth,
+        });
+    }
+    let (min, max) = range.unwrap_or_else(|| options.quantization.range(pixels, components));
+
+    // Images only come with one, three or four channels
+    let channels = match components {
+        1 => 1,
+        2 | 3 => 3,
+        _ => 4,
+    };
+    let interleave = |values: &mut dyn Iterator<Item = Vec4>, channels: usize| -> Vec<f32> {
+        values
+            .flat_map(|value| value.to_array().into_iter().take(channels))
+            .collect()
+    };
+    match format {
+        ImageFormat::Png => {
+            let extent = (max - min).max(Vec4::splat(f32::EPSILON));
+            let levels = match options.bit_depth {
+                BitDepth::Eight => u8::MAX as f32,
+                _ => u16::MAX as f32,
+            };
+            let data: Vec<u16> = interleave(
+                &mut pixels
+                    .iter()
+                    .map(|pixel| ((*pixel - min) / extent).clamp(Vec4::ZERO, Vec4::ONE)),
+                channels,
+            )
+            .into_iter()
+            .map(|value| (value * levels).round() as u16)
+            .collect();
+            let bytes = || data.iter().map(|&value| value as u8).collect::<Vec<_>>();
+            let image = match (options.bit_depth, channels) {
+                (BitDepth::Eight, 1) => DynamicImage::ImageLuma8(buffer(width, height, bytes())),
+                (BitDepth::Eight, 3) => DynamicImage::ImageRgb8(buffer(width, height, bytes())),
+                (BitDepth::Eight, _) => DynamicImage::ImageRgba8(buffer(width, height, bytes())),
+                (_, 1) => DynamicImage::ImageLuma16(buffer(width, height, data)),
+                (_, 3) => DynamicImage::ImageRgb16(buffer(width, height, data)),
+                _ => DynamicImage::ImageRgba16(buffer(width, height, data)),
+            };
+            image.save(path)?;
+        }
+        ImageFormat::Exr => {
+            // EXR files only come with RGB and RGBA, so scalars are stored in the red channel
+            let image = match channels {
+                4 => DynamicImage::ImageRgba32F(buffer(
+                    width,
+                    height,
+                    interleave(&mut pixels.iter().copied(), 4),
+                )),
+                _ => DynamicImage::ImageRgb32F(buffer(
+                    width,
+                    height,
+                    interleave(&mut pixels.iter().copied(), 3),
+                )),
+            };
+            image.save(path)?;
+        }
+        ImageFormat::RawF32 => {
+            let data = interleave(&mut pixels.iter().copied(), components);
+            std::fs::write(path, encode_raw(width, height, components as u32, &data))?;
+        }
+    }
+    Ok((min, max))
+}
+
+fn buffer<P: image::Pixel>(
+    width: u32,
+    height: u32,
+    data: Vec<P::Subpixel>,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    ImageBuffer::from_raw(width, height, data).expect("The buffer should match the image size")
+}
+
+/// Reads a geometry image that was written by [`write_image`]. Quantized images need the `range` that was
+/// returned when writing them. The file does not know which pixels were valid, so they are all marked as valid.
+pub fn read_image(path: impl AsRef<Path>, range: Option<&AABB>) -> Result<Image, EncodingError> {
+    let path = path.as_ref();
+    let format = ImageFormat::from_extension(path).ok_or(EncodingError::UnknownFormat)?;
+    let (width, height, pixels) = match format {
+        ImageFormat::Png => {
+            let range = range.ok_or(EncodingError::MissingRange)?;
+            let image = image::open(path)?.into_rgb32f();
+            let pixels = image
+                .pixels()
+                .map(|pixel| range.min + Vec3::from(pixel.0) * (range.max - range.min))
+                .collect();
+            (image.width(), image.height(), pixels)
+        }
+        ImageFormat::Exr => {
+            let image = image::open(path)?.into_rgb32f();
+            let pixels = image.pixels().map(|pixel| Vec3::from(pixel.0)).collect();
+            (image.width(), image.height(), pixels)
+        }
+        ImageFormat::RawF32 => {
+            let (width, height, channels, data) = decode_raw(&std::fs::read(path)?)?;
+            let pixels = data
+                .chunks_exact(channels)
+                .map(|pixel| {
+                    let mut position = Vec3::ZERO;
+                    for (i, value) in pixel.iter().take(3).enumerate() {
+                        position[i] = *value;
+                    }
+                    position
+                })
+                .collect();
+            (width, height, pixels)
+        }
+    };
+    Ok(Image {
+        width,
+        height,
+        pixels,
+        valid: vec![true; (width * height) as usize],
+    })
+}
+
+fn encode_raw(width: u32, height: u32, channels: u32, data: &[f32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(RAW_HEADER_SIZE + data.len() * 4);
+    bytes.extend_from_slice(RAW_MAGIC);
+    for value in [RAW_VERSION, width, height, channels] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in data {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+/// Returns the width, the height, the channels and the floats of a raw `.f32` file
+fn decode_raw(bytes: &[u8]) -> Result<(u32, u32, usize, Vec<f32>), EncodingError> {
+    if bytes.len() < RAW_HEADER_SIZE || !bytes.starts_with(RAW_MAGIC) {
+        return Err(EncodingError::Raw("missing GIMF header".to_string()));
+    }
+    let (header, body) = bytes.split_at(RAW_HEADER_SIZE);
+    let [version, width, height, channels] = [4, 8, 12, 16]
+        .map(|offset| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap()));
+    if version != RAW_VERSION {
+        return Err(EncodingError::Raw(format!("unsupported version {version}")));
+    }
+    if !(1..=4).contains(&channels) {
+        return Err(EncodingError::Raw(format!(
+            "unsupported channel count {channels}"
+        )));
+    }
+    let expected_size = width as u64 * height as u64 * channels as u64 * 4;
+    if body.len() as u64 != expected_size {
+        return Err(EncodingError::Raw(format!(
+            "expected {expected_size} bytes of pixels, found {}",
+            body.len()
+        )));
+    }
+    let data = body
+        .as_chunks::<4>()
+        .0
+        .iter()
+        .map(|bytes| f32::from_le_bytes(*bytes))
+        .collect();
+    Ok((width, height, channels as usize, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AttributeValues;
+
+    fn test_image() -> Image {
+        let (width, height) = (5, 3);
+        let pixels = (0..width * height)
+            .map(|i| Vec3::new(i as f32 * 0.37 - 2.0, (i * i) as f32 * 0.01, 1.5))
+            .collect();
+        Image {
+            width,
+            height,
+            pixels,
+            valid: vec![true; (width * height) as usize],
+        }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("mesh2gim_{}_{name}", std::process::id()))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let image = test_image();
+        let cases = [
+            ("8.png", BitDepth::Eight, 1.0 / 255.0),
+            ("16.png", BitDepth::Sixteen, 1.0 / 65535.0),
+            ("32.exr", BitDepth::Float32, 0.0),
+            ("32.f32", BitDepth::Float32, 0.0),
+        ];
+        for (name, bit_depth, relative_error) in cases {
+            for quantization in [Quantization::Bounds, Quantization::Uniform] {
+                let path = temp_path(name);
+                let options = OutputOptions {
+                    bit_depth,
+                    quantization,
+                };
+                let range = write_image(&image, &path, &options).unwrap();
+                let read = read_image(&path, Some(&range)).unwrap();
+                std::fs::remove_file(&path).unwrap();
+
+                assert_eq!((read.width, read.height), (image.width, image.height));
+                let tolerance = (range.max - range.min) * relative_error * 0.5 + 1e-6;
+                for (a, b) in image.pixels.iter().zip(&read.pixels) {
+                    assert!(
+                        ((*a - *b).abs()).cmple(tolerance).all(),
+                        "{name}: {a} != {b}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_range() {
+        let image = test_image();
+        let range = AABB {
+            min: Vec3::splat(-10.0),
+            max: Vec3::splat(10.0),
+        };
+        let path = temp_path("shared.png");
+        write_image_in_range(&image, &range, &path, &OutputOptions::default()).unwrap();
+        let read = read_image(&path, Some(&range)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for (a, b) in image.pixels.iter().zip(&read.pixels) {
+            assert!(a.distance(*b) < 20.0 / 65535.0, "{a} != {b}");
+        }
+    }
+
+    #[test]
+    fn test_encode_rgba16() {
+        let mut image = test_image();
+        image.valid[1] = false;
+        let path = temp_path("rgba.png");
+        let range = write_image(&image, &path, &OutputOptions::default()).unwrap();
+        let written = image::open(&path).unwrap().into_rgb16();
+        std::fs::remove_file(&path).unwrap();
+
+        let (data, encoded_range) = encode_rgba16(&image, Quantization::Bounds);
+        assert_eq!(
+            (encoded_range.min, encoded_range.max),
+            (range.min, range.max)
+        );
+        assert_eq!(data.len(), image.pixels.len() * 4);
+        for (rgba, rgb) in data.chunks(4).zip(written.pixels()) {
+            assert_eq!(rgba[..3], rgb.0);
+        }
+        assert_eq!(data[3], u16::MAX);
+        assert_eq!(data[7], 0);
+
+        let png = rgba16_to_png(image.width, image.height, data.clone()).unwrap();
+        let (width, height, decoded) = rgba16_from_png(&png).unwrap();
+        assert_eq!((width, height), (image.width, image.height));
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_uniform_quantization() {
+        let values = [Vec4::new(0.0, 0.0, 1.0, 7.0), Vec4::new(4.0, 1.0, 1.0, 9.0)];
+        let (min, max) = Quantization::Uniform.range(&values, 3);
+        assert_eq!(min, Vec4::new(0.0, 0.0, 1.0, 0.0));
+        assert_eq!(max, Vec4::new(4.0, 4.0, 5.0, 0.0));
+        let (min, max) = Quantization::Bounds.range(&values, 3);
+        assert_eq!(min, Vec4::new(0.0, 0.0, 1.0, 0.0));
+        assert_eq!(max, Vec4::new(4.0, 1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_attribute_channels() {
+        let attribute = AttributeImage {
+            name: "uv".to_string(),
+            width: 2,
+            height: 1,
+            values: AttributeValues::Vec2s(vec![glam::Vec2::ZERO, glam::Vec2::ONE]),
+        };
+        let path = temp_path("uv.f32");
+        write_attribute_image(
+            &attribute,
+            &path,
+            &OutputOptions {
+                bit_depth: BitDepth::Float32,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (width, height, channels, data) = decode_raw(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((width, height, channels), (2, 1, 2));
+        assert_eq!(data, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_scalar_exr() {
+        let attribute = AttributeImage {
+            name: "curvature".to_string(),
+            width: 3,
+            height: 2,
+            values: AttributeValues::Floats((0..6).map(|i| i as f32 * 0.5).collect()),
+        };
+        let path = temp_path("curvature.exr");
+        write_attribute_image(
+            &attribute,
+            &path,
+            &OutputOptions {
+                bit_depth: BitDepth::Float32,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let read = image::open(&path).unwrap().into_rgb32f();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((read.width(), read.height()), (3, 2));
+        let red: Vec<f32> = read.pixels().map(|pixel| pixel.0[0]).collect();
+        assert_eq!(red, [0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+    }
+
+    #[test]
+    fn test_invalid_output() {
+        let image = test_image();
+        let float_png = write_image(
+            &image,
+            temp_path("float.png"),
+            &OutputOptions {
+                bit_depth: BitDepth::Float32,
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            float_png,
+            Err(EncodingError::UnsupportedBitDepth { .. })
+        ));
+        let unknown = write_image(&image, temp_path("image.bmp"), &OutputOptions::default());
+        assert!(matches!(unknown, Err(EncodingError::UnknownFormat)));
+
+        assert!(decode_raw(b"GIMF").is_err());
+        let mut truncated = encode_raw(2, 2, 3, &[0.0; 12]);
+        truncated.pop();
+        assert!(decode_raw(&truncated).is_err());
+    }
+}
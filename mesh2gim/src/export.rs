@@ -0,0 +1,68 @@
+//! Writes meshes to files
+
+use std::io::{self, Write};
+
+use crate::{Attribute, AttributeValues, Mesh};
+
+/// Writes the mesh as an OBJ file. Normals and texture coordinates are written if the mesh has them.
+pub fn write_obj(mesh: &Mesh, mut writer: impl Write) -> io::Result<()> {
+    let find = |name: &str| {
+        mesh.attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+            .map(|attribute| &attribute.values)
+    };
+    let normals = match find(Attribute::NORMAL) {
+        Some(AttributeValues::Vec3s(normals)) => Some(normals),
+        _ => None,
+    };
+    let tex_coords = match find(Attribute::TEX_COORDS) {
+        Some(AttributeValues::Vec2s(tex_coords)) => Some(tex_coords),
+        _ => None,
+    };
+
+    for position in &mesh.positions {
+        writeln!(writer, "v {} {} {}", position.x, position.y, position.z)?;
+    }
+    for tex_coord in tex_coords.into_iter().flatten() {
+        writeln!(writer, "vt {} {}", tex_coord.x, tex_coord.y)?;
+    }
+    for normal in normals.into_iter().flatten() {
+        writeln!(writer, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+    }
+    for triangle in mesh.triangles() {
+        // OBJ indices start at 1
+        let [a, b, c] = triangle.to_array().map(|i| {
+            let i = i + 1;
+            match (tex_coords.is_some(), normals.is_some()) {
+                (true, true) => format!("{i}/{i}/{i}"),
+                (true, false) => format!("{i}/{i}"),
+                (false, true) => format!("{i}//{i}"),
+                (false, false) => format!("{i}"),
+            }
+        });
+        writeln!(writer, "f {a} {b} {c}")?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loaders::{parse_mesh, MeshFormat};
+
+    #[test]
+    fn test_obj_round_trip() {
+        let mesh = parse_mesh(
+            include_bytes!("../fixtures/tetrahedron_textured.obj"),
+            MeshFormat::Obj,
+        )
+        .unwrap();
+        let mut bytes = Vec::new();
+        write_obj(&mesh, &mut bytes).unwrap();
+        let read = parse_mesh(&bytes, MeshFormat::Obj).unwrap();
+        assert_eq!(read.positions, mesh.positions);
+        assert_eq!(read.indices, mesh.indices);
+        assert_eq!(read.attributes.len(), 2);
+    }
+}
@@ -1,11 +1,89 @@
-mod parametrization;
+pub mod adjacency;
+pub mod atlas;
+mod bvh;
+pub mod decimation;
+pub mod distortion;
+mod embedding;
+pub mod encoding;
+pub mod export;
+pub mod loaders;
+pub mod metadata;
+pub mod mipmap;
+pub mod parametrization;
+pub mod reconstruct;
+pub mod repair;
+pub mod resampling;
+mod sampling;
+#[cfg(test)]
+mod test_meshes;
+pub mod video;
+
+use encoding::EncodingError;
 use glam::{UVec2, UVec3, Vec2, Vec3, Vec4};
+use loaders::LoadError;
+use parametrization::{
+    HoleFilling, Initialization, LaplacianWeights, Layout, Octahedral, Parametrization,
+    ParametrizationOptions, SampleGrid,
+};
+use resampling::ResamplingReport;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Mesh2GimError {
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error(transparent)]
+    Encoding(#[from] EncodingError),
+    #[error("could not write the output: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(
+        "invalid geometry image size {width}x{height}, expected at least {min_width}x{min_height}"
+    )]
+    InvalidSize {
+        width: u32,
+        height: u32,
+        min_width: u32,
+        min_height: u32,
+    },
+    #[error("the mesh has no triangles")]
+    EmptyMesh,
+    #[error("vertex index {index} is out of range, the mesh only has {vertex_count} vertices")]
+    InvalidIndex { index: u32, vertex_count: usize },
+    #[error("the bounds of the mesh are degenerate, from {min} to {max}")]
+    DegenerateBounds { min: Vec3, max: Vec3 },
+    #[error("the mesh can not be mapped to a sphere: {0}")]
+    UnsupportedTopology(String),
+    #[error("could not start the threads: {0}")]
+    Threads(#[from] rayon::ThreadPoolBuildError),
+    #[error("the {layout} layout does not support {feature}")]
+    UnsupportedLayout {
+        layout: &'static str,
+        feature: &'static str,
+    },
+    #[error("there is no frame {frame}, the animation has {frames} frames")]
+    InvalidFrame { frame: usize, frames: usize },
+    #[error("frame {frame} does not have the same triangles as the reference frame")]
+    FrameMismatch { frame: usize },
+    #[error("the {charts} charts of the mesh do not fit in a {width}x{height} atlas")]
+    AtlasTooSmall {
+        charts: usize,
+        width: u32,
+        height: u32,
+    },
+}
 
 pub struct Attribute {
     pub name: String,
     pub values: AttributeValues,
 }
 
+impl Attribute {
+    /// Name of the vertex normals attribute
+    pub const NORMAL: &'static str = "normal";
+    /// Name of the texture coordinates attribute
+    pub const TEX_COORDS: &'static str = "tex_coords";
+}
+
 pub enum AttributeValues {
     Floats(Vec<f32>),
     Vec2s(Vec<Vec2>),
@@ -13,6 +91,41 @@ pub enum AttributeValues {
     Vec4s(Vec<Vec4>),
 }
 
+impl AttributeValues {
+    pub fn len(&self) -> usize {
+        match self {
+            AttributeValues::Floats(values) => values.len(),
+            AttributeValues::Vec2s(values) => values.len(),
+            AttributeValues::Vec3s(values) => values.len(),
+            AttributeValues::Vec4s(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of floats per value
+    pub fn components(&self) -> usize {
+        match self {
+            AttributeValues::Floats(_) => 1,
+            AttributeValues::Vec2s(_) => 2,
+            AttributeValues::Vec3s(_) => 3,
+            AttributeValues::Vec4s(_) => 4,
+        }
+    }
+
+    /// Gets a value, padded with zeros
+    pub fn get_vec4(&self, index: usize) -> Vec4 {
+        match self {
+            AttributeValues::Floats(values) => Vec4::new(values[index], 0.0, 0.0, 0.0),
+            AttributeValues::Vec2s(values) => values[index].extend(0.0).extend(0.0),
+            AttributeValues::Vec3s(values) => values[index].extend(0.0),
+            AttributeValues::Vec4s(values) => values[index],
+        }
+    }
+}
+
 pub struct AABB {
     pub min: Vec3,
     pub max: Vec3,
@@ -30,8 +143,10 @@ impl Mesh {
     }
     pub fn triangles(&self) -> impl Iterator<Item = UVec3> + '_ {
         self.indices
-            .chunks_exact(3)
-            .map(|chunk| UVec3::new(chunk[0], chunk[1], chunk[2]))
+            .as_chunks::<3>()
+            .0
+            .iter()
+            .map(|&[a, b, c]| UVec3::new(a, b, c))
     }
     pub fn edges(&self) -> Vec<UVec2> {
         self.triangles()
@@ -60,12 +175,288 @@ pub struct Image {
     pub width: u32,
     pub height: u32,
     pub pixels: Vec<Vec3>,
+    /// Whether each pixel was sampled from the mesh, or filled in because the mesh has a hole there
+    pub valid: Vec<bool>,
+}
+
+/// A per-vertex attribute, sampled at the same positions as the geometry image
+pub struct AttributeImage {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub values: AttributeValues,
+}
+
+/// The positions of the mesh, and every attribute of the mesh, aligned pixel-for-pixel
+pub struct GeometryImage {
+    pub positions: Image,
+    pub attributes: Vec<AttributeImage>,
+    /// How much the area resampling evened out the pixels over the mesh, if it ran
+    pub resampling: Option<ResamplingReport>,
+}
+
+/// Checks that the mesh can be turned into a geometry image. Flat meshes are fine, but the mesh can not
+/// collapse to a single point, and all its positions have to be finite.
+pub fn check_mesh(mesh: &Mesh) -> Result<(), Mesh2GimError> {
+    if mesh.faces_count() == 0 {
+        return Err(Mesh2GimError::EmptyMesh);
+    }
+    let vertex_count = mesh.positions.len();
+    if let Some(&index) = mesh.indices.iter().find(|&&i| i as usize >= vertex_count) {
+        return Err(Mesh2GimError::InvalidIndex {
+            index,
+            vertex_count,
+        });
+    }
+    let AABB { min, max } = mesh.get_bounds();
+    let extent = max - min;
+    if !extent.is_finite() || extent.max_element() <= 0.0 {
+        return Err(Mesh2GimError::DegenerateBounds { min, max });
+    }
+    Ok(())
+}
+
+/// The phase of [`make_geometry_image_with`] that is running, reported through [`GimOptions::progress`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// Decimating the mesh before the parametrization
+    Decimation,
+    /// Collecting the neighbors of every vertex
+    Adjacency,
+    /// A smoothing iteration of the spherical parametrization finished. Stops early when it converges.
+    Smoothing { iteration: u32, max_iterations: u32 },
+    /// An iteration of the area resampling finished
+    Resampling { iteration: u32, max_iterations: u32 },
+    /// Grouping the triangles on the sphere, so the rays can find them quickly
+    Grouping,
+    /// A row of pixels was sampled
+    Rasterization { row: u32, rows: u32 },
+    /// Moving the pixels from the decimated mesh to the full mesh
+    Projection,
+}
+
+/// How a geometry image is made. Starts out with the defaults for the given size.
+///
+/// ```
+/// # use mesh2gim::{GimOptions, parametrization::{EqualAreaOctahedral, HoleFilling}};
+/// let options = GimOptions::new((255, 255))
+///     .iterations(1000)
+///     .layout(&EqualAreaOctahedral)
+///     .hole_filling(HoleFilling::Diffusion)
+///     .progress(|progress| eprintln!("{progress:?}"));
+/// ```
+pub struct GimOptions<'a> {
+    size: (u32, u32),
+    parametrization: ParametrizationOptions,
+    layout: &'static dyn Layout,
+    hole_filling: HoleFilling,
+    resampling_iterations: u32,
+    decimation: usize,
+    progress: Option<Box<dyn Fn(Progress) + Sync + 'a>>,
+    threads: usize,
+}
+
+impl<'a> GimOptions<'a> {
+    pub fn new(size: (u32, u32)) -> Self {
+        GimOptions {
+            size,
+            parametrization: ParametrizationOptions::default(),
+            layout: &Octahedral,
+            hole_filling: HoleFilling::default(),
+            resampling_iterations: 0,
+            decimation: 0,
+            progress: None,
+            threads: 0,
+        }
+    }
+
+    /// The most smoothing iterations of the spherical parametrization
+    pub fn iterations(mut self, max_iterations: u32) -> Self {
+        self.parametrization.max_iterations = max_iterations;
+        self
+    }
+
+    /// Stop smoothing once no vertex moves further than this on the sphere
+    pub fn tolerance(mut self, tolerance: f32) -> Self {
+        self.parametrization.tolerance = tolerance;
+        self
+    }
+
+    pub fn weights(mut self, weights: LaplacianWeights) -> Self {
+        self.parametrization.weights = weights;
+        self
+    }
+
+    /// Where the vertices start on the sphere. Bent meshes need [`Initialization::Tutte`] or
+    /// [`Initialization::Spectral`]. If the one asked for folds on an open mesh, the other is used, and
+    /// [`Parametrization::initialization`] tells which one it was.
+    pub fn initialization(mut self, initialization: Initialization) -> Self {
+        self.parametrization.initialization = initialization;
+        self
+    }
+
+    pub fn layout(mut self, layout: &'static dyn Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn hole_filling(mut self, hole_filling: HoleFilling) -> Self {
+        self.hole_filling = hole_filling;
+        self
+    }
+
+    /// Moves the vertices over the sphere for this many iterations after the smoothing, so that parts of the mesh the
+    /// smoothing squeezed together get more pixels. 0, the default, leaves the parametrization as it is. The mean
+    /// and max area stretch before and after end up in [`Parametrization::resampling`].
+    pub fn area_resampling(mut self, iterations: u32) -> Self {
+        self.resampling_iterations = iterations;
+        self
+    }
+
+    /// Decimates meshes with more triangles than this before the parametrization, which then only takes time for
+    /// this many triangles. The pixels still sample the full mesh. 0, the default, parametrizes every triangle.
+    pub fn decimate(mut self, target_triangles: usize) -> Self {
+        self.decimation = target_triangles;
+        self
+    }
+
+    /// Called at the start of every phase, after every smoothing iteration and after every few rows of pixels.
+    /// Always called from one thread at a time, in order.
+    pub fn progress(mut self, callback: impl Fn(Progress) + Sync + 'a) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// How many threads smooth the parametrization and sample the pixels. 0 uses the global rayon thread pool,
+    /// which has a thread per core unless the application configured it differently.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(callback) = &self.progress {
+            callback(progress);
+        }
+    }
+
+    /// Runs the work on a thread pool with the requested number of threads
+    fn run<R: Send>(&self, work: impl FnOnce() -> R + Send) -> Result<R, Mesh2GimError> {
+        if self.threads == 0 {
+            return Ok(work());
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()?;
+        Ok(pool.install(work))
+    }
 }
 
-pub fn make_geometry_image(mesh: &Mesh, size: (u32, u32)) -> Image {
-    let parametrization = parametrization::spherical_parametrization(mesh, 500);
-    let groups = parametrization::separate_triangle_groups(mesh, &parametrization);
-    parametrization::to_image(mesh, &parametrization, groups, size)
+/// Checks the mesh, and maps it onto the unit sphere. Resamples the mapping by area if the options ask for it.
+pub fn parametrize_mesh(
+    mesh: &Mesh,
+    options: &GimOptions,
+) -> Result<Parametrization, Mesh2GimError> {
+    check_mesh(mesh)?;
+    options.run(|| {
+        let progress = |progress| options.report(progress);
+        let mut parametrization = parametrization::spherical_parametrization_with_progress(
+            mesh,
+            &options.parametrization,
+            &progress,
+        );
+        if options.resampling_iterations > 0 {
+            parametrization.resampling = Some(resampling::resample_by_area_with_progress(
+                mesh,
+                &mut parametrization.vertices,
+                options.resampling_iterations,
+                &progress,
+            ));
+        }
+        parametrization
+    })
+}
+
+/// Finds where every pixel of the image lands on the mesh, with the layout and hole filling of the options
+pub fn sample_mesh(
+    mesh: &Mesh,
+    parametrization: &[Vec3],
+    options: &GimOptions,
+) -> Result<SampleGrid, Mesh2GimError> {
+    options.run(|| {
+        parametrization::sample_grid_with_progress(
+            mesh,
+            parametrization,
+            options.size,
+            options.hole_filling,
+            options.layout,
+            &|progress| options.report(progress),
+        )
+    })?
+}
+
+/// The mesh decimated to the triangle count of [`GimOptions::decimate`], to parametrize instead of the mesh. None
+/// if the options do not decimate, or the mesh already has few enough triangles.
+pub fn decimate_for_parametrization(
+    mesh: &Mesh,
+    options: &GimOptions,
+) -> Result<Option<Mesh>, Mesh2GimError> {
+    check_mesh(mesh)?;
+    if options.decimation == 0 || mesh.faces_count() <= options.decimation {
+        return Ok(None);
+    }
+    options.report(Progress::Decimation);
+    Ok(Some(decimation::decimate_mesh(mesh, options.decimation)))
+}
+
+/// Moves the pixels of a grid that was sampled on the decimated mesh to the closest points of the full mesh
+pub fn sample_full_mesh(
+    grid: &SampleGrid,
+    decimated: &Mesh,
+    mesh: &Mesh,
+    options: &GimOptions,
+) -> Result<SampleGrid, Mesh2GimError> {
+    options.report(Progress::Projection);
+    options.run(|| grid.project_onto(decimated, mesh))
+}
+
+/// Makes a geometry image with the default options
+pub fn make_geometry_image(mesh: &Mesh, size: (u32, u32)) -> Result<GeometryImage, Mesh2GimError> {
+    make_geometry_image_with(mesh, &GimOptions::new(size))
+}
+
+pub fn make_geometry_image_with(
+    mesh: &Mesh,
+    options: &GimOptions,
+) -> Result<GeometryImage, Mesh2GimError> {
+    // Check the size before spending time on the parametrization
+    parametrization::check_size(options.size, options.layout)?;
+    let decimated = decimate_for_parametrization(mesh, options)?;
+    let surface = decimated.as_ref().unwrap_or(mesh);
+    let parametrization = parametrize_mesh(surface, options)?;
+    let mut grid = sample_mesh(surface, &parametrization.vertices, options)?;
+    if let Some(decimated) = &decimated {
+        grid = sample_full_mesh(&grid, decimated, mesh, options)?;
+    }
+    Ok(GeometryImage {
+        resampling: parametrization.resampling,
+        ..GeometryImage::from_grid(mesh, &grid)
+    })
+}
+
+impl GeometryImage {
+    /// Bakes the positions and every attribute of the mesh into images
+    pub fn from_grid(mesh: &Mesh, grid: &SampleGrid) -> Self {
+        GeometryImage {
+            positions: grid.to_image(&mesh.positions),
+            attributes: mesh
+                .attributes
+                .iter()
+                .map(|attribute| grid.to_attribute_image(attribute))
+                .collect(),
+            resampling: None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -83,10 +474,122 @@ mod tests {
             attributes: vec![],
             indices: vec![0, 1, 2],
         };
-        let size = (256, 256);
-        let image = make_geometry_image(&mesh, size);
-        assert_eq!(image.width, size.0);
-        assert_eq!(image.height, size.1);
-        assert_eq!(image.pixels.len(), (size.0 * size.1 * 4) as usize);
+        for size in [(255, 255), (256, 256), (64, 32)] {
+            let image = make_geometry_image(&mesh, size).unwrap().positions;
+            assert_eq!(image.width, size.0);
+            assert_eq!(image.height, size.1);
+            assert_eq!(image.pixels.len(), (size.0 * size.1) as usize);
+        }
+    }
+
+    #[test]
+    fn test_progress() {
+        let mesh = test_meshes::subdivided_octahedron(2, Vec3::new(1.0, 2.0, 3.0));
+        let phases = std::sync::Mutex::new(Vec::new());
+        let options = GimOptions::new((17, 9))
+            .iterations(3)
+            .tolerance(0.0)
+            .progress(|progress| phases.lock().unwrap().push(progress));
+        let image = make_geometry_image_with(&mesh, &options).unwrap();
+        assert_eq!((image.positions.width, image.positions.height), (17, 9));
+        drop(options);
+
+        let phases = phases.into_inner().unwrap();
+        assert_eq!(phases[0], Progress::Adjacency);
+        assert_eq!(
+            phases[3],
+            Progress::Smoothing {
+                iteration: 3,
+                max_iterations: 3
+            }
+        );
+        assert_eq!(phases[4], Progress::Grouping);
+        let rows: Vec<u32> = phases[5..]
+            .iter()
+            .map(|progress| match progress {
+                Progress::Rasterization { row, rows: 9 } => *row,
+                _ => panic!("unexpected {progress:?}"),
+            })
+            .collect();
+        assert!(rows.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(rows.last(), Some(&9));
+    }
+
+    #[test]
+    fn test_threads_do_not_change_the_result() {
+        let mesh = test_meshes::subdivided_octahedron(3, Vec3::new(1.0, 2.0, 3.0));
+        let image = |threads| {
+            let options = GimOptions::new((33, 33)).threads(threads);
+            make_geometry_image_with(&mesh, &options).unwrap().positions
+        };
+        let single = image(1);
+        for threads in [3, 8] {
+            assert!(image(threads).pixels == single.pixels);
+        }
+    }
+
+    #[test]
+    fn test_area_resampling_is_reported() {
+        let mesh = test_meshes::subdivided_octahedron(2, Vec3::new(4.0, 1.0, 1.0));
+        let image = make_geometry_image(&mesh, (17, 17)).unwrap();
+        assert!(image.resampling.is_none());
+        let options = GimOptions::new((17, 17)).area_resampling(20);
+        let report = make_geometry_image_with(&mesh, &options)
+            .unwrap()
+            .resampling
+            .unwrap();
+        assert_eq!(report.iterations, 20);
+        assert!(report.mean_area_stretch_after < report.mean_area_stretch_before);
+    }
+
+    #[test]
+    fn test_decimation_samples_the_full_mesh() {
+        let sphere = test_meshes::subdivided_octahedron(5, Vec3::ONE);
+        let options = GimOptions::new((33, 33)).decimate(200);
+        let decimated = decimate_for_parametrization(&sphere, &options)
+            .unwrap()
+            .unwrap();
+        assert!(decimated.faces_count() <= 200);
+        // The pixels land on the sphere, not on the flat triangles of the decimated mesh
+        let deviation = |image: &Image| {
+            image
+                .pixels
+                .iter()
+                .map(|pixel| (pixel.length() - 1.0).abs())
+                .fold(0.0f32, f32::max)
+        };
+        let image = make_geometry_image_with(&sphere, &options).unwrap();
+        assert!(deviation(&image.positions) < 2e-3);
+        let coarse = make_geometry_image(&decimated, (33, 33)).unwrap();
+        assert!(deviation(&coarse.positions) > 1e-2);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let triangle = |positions: Vec<Vec3>, indices: Vec<u32>| Mesh {
+            positions,
+            attributes: vec![],
+            indices,
+        };
+        let positions = vec![Vec3::ZERO, Vec3::X, Vec3::Y];
+        let valid = triangle(positions.clone(), vec![0, 1, 2]);
+        for size in [(0, 0), (1, 1), (255, 1)] {
+            assert!(matches!(
+                make_geometry_image(&valid, size),
+                Err(Mesh2GimError::InvalidSize { .. })
+            ));
+        }
+        assert!(matches!(
+            make_geometry_image(&triangle(positions.clone(), vec![]), (15, 15)),
+            Err(Mesh2GimError::EmptyMesh)
+        ));
+        assert!(matches!(
+            make_geometry_image(&triangle(positions, vec![0, 1, 3]), (15, 15)),
+            Err(Mesh2GimError::InvalidIndex { index: 3, .. })
+        ));
+        assert!(matches!(
+            make_geometry_image(&triangle(vec![Vec3::ONE; 3], vec![0, 1, 2]), (15, 15)),
+            Err(Mesh2GimError::DegenerateBounds { .. })
+        ));
     }
 }
@@ -0,0 +1,162 @@
+//! Turns mesh files into a [`Mesh`]
+
+mod gltf;
+mod obj;
+mod ply;
+mod stl;
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::Mesh;
+
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("could not read the mesh file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unknown mesh format, expected an .obj, .stl, .ply, .gltf or .glb file")]
+    UnknownFormat,
+    #[error("invalid OBJ file: {0}")]
+    Obj(#[from] ::obj::ObjError),
+    #[error("invalid STL file: {0}")]
+    Stl(std::io::Error),
+    #[error("invalid PLY file: {0}")]
+    Ply(String),
+    #[error("invalid glTF file: {0}")]
+    Gltf(#[from] ::gltf::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshFormat {
+    Obj,
+    Stl,
+    Ply,
+    /// Both .gltf and .glb files
+    Gltf,
+}
+
+impl MeshFormat {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "obj" => Some(MeshFormat::Obj),
+            "stl" => Some(MeshFormat::Stl),
+            "ply" => Some(MeshFormat::Ply),
+            "gltf" | "glb" => Some(MeshFormat::Gltf),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from the start of the file
+    pub fn from_magic_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"glTF") {
+            return Some(MeshFormat::Gltf);
+        }
+        if bytes.starts_with(b"ply") {
+            return Some(MeshFormat::Ply);
+        }
+        // Binary STL files have an 80 byte header, a triangle count and 50 bytes per triangle
+        if bytes.len() >= 84 {
+            let triangle_count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]);
+            if bytes.len() as u64 == 84 + 50 * triangle_count as u64 {
+                return Some(MeshFormat::Stl);
+            }
+        }
+        let text = std::str::from_utf8(&bytes[..bytes.len().min(1024)])
+            .unwrap_or_default()
+            .trim_start();
+        if text.starts_with("solid") {
+            return Some(MeshFormat::Stl);
+        }
+        if text.starts_with('{') {
+            return Some(MeshFormat::Gltf);
+        }
+        let is_obj_line = |line: &str| {
+            ["v ", "vn ", "vt ", "f ", "o ", "g ", "mtllib "]
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+        };
+        if text.lines().any(is_obj_line) {
+            return Some(MeshFormat::Obj);
+        }
+        None
+    }
+}
+
+/// Loads a mesh file. The format is detected by the file extension, or by the content of the file.
+pub fn load_mesh(path: impl AsRef<Path>) -> Result<Mesh, LoadError> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)?;
+    let format = MeshFormat::from_extension(path)
+        .or_else(|| MeshFormat::from_magic_bytes(&bytes))
+        .ok_or(LoadError::UnknownFormat)?;
+    match format {
+        // External glTF buffers are relative to the file
+        MeshFormat::Gltf => gltf::parse_gltf(&bytes, path.parent()),
+        format => parse_mesh(&bytes, format),
+    }
+}
+
+/// Reads the mesh file and every other file that [`load_mesh`] would read with it, which are the external buffers of
+/// glTF files. The loaded mesh only changes if one of them does.
+pub fn read_mesh_files(path: impl AsRef<Path>) -> Result<Vec<Vec<u8>>, LoadError> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)?;
+    let format = MeshFormat::from_extension(path).or_else(|| MeshFormat::from_magic_bytes(&bytes));
+    let buffers = match format {
+        Some(MeshFormat::Gltf) => gltf::external_buffers(&bytes, path.parent())?,
+        _ => Vec::new(),
+    };
+    Ok(std::iter::once(bytes).chain(buffers).collect())
+}
+
+pub fn parse_mesh(bytes: &[u8], format: MeshFormat) -> Result<Mesh, LoadError> {
+    match format {
+        MeshFormat::Obj => obj::parse_obj(bytes),
+        MeshFormat::Stl => stl::parse_stl(bytes),
+        MeshFormat::Ply => ply::parse_ply(bytes),
+        MeshFormat::Gltf => gltf::parse_gltf(bytes, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        let detect = |bytes: &[u8]| MeshFormat::from_magic_bytes(bytes);
+        assert_eq!(
+            detect(include_bytes!("../fixtures/tetrahedron.obj")),
+            Some(MeshFormat::Obj)
+        );
+        assert_eq!(
+            detect(include_bytes!("../fixtures/tetrahedron_ascii.stl")),
+            Some(MeshFormat::Stl)
+        );
+        assert_eq!(
+            detect(include_bytes!("../fixtures/tetrahedron_binary.stl")),
+            Some(MeshFormat::Stl)
+        );
+        assert_eq!(
+            detect(include_bytes!("../fixtures/tetrahedron_ascii.ply")),
+            Some(MeshFormat::Ply)
+        );
+        assert_eq!(
+            detect(include_bytes!("../fixtures/tetrahedron.glb")),
+            Some(MeshFormat::Gltf)
+        );
+        assert_eq!(
+            detect(include_bytes!("../fixtures/tetrahedron.gltf")),
+            Some(MeshFormat::Gltf)
+        );
+        assert_eq!(detect(b"\x00\x01\x02"), None);
+
+        assert_eq!(
+            MeshFormat::from_extension(Path::new("bunny.GLB")),
+            Some(MeshFormat::Gltf)
+        );
+        assert_eq!(MeshFormat::from_extension(Path::new("bunny")), None);
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+
+use glam::{Mat3, Mat4, Vec2, Vec3};
+
+use super::LoadError;
+use crate::{Attribute, AttributeValues, Mesh};
+
+/// Collects the triangles of every primitive into one mesh
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Vec3>,
+    indices: Vec<u32>,
+    /// Only kept if every primitive has them
+    normals: Option<Vec<Vec3>>,
+    tex_coords: Option<Vec<Vec2>>,
+}
+
+/// Parses .gltf and .glb files. Every triangle primitive in the default scene is merged into a single mesh,
+/// with the node transforms applied. External buffers are loaded relative to `base`.
+pub fn parse_gltf(bytes: &[u8], base: Option<&Path>) -> Result<Mesh, LoadError> {
+    let ::gltf::Gltf { document, blob } = ::gltf::Gltf::from_slice(bytes)?;
+    let buffers = ::gltf::import_buffers(&document, base, blob)?;
+
+    let mut builder = MeshBuilder {
+        normals: Some(Vec::new()),
+        tex_coords: Some(Vec::new()),
+        ..Default::default()
+    };
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            for node in scene.nodes() {
+                add_node(&mut builder, &node, Mat4::IDENTITY, &buffers);
+            }
+        }
+        None => {
+            for mesh in document.meshes() {
+                add_mesh(&mut builder, &mesh, Mat4::IDENTITY, &buffers);
+            }
+        }
+    }
+
+    let mut attributes = Vec::new();
+    if let Some(normals) = builder.normals {
+        attributes.push(Attribute {
+            name: Attribute::NORMAL.to_string(),
+            values: AttributeValues::Vec3s(normals),
+        });
+    }
+    if let Some(tex_coords) = builder.tex_coords {
+        attributes.push(Attribute {
+            name: Attribute::TEX_COORDS.to_string(),
+            values: AttributeValues::Vec2s(tex_coords),
+        });
+    }
+    Ok(Mesh {
+        positions: builder.positions,
+        attributes,
+        indices: builder.indices,
+    })
+}
+
+/// The contents of the buffers that a .gltf file loads from other files, relative to `base`. Buffers in the file
+/// itself are left out.
+pub fn external_buffers(bytes: &[u8], base: Option<&Path>) -> Result<Vec<Vec<u8>>, LoadError> {
+    let ::gltf::Gltf { document, blob } = ::gltf::Gltf::from_slice(bytes)?;
+    let buffers = ::gltf::import_buffers(&document, base, blob)?;
+    Ok(document
+        .buffers()
+        .zip(buffers)
+        .filter(|(buffer, _)| {
+            matches!(buffer.source(), ::gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:"))
+        })
+        .map(|(_, data)| data.0)
+        .collect())
+}
+
+fn add_node(
+    builder: &mut MeshBuilder,
+    node: &::gltf::Node,
+    parent_transform: Mat4,
+    buffers: &[::gltf::buffer::Data],
+) {
+    let transform = parent_transform * Mat4::from_cols_array_2d(&node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        add_mesh(builder, &mesh, transform, buffers);
+    }
+    for child in node.children() {
+        add_node(builder, &child, transform, buffers);
+    }
+}
+
+fn add_mesh(
+    builder: &mut MeshBuilder,
+    mesh: &::gltf::Mesh,
+    transform: Mat4,
+    buffers: &[::gltf::buffer::Data],
+) {
+    let normal_transform = Mat3::from_mat4(transform).inverse().transpose();
+    for primitive in mesh.primitives() {
+        if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+            continue;
+        }
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        let offset = builder.positions.len() as u32;
+        builder
+            .positions
+            .extend(positions.map(|position| transform.transform_point3(Vec3::from(position))));
+        let vertex_count = builder.positions.len() - offset as usize;
+
+        match reader.read_indices() {
+            Some(indices) => builder
+                .indices
+                .extend(indices.into_u32().map(|index| index + offset)),
+            None => builder.indices.extend(offset..offset + vertex_count as u32),
+        }
+
+        match (&mut builder.normals, reader.read_normals()) {
+            (Some(normals), Some(primitive_normals)) => normals.extend(
+                primitive_normals
+                    .map(|normal| (normal_transform * Vec3::from(normal)).normalize_or_zero()),
+            ),
+            _ => builder.normals = None,
+        }
+        match (&mut builder.tex_coords, reader.read_tex_coords(0)) {
+            (Some(tex_coords), Some(primitive_tex_coords)) => {
+                tex_coords.extend(primitive_tex_coords.into_f32().map(Vec2::from))
+            }
+            _ => builder.tex_coords = None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_tetrahedron(mesh: &Mesh) {
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.faces_count(), 4);
+        let names: Vec<_> = mesh.attributes.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, [Attribute::NORMAL, Attribute::TEX_COORDS]);
+        // The node moves the tetrahedron up by 2
+        let bounds = mesh.get_bounds();
+        assert!((bounds.min.y - 1.0).abs() < 1e-5);
+        assert!((bounds.max.y - 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_parse_glb() {
+        let mesh = parse_gltf(include_bytes!("../../fixtures/tetrahedron.glb"), None).unwrap();
+        assert_tetrahedron(&mesh);
+    }
+
+    #[test]
+    fn test_parse_gltf_with_embedded_buffer() {
+        let mesh = parse_gltf(include_bytes!("../../fixtures/tetrahedron.gltf"), None).unwrap();
+        assert_tetrahedron(&mesh);
+    }
+}
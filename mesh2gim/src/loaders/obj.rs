@@ -0,0 +1,78 @@
+use glam::Vec2;
+
+use super::LoadError;
+use crate::{Attribute, AttributeValues, Mesh};
+
+pub fn parse_obj(bytes: &[u8]) -> Result<Mesh, LoadError> {
+    let raw = ::obj::raw::parse_obj(bytes)?;
+    if raw.normals.is_empty() {
+        let obj: ::obj::Obj<::obj::Position, u32> = ::obj::Obj::new(raw)?;
+        let positions = obj.vertices.iter().map(|v| v.position.into()).collect();
+        Ok(Mesh {
+            positions,
+            attributes: vec![],
+            indices: obj.indices,
+        })
+    } else if raw.tex_coords.is_empty() {
+        // Normals but no texture coordinates
+        let obj: ::obj::Obj<::obj::Vertex, u32> = ::obj::Obj::new(raw)?;
+        let positions = obj.vertices.iter().map(|v| v.position.into()).collect();
+        let normals = obj.vertices.iter().map(|v| v.normal.into()).collect();
+        Ok(Mesh {
+            positions,
+            attributes: vec![Attribute {
+                name: Attribute::NORMAL.to_string(),
+                values: AttributeValues::Vec3s(normals),
+            }],
+            indices: obj.indices,
+        })
+    } else {
+        // Normals and texture coordinates
+        let obj: ::obj::Obj<::obj::TexturedVertex, u32> = ::obj::Obj::new(raw)?;
+        let positions = obj.vertices.iter().map(|v| v.position.into()).collect();
+        let normals = obj.vertices.iter().map(|v| v.normal.into()).collect();
+        let tex_coords = obj
+            .vertices
+            .iter()
+            .map(|v| Vec2::new(v.texture[0], v.texture[1]))
+            .collect();
+        Ok(Mesh {
+            positions,
+            attributes: vec![
+                Attribute {
+                    name: Attribute::TEX_COORDS.to_string(),
+                    values: AttributeValues::Vec2s(tex_coords),
+                },
+                Attribute {
+                    name: Attribute::NORMAL.to_string(),
+                    values: AttributeValues::Vec3s(normals),
+                },
+            ],
+            indices: obj.indices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_obj() {
+        let mesh = parse_obj(include_bytes!("../../fixtures/tetrahedron.obj")).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.faces_count(), 4);
+        assert!(mesh.attributes.is_empty());
+    }
+
+    #[test]
+    fn test_parse_obj_with_attributes() {
+        let mesh = parse_obj(include_bytes!("../../fixtures/tetrahedron_textured.obj")).unwrap();
+        assert_eq!(mesh.faces_count(), 4);
+        let names: Vec<_> = mesh.attributes.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, [Attribute::TEX_COORDS, Attribute::NORMAL]);
+        for attribute in &mesh.attributes {
+            assert_eq!(attribute.values.len(), mesh.positions.len());
+        }
+    }
+}
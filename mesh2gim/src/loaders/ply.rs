@@ -0,0 +1,324 @@
+use glam::{Vec2, Vec3};
+
+use super::LoadError;
+use crate::{Attribute, AttributeValues, Mesh};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self, LoadError> {
+        match name {
+            "char" | "int8" => Ok(ScalarType::I8),
+            "uchar" | "uint8" => Ok(ScalarType::U8),
+            "short" | "int16" => Ok(ScalarType::I16),
+            "ushort" | "uint16" => Ok(ScalarType::U16),
+            "int" | "int32" => Ok(ScalarType::I32),
+            "uint" | "uint32" => Ok(ScalarType::U32),
+            "float" | "float32" => Ok(ScalarType::F32),
+            "double" | "float64" => Ok(ScalarType::F64),
+            _ => Err(LoadError::Ply(format!("unknown property type {name}"))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+}
+
+enum PropertyType {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+struct Property {
+    name: String,
+    property_type: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads the values of the body, one after the other
+struct BodyReader<'a> {
+    encoding: Encoding,
+    bytes: &'a [u8],
+    tokens: std::str::SplitAsciiWhitespace<'a>,
+}
+
+impl BodyReader<'_> {
+    fn read(&mut self, scalar_type: ScalarType) -> Result<f64, LoadError> {
+        if self.encoding == Encoding::Ascii {
+            let token = self
+                .tokens
+                .next()
+                .ok_or_else(|| LoadError::Ply("unexpected end of file".to_string()))?;
+            return token
+                .parse()
+                .map_err(|_| LoadError::Ply(format!("invalid number {token}")));
+        }
+
+        let size = scalar_type.size();
+        if self.bytes.len() < size {
+            return Err(LoadError::Ply("unexpected end of file".to_string()));
+        }
+        let (value, rest) = self.bytes.split_at(size);
+        self.bytes = rest;
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(value);
+        if self.encoding == Encoding::BinaryBigEndian {
+            buffer[..size].reverse();
+        }
+        let [b0, b1, b2, b3, ..] = buffer;
+        Ok(match scalar_type {
+            ScalarType::I8 => b0 as i8 as f64,
+            ScalarType::U8 => b0 as f64,
+            ScalarType::I16 => i16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::U16 => u16::from_le_bytes([b0, b1]) as f64,
+            ScalarType::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+            ScalarType::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+}
+
+/// Parses ASCII and binary PLY files, with optional normals and texture coordinates.
+/// Polygons are triangulated as triangle fans.
+pub fn parse_ply(bytes: &[u8]) -> Result<Mesh, LoadError> {
+    let (encoding, elements, body) = parse_header(bytes)?;
+    let mut reader = BodyReader {
+        encoding,
+        bytes: body,
+        tokens: if encoding == Encoding::Ascii {
+            std::str::from_utf8(body)
+                .map_err(|_| LoadError::Ply("ASCII body is not valid UTF-8".to_string()))?
+                .split_ascii_whitespace()
+        } else {
+            "".split_ascii_whitespace()
+        },
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut tex_coords = Vec::new();
+    let mut indices = Vec::new();
+    let mut has_normals = false;
+    let mut has_tex_coords = false;
+    for element in &elements {
+        let find = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|property| names.contains(&property.name.as_str()))
+        };
+        let position_properties = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let normal_properties = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let tex_coord_properties = [
+            find(&["u", "s", "texture_u", "texture_s"]),
+            find(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let index_property = find(&["vertex_indices", "vertex_index"]);
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        if is_vertex {
+            if position_properties.iter().any(|p| p.is_none()) {
+                return Err(LoadError::Ply("vertices need x, y and z".to_string()));
+            }
+            has_normals = normal_properties.iter().all(|p| p.is_some());
+            has_tex_coords = tex_coord_properties.iter().all(|p| p.is_some());
+        }
+
+        let mut values = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            let mut face = Vec::new();
+            for (i, property) in element.properties.iter().enumerate() {
+                match property.property_type {
+                    PropertyType::Scalar(scalar_type) => values[i] = reader.read(scalar_type)?,
+                    PropertyType::List { count, item } => {
+                        let count = reader.read(count)? as usize;
+                        for _ in 0..count {
+                            let value = reader.read(item)?;
+                            if is_face && index_property == Some(i) {
+                                face.push(value as u32);
+                            }
+                        }
+                    }
+                }
+            }
+            let vec3 = |properties: [Option<usize>; 3]| {
+                Vec3::from(properties.map(|p| p.map(|p| values[p] as f32).unwrap_or_default()))
+            };
+            if is_vertex {
+                positions.push(vec3(position_properties));
+                normals.push(vec3(normal_properties));
+                let [u, v] = tex_coord_properties.map(|p| p.map(|p| values[p] as f32));
+                tex_coords.push(Vec2::new(u.unwrap_or_default(), v.unwrap_or_default()));
+            }
+            if is_face {
+                for i in 1..face.len().saturating_sub(1) {
+                    indices.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+                }
+            }
+        }
+    }
+
+    if let Some(index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+        return Err(LoadError::Ply(format!(
+            "vertex index {index} is out of range"
+        )));
+    }
+    let mut attributes = Vec::new();
+    if has_normals {
+        attributes.push(Attribute {
+            name: Attribute::NORMAL.to_string(),
+            values: AttributeValues::Vec3s(normals),
+        });
+    }
+    if has_tex_coords {
+        attributes.push(Attribute {
+            name: Attribute::TEX_COORDS.to_string(),
+            values: AttributeValues::Vec2s(tex_coords),
+        });
+    }
+    Ok(Mesh {
+        positions,
+        attributes,
+        indices,
+    })
+}
+
+/// Returns the encoding, the elements and the remaining bytes after the header
+fn parse_header(bytes: &[u8]) -> Result<(Encoding, Vec<Element>, &[u8]), LoadError> {
+    const END_HEADER: &[u8] = b"end_header";
+    let header_end = bytes
+        .windows(END_HEADER.len())
+        .position(|window| window == END_HEADER)
+        .ok_or_else(|| LoadError::Ply("missing end_header".to_string()))?;
+    let mut body_start = header_end + END_HEADER.len();
+    if bytes.get(body_start) == Some(&b'\r') {
+        body_start += 1;
+    }
+    if bytes.get(body_start) == Some(&b'\n') {
+        body_start += 1;
+    }
+    let header = std::str::from_utf8(&bytes[..header_end])
+        .map_err(|_| LoadError::Ply("header is not valid UTF-8".to_string()))?;
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(LoadError::Ply("missing ply magic number".to_string()));
+    }
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["format", format, _version] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::BinaryLittleEndian,
+                    "binary_big_endian" => Encoding::BinaryBigEndian,
+                    _ => return Err(LoadError::Ply(format!("unknown format {format}"))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| LoadError::Ply(format!("invalid element count {count}")))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| LoadError::Ply("property before element".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    property_type: PropertyType::List {
+                        count: ScalarType::parse(count)?,
+                        item: ScalarType::parse(item)?,
+                    },
+                });
+            }
+            ["property", scalar_type, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| LoadError::Ply("property before element".to_string()))?;
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    property_type: PropertyType::Scalar(ScalarType::parse(scalar_type)?),
+                });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(LoadError::Ply(format!("unexpected header line {line}"))),
+        }
+    }
+    let encoding = encoding.ok_or_else(|| LoadError::Ply("missing format".to_string()))?;
+    Ok((encoding, elements, &bytes[body_start..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ascii_ply() {
+        let mesh = parse_ply(include_bytes!("../../fixtures/tetrahedron_ascii.ply")).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.faces_count(), 4);
+        let names: Vec<_> = mesh.attributes.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, [Attribute::NORMAL, Attribute::TEX_COORDS]);
+        let AttributeValues::Vec2s(tex_coords) = &mesh.attributes[1].values else {
+            panic!("Expected texture coordinates");
+        };
+        assert_eq!(tex_coords[3], Vec2::new(0.5, 1.0));
+    }
+
+    #[test]
+    fn test_parse_binary_ply() {
+        // A cube made out of quads, with an extra element that must be skipped
+        let little_endian = parse_ply(include_bytes!(
+            "../../fixtures/cube_binary_little_endian.ply"
+        ))
+        .unwrap();
+        let big_endian =
+            parse_ply(include_bytes!("../../fixtures/cube_binary_big_endian.ply")).unwrap();
+        for mesh in [little_endian, big_endian] {
+            assert_eq!(mesh.positions.len(), 8);
+            assert_eq!(mesh.faces_count(), 12);
+            assert!(mesh.attributes.is_empty());
+            assert_eq!(mesh.positions[7], Vec3::ONE);
+        }
+    }
+
+    #[test]
+    fn test_invalid_ply() {
+        assert!(parse_ply(b"ply\nformat ascii 1.0\nelement vertex 1\nend_header\n").is_err());
+        assert!(parse_ply(b"ply\nformat ascii 1.0\n").is_err());
+    }
+}
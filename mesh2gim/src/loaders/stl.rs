@@ -0,0 +1,68 @@
+use std::io::Cursor;
+
+use glam::Vec3;
+
+use super::LoadError;
+use crate::{Attribute, AttributeValues, Mesh};
+
+/// Parses binary and ASCII STL files. STL only has face normals, which get averaged into vertex normals.
+pub fn parse_stl(bytes: &[u8]) -> Result<Mesh, LoadError> {
+    let stl = stl_io::read_stl(&mut Cursor::new(bytes)).map_err(LoadError::Stl)?;
+    let positions: Vec<Vec3> = stl.vertices.iter().map(|v| Vec3::from(v.0)).collect();
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    let mut indices = Vec::with_capacity(stl.faces.len() * 3);
+    for face in &stl.faces {
+        let [a, b, c] = face.vertices.map(|v| positions[v]);
+        // Weighted by the area of the face
+        let mut normal = (b - a).cross(c - a);
+        if normal == Vec3::ZERO {
+            normal = Vec3::from(face.normal.0);
+        }
+        for vertex in face.vertices {
+            normals[vertex] += normal;
+            indices.push(vertex as u32);
+        }
+    }
+    for normal in &mut normals {
+        *normal = normal.normalize_or_zero();
+    }
+    Ok(Mesh {
+        positions,
+        attributes: vec![Attribute {
+            name: Attribute::NORMAL.to_string(),
+            values: AttributeValues::Vec3s(normals),
+        }],
+        indices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_tetrahedron(mesh: &Mesh) {
+        // Identical vertices get merged
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.faces_count(), 4);
+        let AttributeValues::Vec3s(normals) = &mesh.attributes[0].values else {
+            panic!("Expected normals");
+        };
+        for (position, normal) in mesh.positions.iter().zip(normals) {
+            // The tetrahedron is centered around the origin, so the normals point outwards
+            assert!(position.dot(*normal) > 0.0);
+            assert!((normal.length() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_parse_binary_stl() {
+        let mesh = parse_stl(include_bytes!("../../fixtures/tetrahedron_binary.stl")).unwrap();
+        assert_tetrahedron(&mesh);
+    }
+
+    #[test]
+    fn test_parse_ascii_stl() {
+        let mesh = parse_stl(include_bytes!("../../fixtures/tetrahedron_ascii.stl")).unwrap();
+        assert_tetrahedron(&mesh);
+    }
+}
@@ -1,129 +1,818 @@
-use clap::Parser;
-use glam::Vec2;
+mod batch;
+
+use batch::BatchArgs;
+use clap::{Args, Parser, Subcommand};
 use image::{DynamicImage, ImageBuffer};
-use mesh2gim::{make_geometry_image, Attribute, AttributeValues, Mesh, AABB};
+use mesh2gim::{
+    atlas::{make_geometry_image_atlas, AtlasOptions},
+    decimate_for_parametrization,
+    distortion::{analyze_distortion, write_heat_map, DistortionReport},
+    encoding::{
+        read_image, write_attribute_image, write_image, write_image_in_range, BitDepth,
+        EncodingError, ImageFormat, OutputOptions, Quantization,
+    },
+    export::write_obj,
+    loaders::{load_mesh, MeshFormat},
+    metadata::{
+        AttributeMetadata, Metadata, MipMetadata, ParameterMetadata, RectMetadata, SourceMetadata,
+        ATLAS_LAYOUT, METADATA_VERSION,
+    },
+    mipmap::{mip_chain, pack_mip_chain, MipRect},
+    parametrization::{
+        check_size, layout_by_name, HoleFilling, Initialization, LaplacianWeights, Layout,
+        Parametrization, LAYOUTS,
+    },
+    parametrize_mesh,
+    reconstruct::{compare_meshes, reconstruct_atlas, reconstruct_mesh},
+    repair::{repair_mesh, RepairOptions, RepairReport},
+    sample_full_mesh, sample_mesh,
+    video::make_geometry_video,
+    AttributeImage, GeometryImage, GimOptions, Image, Mesh2GimError, Progress, AABB,
+};
 use miniserde::{json, Deserialize, Serialize};
-use std::{fs::File, io::BufReader};
+use std::{
+    fmt,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+};
+
+/// Points sampled on each surface when comparing the reconstructed mesh to the input
+const COMPARE_SAMPLES: usize = 100_000;
+/// The heat map saturates at this base 2 logarithm of the area distortion, so 4 times too large or too small
+const HEAT_MAP_LIMIT: f32 = 2.0;
 
 #[derive(Parser)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
-    /// Specify the path of the geometry image that will be generated
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    convert: ConvertArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert every mesh in a directory, or the jobs listed in a JSON manifest, at one or more sizes. Outputs that
+    /// are up to date are skipped.
+    Batch(BatchArgs),
+    /// Convert an animation with a mesh file per frame into a numbered sequence of geometry images. Every frame is
+    /// sampled with the mapping of the reference frame, and quantized with the bounds of all frames.
+    Video(VideoArgs),
+}
+
+/// Converts one mesh. Options given twice take the last value, so batch jobs can override the shared options.
+#[derive(Parser)]
+#[command(args_override_self = true)]
+struct ConvertArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+
+    /// Optionally write a black and white image that shows which pixels could not be sampled from the mesh
+    #[clap(short, long)]
+    mask: Option<String>,
+
+    /// Vertices closer than this fraction of the bounding box diagonal are welded together
+    #[clap(long, default_value_t = 1e-6)]
+    weld_tolerance: f32,
+
+    /// Refuse meshes that can not be mapped to a sphere, instead of only warning about them
+    #[clap(long)]
+    strict: bool,
+
+    #[command(flatten)]
+    mapping: MappingArgs,
+
+    /// Cut the mesh into charts that are packed into an atlas, instead of mapping it to a sphere.
+    /// Works for meshes of any genus.
+    #[clap(long)]
+    atlas: bool,
+
+    /// Decode the written geometry image back into a mesh, save it as an OBJ file, and print how far it is from
+    /// the input mesh
+    #[clap(short, long)]
+    reconstruct: Option<String>,
+
+    /// Write a JSON report of how much the spherical parametrization distorts the triangles
+    #[clap(long, conflicts_with = "atlas")]
+    distortion: Option<String>,
+
+    /// Write a heat map of the area distortion, in the layout of the geometry image. Red pixels got more than
+    /// their share of the image, blue pixels less.
+    #[clap(long, conflicts_with = "atlas")]
+    heat_map: Option<String>,
+
+    /// Also write every coarser level of the geometry image, down to 2x2. "separate" writes a file per level,
+    /// "packed" writes all levels side by side into one file.
+    #[clap(long, conflicts_with = "atlas")]
+    mipmaps: Option<MipLayout>,
+
+    /// The input mesh file (.obj, .stl, .ply, .gltf or .glb). Only optional with a subcommand.
+    #[clap(required = true)]
+    input: Option<String>,
+}
+
+#[derive(Args)]
+struct VideoArgs {
+    #[command(flatten)]
+    image: ImageArgs,
+
+    /// The frame that is mapped to the sphere. Pick one where the mesh is not folded or squeezed, like a rest pose.
+    #[clap(long, default_value_t = 0)]
+    reference: usize,
+
+    /// Vertices of the reference frame closer than this fraction of its bounding box diagonal are welded together
+    #[clap(long, default_value_t = 1e-6)]
+    weld_tolerance: f32,
+
+    #[command(flatten)]
+    mapping: MappingArgs,
+
+    /// A directory with a mesh file per frame, in the order of the numbers at the end of their names. All of them
+    /// must have the same triangles.
+    frames: PathBuf,
+}
+
+/// Where and how the geometry image is written
+#[derive(Args)]
+struct ImageArgs {
+    /// Specify the path of the geometry image that will be generated (.png, .exr or .f32)
     #[clap(short, long, default_value = "./export.png")]
     output: String,
 
-    /// Size of geometry image (<n> x <n>) [must be an odd number]
-    #[clap(short, long, default_value_t = 255)]
-    size: u32,
+    /// Bits per channel: 8 or 16 for .png files, 32 for .exr and .f32 files [default: 16 for .png, 32 otherwise]
+    #[clap(short, long)]
+    bit_depth: Option<BitDepth>,
 
-    /// The input .obj file
-    input: String,
+    /// How .png files map the values to their range: "bounds" stretches every channel, "uniform" keeps the proportions
+    #[clap(short, long, default_value = "bounds")]
+    quantization: Quantization,
+
+    /// Size of geometry image, <n> for a square or <width>x<height>. Odd sizes put a pixel on every vertex of the
+    /// octahedron, powers of two work better with mipmaps.
+    #[clap(short, long, default_value = "255")]
+    size: GimSize,
 }
 
-#[derive(Serialize, Deserialize)]
-struct SerializeVec3 {
-    x: f32,
-    y: f32,
-    z: f32,
+impl ImageArgs {
+    /// Checks the output before spending time on the parametrization
+    fn output_options(&self) -> Result<(ImageFormat, OutputOptions), Mesh2GimError> {
+        let format = ImageFormat::from_extension(Path::new(&self.output))
+            .ok_or(EncodingError::UnknownFormat)?;
+        let options = OutputOptions {
+            bit_depth: self.bit_depth.unwrap_or(format.default_bit_depth()),
+            quantization: self.quantization,
+        };
+        if !format.supports(options.bit_depth) {
+            return Err(EncodingError::UnsupportedBitDepth {
+                format,
+                bit_depth: options.bit_depth,
+            }
+            .into());
+        }
+        Ok((format, options))
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.size.width, self.size.height)
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct SerializeAABB {
-    min: SerializeVec3,
-    max: SerializeVec3,
-}
-
-impl From<AABB> for SerializeAABB {
-    fn from(aabb: AABB) -> Self {
-        SerializeAABB {
-            min: SerializeVec3 {
-                x: aabb.min.x,
-                y: aabb.min.y,
-                z: aabb.min.z,
-            },
-            max: SerializeVec3 {
-                x: aabb.max.x,
-                y: aabb.max.y,
-                z: aabb.max.z,
-            },
+/// How the mesh is mapped to the sphere and sampled
+#[derive(Args)]
+struct MappingArgs {
+    /// How the sphere is unfolded into the image: "octahedral", "equal_area_octahedral" or "cube_map"
+    #[clap(short, long, default_value = "octahedral", value_parser = parse_layout)]
+    layout: &'static dyn Layout,
+
+    /// Where the vertices start on the sphere: "centroid", or "tutte" and "spectral" for bent meshes that are not
+    /// star-shaped around their centroid
+    #[clap(long, default_value = "centroid")]
+    initialization: Initialization,
+
+    /// The most smoothing iterations of the spherical parametrization
+    #[clap(short, long, default_value_t = 500)]
+    iterations: u32,
+
+    /// How the smoothing weights the neighbors of a vertex: "uniform", "cotangent" or "mean_value"
+    #[clap(short, long, default_value = "uniform")]
+    weights: LaplacianWeights,
+
+    /// How pixels are filled where the mesh has holes: "nearest_triangle" or "diffusion"
+    #[clap(long, default_value = "nearest_triangle")]
+    hole_filling: HoleFilling,
+
+    /// Iterations of area resampling after the smoothing, which gives the parts of the mesh that the smoothing
+    /// squeezed together more pixels. 0 turns it off, 100 is a good start.
+    #[clap(long, default_value_t = 0)]
+    area_resampling: u32,
+
+    /// Decimate meshes with more triangles than this before the parametrization, which is much faster then. The
+    /// pixels still sample the full mesh. 0 parametrizes every triangle.
+    #[clap(long, default_value_t = 0)]
+    decimate: usize,
+
+    /// How many threads to use, 0 uses one per core
+    #[clap(short, long, default_value_t = 0)]
+    threads: usize,
+}
+
+impl MappingArgs {
+    fn gim_options(&self, size: (u32, u32)) -> GimOptions<'static> {
+        GimOptions::new(size)
+            .initialization(self.initialization)
+            .iterations(self.iterations)
+            .weights(self.weights)
+            .layout(self.layout)
+            .hole_filling(self.hole_filling)
+            .area_resampling(self.area_resampling)
+            .decimate(self.decimate)
+            .threads(self.threads)
+            .progress(print_progress)
+    }
+
+    fn parameters(
+        &self,
+        weld_tolerance: f32,
+        parametrization: &Parametrization,
+    ) -> ParameterMetadata {
+        ParameterMetadata {
+            weld_tolerance,
+            initialization: Some(parametrization.initialization.to_string()),
+            weights: Some(self.weights.to_string()),
+            max_iterations: self.iterations,
+            iterations: Some(parametrization.iterations),
+            converged: Some(parametrization.converged),
+            hole_filling: Some(self.hole_filling.to_string()),
+            area_resampling: Some(self.area_resampling),
+            decimate: Some(self.decimate),
         }
     }
 }
 
-fn main() {
-    let args = Cli::parse();
-    println!("Output: {}", args.output);
-    println!("Size: {}", args.size);
-    println!("Input: {}", args.input);
+#[derive(Clone, Copy)]
+struct GimSize {
+    width: u32,
+    height: u32,
+}
 
-    let input = BufReader::new(File::open(&args.input).unwrap());
+impl FromStr for GimSize {
+    type Err = String;
 
-    let mesh = into_mesh(obj::raw::parse_obj(input).unwrap());
-    let bounds = mesh.get_bounds();
-    let geometry_image = make_geometry_image(&mesh, (args.size, args.size));
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("invalid size {s}, expected <n> or <width>x<height>"))
+        };
+        let (width, height) = match s.split_once('x') {
+            Some((width, height)) => (parse(width)?, parse(height)?),
+            None => (parse(s)?, parse(s)?),
+        };
+        Ok(GimSize { width, height })
+    }
+}
 
-    let image = ImageBuffer::from_fn(geometry_image.width, geometry_image.height, |x, y| {
-        let pixel = geometry_image.pixels[(y * geometry_image.width + x) as usize];
-        let scaled_pixel = (pixel - bounds.min) / (bounds.max - bounds.min);
-        let x: u16 = (scaled_pixel.x * u16::MAX as f32) as u16;
-        let y: u16 = (scaled_pixel.y * u16::MAX as f32) as u16;
-        let z: u16 = (scaled_pixel.z * u16::MAX as f32) as u16;
-        image::Rgb([x, y, z])
-    });
-    let image = DynamicImage::ImageRgb16(image);
-    image.save(&args.output).unwrap();
-
-    let metadata_path = args.output.replace(".png", ".json");
-    let ser_bounds = SerializeAABB::from(bounds);
-    let metadata = json::to_string(&ser_bounds);
-    std::fs::write(metadata_path, metadata).unwrap();
-}
-
-fn into_mesh(raw: obj::raw::RawObj) -> Mesh {
-    if raw.normals.is_empty() {
-        let obj: obj::Obj<obj::Position, u32> = obj::Obj::new(raw).unwrap();
-        let positions = obj.vertices.iter().map(|v| v.position.into()).collect();
-        Mesh {
-            positions,
-            attributes: vec![],
-            indices: obj.indices,
+impl fmt::Display for GimSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+fn parse_layout(name: &str) -> Result<&'static dyn Layout, String> {
+    layout_by_name(name).ok_or_else(|| {
+        let names: Vec<_> = LAYOUTS.iter().map(|layout| layout.name()).collect();
+        format!("unknown layout {name}, expected {}", names.join(", "))
+    })
+}
+
+#[derive(Clone, Copy)]
+enum MipLayout {
+    Separate,
+    Packed,
+}
+
+impl FromStr for MipLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "separate" => Ok(MipLayout::Separate),
+            "packed" => Ok(MipLayout::Packed),
+            _ => Err(format!(
+                "unknown mipmap layout {s}, expected separate or packed"
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializeDistortion {
+    mean_area_stretch: f32,
+    max_area_stretch: f32,
+    mean_angle_distortion: f32,
+    max_angle_distortion: f32,
+    /// Share of the sphere area in each triangle of the octahedral image, numbered by the signs of x (4), y (2) and z (1)
+    octant_coverage: Vec<f32>,
+    flipped_triangles: Vec<u32>,
+    area_distortion: Vec<f32>,
+    angle_distortion: Vec<f32>,
+}
+
+impl From<&DistortionReport> for SerializeDistortion {
+    fn from(report: &DistortionReport) -> Self {
+        SerializeDistortion {
+            mean_area_stretch: report.mean_area_stretch,
+            max_area_stretch: report.max_area_stretch,
+            mean_angle_distortion: report.mean_angle_distortion,
+            max_angle_distortion: report.max_angle_distortion,
+            octant_coverage: report.octant_coverage.to_vec(),
+            flipped_triangles: report.flipped_triangles.clone(),
+            area_distortion: report.area_distortion.clone(),
+            angle_distortion: report.angle_distortion.clone(),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Some(Command::Batch(args)) => batch::run_batch(args).map_err(|error| error.to_string()),
+        Some(Command::Video(args)) => run_video(args).map_err(|error| error.to_string()),
+        None => run(&cli.convert).map_err(|error| error.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
         }
-    } else if raw.tex_coords.is_empty() {
-        // Normals but no texture coordinates
-        let obj: obj::Obj<obj::Vertex, u32> = obj::Obj::new(raw).unwrap();
-        let positions = obj.vertices.iter().map(|v| v.position.into()).collect();
-        let normals = obj.vertices.iter().map(|v| v.normal.into()).collect();
-        Mesh {
-            positions,
-            attributes: vec![Attribute {
-                name: "normal".to_string(),
-                values: AttributeValues::Vec3s(normals),
-            }],
-            indices: obj.indices,
+    }
+}
+
+fn run(args: &ConvertArgs) -> Result<(), Mesh2GimError> {
+    println!("Output: {}", args.image.output);
+    println!("Size: {}", args.image.size);
+    let input = args.input.as_deref().expect("clap requires the input");
+    println!("Input: {input}");
+
+    let output = Path::new(&args.image.output);
+    let (format, options) = args.image.output_options()?;
+    let layout = args.mapping.layout;
+    if args.mipmaps.is_some() && !args.atlas && !layout.folds_borders() {
+        return Err(Mesh2GimError::UnsupportedLayout {
+            layout: layout.name(),
+            feature: "mipmaps",
+        });
+    }
+
+    let mesh = load_mesh(input)?;
+    let (mesh, report) = repair_mesh(
+        &mesh,
+        &RepairOptions {
+            weld_tolerance: args.weld_tolerance,
+        },
+    )?;
+    print_repair_report(&report, args.atlas);
+    if args.strict && !args.atlas {
+        report.topology.check()?;
+    }
+    let size = args.image.size();
+    let (
+        GeometryImage {
+            positions: geometry_image,
+            attributes,
+            ..
+        },
+        charts,
+        parametrization,
+    ) = if args.atlas {
+        let atlas = make_geometry_image_atlas(&mesh, size, &AtlasOptions::default())?;
+        println!("Packed {} charts", atlas.charts.len());
+        if atlas.dropped_triangles > 0 {
+            println!(
+                "{} broken triangles could not be mapped and were left out",
+                atlas.dropped_triangles
+            );
         }
+        (atlas.image, atlas.charts, None)
     } else {
-        // Normals and texture coordinates
-        let obj: obj::Obj<obj::TexturedVertex, u32> = obj::Obj::new(raw).unwrap();
-        let positions = obj.vertices.iter().map(|v| v.position.into()).collect();
-        let normals = obj.vertices.iter().map(|v| v.normal.into()).collect();
-        let tex_coords = obj
-            .vertices
+        check_size(size, layout)?;
+        let gim_options = args.mapping.gim_options(size);
+        let decimated = decimate_for_parametrization(&mesh, &gim_options)?;
+        if let Some(decimated) = &decimated {
+            println!(
+                "Decimated {} triangles to {}",
+                mesh.faces_count(),
+                decimated.faces_count()
+            );
+        }
+        // The distortion is measured on the mesh that was parametrized
+        let surface = decimated.as_ref().unwrap_or(&mesh);
+        let parametrization = parametrize_mesh(surface, &gim_options)?;
+        print_convergence(&parametrization);
+        let mut grid = sample_mesh(surface, &parametrization.vertices, &gim_options)?;
+        if args.distortion.is_some() || args.heat_map.is_some() {
+            let report = analyze_distortion(surface, &parametrization.vertices);
+            print_distortion_report(&report);
+            if let Some(distortion_path) = &args.distortion {
+                std::fs::write(
+                    distortion_path,
+                    json::to_string(&SerializeDistortion::from(&report)),
+                )?;
+            }
+            if let Some(heat_map_path) = &args.heat_map {
+                let values = grid.bake(&report.vertex_area_distortion(surface));
+                write_heat_map(
+                    &values,
+                    grid.width,
+                    grid.height,
+                    HEAT_MAP_LIMIT,
+                    heat_map_path,
+                )?;
+            }
+        }
+        if let Some(decimated) = &decimated {
+            grid = sample_full_mesh(&grid, decimated, &mesh, &gim_options)?;
+        }
+        (
+            GeometryImage::from_grid(&mesh, &grid),
+            layout.charts(size),
+            Some(parametrization),
+        )
+    };
+
+    // Float formats store the positions as they are, so the range is only needed by quantized images
+    let range = write_image(&geometry_image, output, &options)?;
+
+    let invalid_pixels = geometry_image.valid.iter().filter(|valid| !**valid).count();
+    if invalid_pixels > 0 {
+        println!("{invalid_pixels} pixels are not covered by the mesh and were filled in");
+    }
+    if let Some(mask_path) = &args.mask {
+        let mask = ImageBuffer::from_fn(geometry_image.width, geometry_image.height, |x, y| {
+            let valid = geometry_image.valid[(y * geometry_image.width + x) as usize];
+            image::Luma([if valid { u8::MAX } else { 0 }])
+        });
+        DynamicImage::ImageLuma8(mask)
+            .save(mask_path)
+            .map_err(EncodingError::from)?;
+    }
+
+    let attributes = write_attributes(&attributes, output, format, &options)?;
+
+    let mipmaps = match args.mipmaps {
+        Some(layout) => write_mipmaps(&geometry_image, output, format, &options, layout)?,
+        None => vec![],
+    };
+
+    let metadata = Metadata {
+        version: METADATA_VERSION,
+        layout: if args.atlas {
+            ATLAS_LAYOUT.to_string()
+        } else {
+            layout.name().to_string()
+        },
+        width: geometry_image.width,
+        height: geometry_image.height,
+        file: file_name(output),
+        bit_depth: options.bit_depth.bits(),
+        quantization: options.quantization.to_string(),
+        min: range.min.into(),
+        max: range.max.into(),
+        attributes,
+        charts: charts.iter().map(RectMetadata::from).collect(),
+        mipmaps,
+        frames: None,
+        source: SourceMetadata::new(file_name(Path::new(input)), &mesh, &report),
+        parameters: match &parametrization {
+            Some(parametrization) => args
+                .mapping
+                .parameters(args.weld_tolerance, parametrization),
+            None => ParameterMetadata {
+                weld_tolerance: args.weld_tolerance,
+                initialization: None,
+                weights: None,
+                max_iterations: AtlasOptions::default().max_iterations,
+                iterations: None,
+                converged: None,
+                hole_filling: None,
+                area_resampling: None,
+                decimate: None,
+            },
+        },
+    };
+    std::fs::write(output.with_extension("json"), metadata.to_json())?;
+
+    if let Some(reconstruct_path) = &args.reconstruct {
+        // Read the file back, so the comparison includes the quantization
+        let decoded = read_image(output, Some(&range))?;
+        let reconstructed = if charts.is_empty() {
+            reconstruct_mesh(&decoded)
+        } else {
+            reconstruct_atlas(&decoded, &charts)
+        };
+        write_obj(
+            &reconstructed,
+            BufWriter::new(File::create(reconstruct_path)?),
+        )?;
+        let distance = compare_meshes(&mesh, &reconstructed, COMPARE_SAMPLES);
+        let bounds = mesh.get_bounds();
+        let diagonal = (bounds.max - bounds.min).length();
+        println!(
+            "Hausdorff distance: {} ({:.4}% of the bounding box diagonal)",
+            distance.hausdorff,
+            distance.hausdorff / diagonal * 100.0
+        );
+        println!(
+            "RMS distance: {} ({:.4}% of the bounding box diagonal)",
+            distance.rms,
+            distance.rms / diagonal * 100.0
+        );
+    }
+    Ok(())
+}
+
+/// Converts every frame of an animation with the mapping of the reference frame
+fn run_video(args: &VideoArgs) -> Result<(), Mesh2GimError> {
+    let output = Path::new(&args.image.output);
+    let (format, options) = args.image.output_options()?;
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(&args.frames)? {
+        let path = entry?.path();
+        if path.is_file() && MeshFormat::from_extension(&path).is_some() {
+            paths.push(path);
+        }
+    }
+    sort_frames(&mut paths);
+    println!("Frames: {}", paths.len());
+    let frames = paths.iter().map(load_mesh).collect::<Result<Vec<_>, _>>()?;
+
+    let size = args.image.size();
+    let video = make_geometry_video(
+        &frames,
+        args.reference,
+        &RepairOptions {
+            weld_tolerance: args.weld_tolerance,
+        },
+        &args.mapping.gim_options(size),
+    )?;
+    print_repair_report(&video.repair, false);
+    print_convergence(&video.parametrization);
+
+    // Every frame is quantized with the same range, so a shader can blend them before decoding
+    let bounds = [video.bounds.min.extend(0.0), video.bounds.max.extend(0.0)];
+    let (min, max) = options.quantization.range(&bounds, 3);
+    let range = AABB {
+        min: min.truncate(),
+        max: max.truncate(),
+    };
+    let mut files = Vec::with_capacity(video.frames.len());
+    for (i, frame) in video.frames.iter().enumerate() {
+        let path = output.with_extension(format!("{i:04}.{}", format.extension()));
+        write_image_in_range(frame, &range, &path, &options)?;
+        files.push(file_name(&path));
+    }
+    let attributes = write_attributes(&video.attributes, output, format, &options)?;
+
+    let layout = args.mapping.layout;
+    let reference = &frames[args.reference];
+    let metadata = Metadata {
+        version: METADATA_VERSION,
+        layout: layout.name().to_string(),
+        width: size.0,
+        height: size.1,
+        file: files[0].clone(),
+        bit_depth: options.bit_depth.bits(),
+        quantization: options.quantization.to_string(),
+        min: range.min.into(),
+        max: range.max.into(),
+        attributes,
+        charts: layout.charts(size).iter().map(RectMetadata::from).collect(),
+        mipmaps: vec![],
+        frames: Some(files),
+        source: SourceMetadata::new(file_name(&paths[args.reference]), reference, &video.repair),
+        parameters: args
+            .mapping
+            .parameters(args.weld_tolerance, &video.parametrization),
+    };
+    std::fs::write(output.with_extension("json"), metadata.to_json())?;
+    println!("Wrote {} frames", video.frames.len());
+    Ok(())
+}
+
+/// Writes the attribute images next to the output
+fn write_attributes(
+    attributes: &[AttributeImage],
+    output: &Path,
+    format: ImageFormat,
+    options: &OutputOptions,
+) -> Result<Vec<AttributeMetadata>, Mesh2GimError> {
+    attributes
+        .iter()
+        .map(|attribute_image| {
+            let path =
+                output.with_extension(format!("{}.{}", attribute_image.name, format.extension()));
+            let (min, max) = write_attribute_image(attribute_image, &path, options)?;
+            let components = attribute_image.values.components();
+            Ok(AttributeMetadata {
+                name: attribute_image.name.clone(),
+                file: file_name(&path),
+                min: min.to_array()[..components].to_vec(),
+                max: max.to_array()[..components].to_vec(),
+            })
+        })
+        .collect()
+}
+
+fn print_convergence(parametrization: &Parametrization) {
+    println!("Initialization: {}", parametrization.initialization);
+    println!(
+        "Smoothing {} after {} iterations",
+        if parametrization.converged {
+            "converged"
+        } else {
+            "stopped"
+        },
+        parametrization.iterations
+    );
+    if let Some(report) = &parametrization.resampling {
+        println!(
+            "Area resampling changed the mean area stretch from {:.3} to {:.3}, and the max from {:.3} to {:.3}",
+            report.mean_area_stretch_before,
+            report.mean_area_stretch_after,
+            report.max_area_stretch_before,
+            report.max_area_stretch_after
+        );
+    }
+}
+
+/// Prints the phases as they start
+fn print_progress(progress: Progress) {
+    match progress {
+        Progress::Decimation => println!("Decimating the mesh"),
+        Progress::Adjacency => println!("Building the adjacency"),
+        Progress::Smoothing { iteration: 1, .. } => println!("Smoothing"),
+        Progress::Resampling { iteration: 1, .. } => println!("Resampling by area"),
+        Progress::Grouping => println!("Grouping the triangles"),
+        Progress::Rasterization { row: 1, .. } => println!("Sampling the pixels"),
+        Progress::Projection => println!("Moving the pixels onto the full mesh"),
+        _ => {}
+    }
+}
+
+/// Writes the coarser levels of the geometry image next to the output
+fn write_mipmaps(
+    image: &Image,
+    output: &Path,
+    format: ImageFormat,
+    options: &OutputOptions,
+    layout: MipLayout,
+) -> Result<Vec<MipMetadata>, Mesh2GimError> {
+    let levels = mip_chain(image);
+    let serialize = |path: &Path, rect: MipRect, range: &AABB| MipMetadata {
+        file: file_name(path),
+        rect: rect.into(),
+        min: range.min.into(),
+        max: range.max.into(),
+    };
+    match layout {
+        MipLayout::Separate => levels
             .iter()
-            .map(|v| Vec2::new(v.texture[0], v.texture[1]))
-            .collect();
-        Mesh {
-            positions,
-            attributes: vec![
-                Attribute {
-                    name: "tex_coords".to_string(),
-                    values: AttributeValues::Vec2s(tex_coords),
-                },
-                Attribute {
-                    name: "normals".to_string(),
-                    values: AttributeValues::Vec3s(normals),
-                },
-            ],
-            indices: obj.indices,
+            .enumerate()
+            .map(|(i, level)| {
+                let path = output.with_extension(format!("mip{}.{}", i + 1, format.extension()));
+                let range = write_image(level, &path, options)?;
+                let rect = MipRect {
+                    x: 0,
+                    y: 0,
+                    width: level.width,
+                    height: level.height,
+                };
+                Ok(serialize(&path, rect, &range))
+            })
+            .collect(),
+        MipLayout::Packed => {
+            let (packed, rects) = pack_mip_chain(image, &levels);
+            let path = output.with_extension(format!("mips.{}", format.extension()));
+            let range = write_image(&packed, &path, options)?;
+            Ok(rects
+                .into_iter()
+                .map(|rect| serialize(&path, rect, &range))
+                .collect())
         }
     }
 }
+
+fn print_distortion_report(report: &DistortionReport) {
+    println!(
+        "Area stretch: {:.3} mean, {:.3} max",
+        report.mean_area_stretch, report.max_area_stretch
+    );
+    println!(
+        "Angle distortion: {:.2}° mean, {:.2}° max",
+        report.mean_angle_distortion.to_degrees(),
+        report.max_angle_distortion.to_degrees()
+    );
+    if !report.flipped_triangles.is_empty() {
+        println!(
+            "Warning: {} triangles are flipped on the sphere",
+            report.flipped_triangles.len()
+        );
+    }
+}
+
+/// Atlases have no problem with the topology of the mesh, so only the sphere layout warns about it
+fn print_repair_report(report: &RepairReport, atlas: bool) {
+    if report.welded_vertices > 0 {
+        println!("Welded {} vertices", report.welded_vertices);
+    }
+    if report.degenerate_triangles > 0 || report.duplicate_triangles > 0 {
+        println!(
+            "Removed {} degenerate and {} duplicate triangles",
+            report.degenerate_triangles, report.duplicate_triangles
+        );
+    }
+    if report.unused_vertices > 0 {
+        println!("Removed {} unused vertices", report.unused_vertices);
+    }
+    let topology = &report.topology;
+    match topology.genus {
+        Some(genus) => println!(
+            "Euler characteristic: {}, genus: {genus}",
+            topology.euler_characteristic
+        ),
+        None => println!(
+            "Euler characteristic: {}, not a manifold",
+            topology.euler_characteristic
+        ),
+    }
+    let problems = if atlas { vec![] } else { topology.problems() };
+    for warning in topology.warnings().iter().chain(&problems) {
+        println!("Warning: {warning}");
+    }
+}
+
+/// Sorts frame files by the number at the end of their names, so `frame2` comes before `frame10` even without
+/// leading zeros
+fn sort_frames(paths: &mut [PathBuf]) {
+    paths.sort_by_cached_key(|path| {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+        let number = stem[prefix.len()..].trim_start_matches('0');
+        (
+            prefix.to_string(),
+            number.len(),
+            number.to_string(),
+            path.clone(),
+        )
+    });
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_frames() {
+        let mut paths: Vec<PathBuf> = [
+            "frame10.obj",
+            "frame2.obj",
+            "frame1.obj",
+            "frame0010.obj",
+            "frame.obj",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+        sort_frames(&mut paths);
+        assert_eq!(
+            paths,
+            [
+                "frame.obj",
+                "frame1.obj",
+                "frame2.obj",
+                "frame0010.obj",
+                "frame10.obj"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+}
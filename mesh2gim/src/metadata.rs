@@ -0,0 +1,481 @@
+//! The JSON document that is written next to every geometry image.
+//!
+//! It holds everything needed to read the image back: the layout that unfolds the sphere, the file format, and the
+//! range that quantized formats map to [0, 1]. It also records where the image came from, and the parameters it was
+//! made with.
+//!
+//! `version` is bumped whenever a field changes its meaning or is removed, so readers can refuse documents they do
+//! not understand. Version 1 is the first versioned document, older files without a version are not supported.
+
+use std::{path::Path, str::FromStr};
+
+use glam::Vec3;
+use miniserde::{json, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    atlas::ChartRect,
+    encoding::{BitDepth, ImageFormat, Quantization},
+    mipmap::{mip_size, MipRect},
+    parametrization::{layout_by_name, Layout},
+    repair::RepairReport,
+    Mesh, AABB,
+};
+
+/// The version of the document that this library writes and reads
+pub const METADATA_VERSION: u32 = 1;
+
+/// The layout name of images that are chart atlases instead of unfolded spheres
+pub const ATLAS_LAYOUT: &str = "atlas";
+
+#[derive(Error, Debug)]
+pub enum MetadataError {
+    #[error("could not read the metadata: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("the metadata is not valid JSON, or is missing fields")]
+    Parse,
+    #[error("unsupported metadata version {0}, expected {METADATA_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("invalid metadata: {0}")]
+    Invalid(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Vec3Metadata {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl From<Vec3> for Vec3Metadata {
+    fn from(v: Vec3) -> Self {
+        Vec3Metadata {
+            x: v.x,
+            y: v.y,
+            z: v.z,
+        }
+    }
+}
+
+impl From<Vec3Metadata> for Vec3 {
+    fn from(v: Vec3Metadata) -> Self {
+        Vec3::new(v.x, v.y, v.z)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub version: u32,
+    /// The [`Layout::name`] that unfolds the sphere, or [`ATLAS_LAYOUT`]
+    pub layout: String,
+    pub width: u32,
+    pub height: u32,
+    /// The positions image, relative to the metadata file
+    pub file: String,
+    /// Bits per channel: 8, 16 or 32
+    pub bit_depth: u32,
+    /// "bounds" or "uniform", only matters for quantized formats
+    pub quantization: String,
+    /// The range of the positions that was mapped to [0, 1]
+    pub min: Vec3Metadata,
+    pub max: Vec3Metadata,
+    pub attributes: Vec<AttributeMetadata>,
+    /// Rectangles of the charts in pixels, empty unless the image is made of separate pieces
+    pub charts: Vec<RectMetadata>,
+    /// The coarser levels in their own files, or every level when they are packed. Empty unless mipmaps were
+    /// written.
+    pub mipmaps: Vec<MipMetadata>,
+    /// The image of every frame of a geometry video, in order. They share the layout and the range of the
+    /// positions, and `file` is the first frame. None for single images.
+    pub frames: Option<Vec<String>>,
+    pub source: SourceMetadata,
+    pub parameters: ParameterMetadata,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AttributeMetadata {
+    pub name: String,
+    pub file: String,
+    /// One value per component of the attribute
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RectMetadata {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<&ChartRect> for RectMetadata {
+    fn from(rect: &ChartRect) -> Self {
+        RectMetadata {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+impl From<MipRect> for RectMetadata {
+    fn from(rect: MipRect) -> Self {
+        RectMetadata {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MipMetadata {
+    pub file: String,
+    /// Where the level is in the file, which only holds that level unless the levels are packed
+    pub rect: RectMetadata,
+    pub min: Vec3Metadata,
+    pub max: Vec3Metadata,
+}
+
+/// The mesh the image was made from, after it was repaired
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SourceMetadata {
+    pub file: String,
+    pub vertices: u32,
+    pub triangles: u32,
+    pub components: u32,
+    pub boundary_loops: u32,
+    /// Only known for manifold meshes
+    pub genus: Option<u32>,
+    pub min: Vec3Metadata,
+    pub max: Vec3Metadata,
+}
+
+impl SourceMetadata {
+    /// Describes the repaired mesh, with the counts of its topology
+    pub fn new(file: String, mesh: &Mesh, report: &RepairReport) -> Self {
+        let bounds = mesh.get_bounds();
+        let topology = &report.topology;
+        SourceMetadata {
+            file,
+            vertices: topology.vertices as u32,
+            triangles: topology.faces as u32,
+            components: topology.components as u32,
+            boundary_loops: topology.boundary_loops as u32,
+            genus: topology.genus,
+            min: bounds.min.into(),
+            max: bounds.max.into(),
+        }
+    }
+}
+
+/// The settings the image was made with
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParameterMetadata {
+    pub weld_tolerance: f32,
+    /// Where the vertices started on the sphere, none for atlases
+    pub initialization: Option<String>,
+    /// How the smoothing weights the neighbors, none for atlases
+    pub weights: Option<String>,
+    pub max_iterations: u32,
+    /// Smoothing iterations that were actually done, none for atlases
+    pub iterations: Option<u32>,
+    pub converged: Option<bool>,
+    pub hole_filling: Option<String>,
+    /// Iterations of area resampling, none for atlases
+    pub area_resampling: Option<u32>,
+    /// The triangle count meshes were decimated to before the parametrization, 0 if they were not. None for
+    /// atlases.
+    pub decimate: Option<usize>,
+}
+
+impl Metadata {
+    /// Parses and validates a document
+    pub fn from_json(s: &str) -> Result<Self, MetadataError> {
+        let metadata: Metadata = json::from_str(s).map_err(|_| {
+            // Tell documents of other versions apart from broken ones
+            match json::from_str::<Versioned>(s) {
+                Ok(Versioned { version }) if version != METADATA_VERSION => {
+                    MetadataError::UnsupportedVersion(version)
+                }
+                Ok(_) => MetadataError::Parse,
+                // The documents written before the version was added
+                Err(_) if json::from_str::<json::Value>(s).is_ok() => {
+                    MetadataError::UnsupportedVersion(0)
+                }
+                Err(_) => MetadataError::Parse,
+            }
+        })?;
+        metadata.validate()?;
+        Ok(metadata)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, MetadataError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        json::to_string(self)
+    }
+
+    /// Checks that the values are consistent, and that every rectangle is inside its image
+    pub fn validate(&self) -> Result<(), MetadataError> {
+        let invalid = |message: String| Err(MetadataError::Invalid(message));
+        if self.version != METADATA_VERSION {
+            return Err(MetadataError::UnsupportedVersion(self.version));
+        }
+        if self.layout != ATLAS_LAYOUT && self.layout().is_none() {
+            return invalid(format!("unknown layout {}", self.layout));
+        }
+        if self.width < 2 || self.height < 2 {
+            return invalid(format!("image size {}x{}", self.width, self.height));
+        }
+        let format = self.format()?;
+        let bit_depth = self.bit_depth()?;
+        if !format.supports(bit_depth) {
+            return invalid(format!("{format} files can not have {bit_depth} bits"));
+        }
+        self.quantization()?;
+        let ordered =
+            |min: Vec3Metadata, max: Vec3Metadata| Vec3::from(min).cmple(Vec3::from(max)).all();
+        if !ordered(self.min, self.max) {
+            return invalid("the minimum of the positions is above the maximum".to_string());
+        }
+        for attribute in &self.attributes {
+            if attribute.min.is_empty()
+                || attribute.min.len() > 4
+                || attribute.min.len() != attribute.max.len()
+            {
+                return invalid(format!("the range of attribute {}", attribute.name));
+            }
+        }
+        for chart in &self.charts {
+            if !inside(chart, self.width, self.height) {
+                return invalid(format!("chart {chart:?} is outside of the image"));
+            }
+        }
+        // Every level has the size that the levels are always halved to, and is inside its file. Packed mipmaps
+        // start with the full image, and their file is as large as the full image with the levels next to it.
+        let mut levels = vec![(self.width, self.height)];
+        while let Some(&size) = levels.last().filter(|&&size| size != (2, 2)) {
+            levels.push(mip_size(size));
+        }
+        let packed = self
+            .mipmaps
+            .first()
+            .is_some_and(|mip| (mip.rect.width, mip.rect.height) == (self.width, self.height));
+        let packed_size = (
+            self.width + levels.get(1).map_or(0, |level| level.0),
+            self.height
+                .max(levels[1..].iter().map(|level| level.1).sum()),
+        );
+        let expected = if packed { &levels[..] } else { &levels[1..] };
+        if self.mipmaps.len() > expected.len() {
+            return invalid(format!("{} mipmap levels", self.mipmaps.len()));
+        }
+        for (mip, &size) in self.mipmaps.iter().zip(expected) {
+            let file_size = if packed { packed_size } else { size };
+            if (mip.rect.width, mip.rect.height) != size
+                || !inside(&mip.rect, file_size.0, file_size.1)
+                || !ordered(mip.min, mip.max)
+            {
+                return invalid(format!("mipmap {mip:?}"));
+            }
+        }
+        if let Some(frames) = &self.frames {
+            if frames.first() != Some(&self.file) {
+                return invalid("the first frame is not the image file".to_string());
+            }
+            if let Some(frame) = frames
+                .iter()
+                .find(|frame| ImageFormat::from_extension(Path::new(frame)) != Some(format))
+            {
+                return invalid(format!("frame {frame} is not a {format} file"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The layout of the image, none for atlases
+    pub fn layout(&self) -> Option<&'static dyn Layout> {
+        layout_by_name(&self.layout)
+    }
+
+    pub fn format(&self) -> Result<ImageFormat, MetadataError> {
+        ImageFormat::from_extension(Path::new(&self.file))
+            .ok_or_else(|| MetadataError::Invalid(format!("unknown image format of {}", self.file)))
+    }
+
+    pub fn bit_depth(&self) -> Result<BitDepth, MetadataError> {
+        [BitDepth::Eight, BitDepth::Sixteen, BitDepth::Float32]
+            .into_iter()
+            .find(|bit_depth| bit_depth.bits() == self.bit_depth)
+            .ok_or_else(|| MetadataError::Invalid(format!("bit depth {}", self.bit_depth)))
+    }
+
+    pub fn quantization(&self) -> Result<Quantization, MetadataError> {
+        Quantization::from_str(&self.quantization).map_err(MetadataError::Invalid)
+    }
+
+    /// The range that is needed to read back the positions with [`read_image`](crate::encoding::read_image)
+    pub fn range(&self) -> AABB {
+        AABB {
+            min: self.min.into(),
+            max: self.max.into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Versioned {
+    version: u32,
+}
+
+fn inside(rect: &RectMetadata, width: u32, height: u32) -> bool {
+    rect.width > 0
+        && rect.height > 0
+        && rect
+            .x
+            .checked_add(rect.width)
+            .is_some_and(|right| right <= width)
+        && rect
+            .y
+            .checked_add(rect.height)
+            .is_some_and(|bottom| bottom <= height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            version: METADATA_VERSION,
+            layout: "octahedral".to_string(),
+            width: 65,
+            height: 33,
+            file: "bunny.png".to_string(),
+            bit_depth: 16,
+            quantization: "bounds".to_string(),
+            min: Vec3::splat(-1.0).into(),
+            max: Vec3::new(1.0, 2.0, 3.0).into(),
+            attributes: vec![AttributeMetadata {
+                name: "normal".to_string(),
+                file: "bunny.normal.png".to_string(),
+                min: vec![-1.0; 3],
+                max: vec![1.0; 3],
+            }],
+            charts: vec![],
+            mipmaps: vec![MipMetadata {
+                file: "bunny.mip1.png".to_string(),
+                rect: RectMetadata {
+                    x: 0,
+                    y: 0,
+                    width: 33,
+                    height: 17,
+                },
+                min: Vec3::splat(-1.0).into(),
+                max: Vec3::ONE.into(),
+            }],
+            frames: None,
+            source: SourceMetadata {
+                file: "bunny.obj".to_string(),
+                vertices: 34_834,
+                triangles: 69_664,
+                components: 1,
+                boundary_loops: 0,
+                genus: Some(0),
+                min: Vec3::splat(-1.0).into(),
+                max: Vec3::new(1.0, 2.0, 3.0).into(),
+            },
+            parameters: ParameterMetadata {
+                weld_tolerance: 1e-6,
+                initialization: Some("tutte".to_string()),
+                weights: Some("uniform".to_string()),
+                max_iterations: 500,
+                iterations: Some(123),
+                converged: Some(true),
+                hole_filling: Some("nearest_triangle".to_string()),
+                area_resampling: Some(0),
+                decimate: Some(100_000),
+            },
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut metadata = metadata();
+        let parsed = Metadata::from_json(&metadata.to_json()).unwrap();
+        assert_eq!(parsed, metadata);
+        assert_eq!(parsed.layout().unwrap().name(), "octahedral");
+        assert_eq!(parsed.format().unwrap(), ImageFormat::Png);
+        assert_eq!(parsed.bit_depth().unwrap(), BitDepth::Sixteen);
+        assert_eq!(parsed.range().max, Vec3::new(1.0, 2.0, 3.0));
+
+        metadata.frames = Some(vec!["bunny.png".to_string(), "bunny.0001.png".to_string()]);
+        assert_eq!(Metadata::from_json(&metadata.to_json()).unwrap(), metadata);
+
+        let packed = |x, y, width, height| MipMetadata {
+            file: "bunny.mips.png".to_string(),
+            rect: RectMetadata {
+                x,
+                y,
+                width,
+                height,
+            },
+            min: Vec3::splat(-1.0).into(),
+            max: Vec3::ONE.into(),
+        };
+        metadata.mipmaps = vec![packed(0, 0, 65, 33), packed(65, 0, 33, 17)];
+        assert_eq!(Metadata::from_json(&metadata.to_json()).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_invalid_metadata() {
+        let check = |change: fn(&mut Metadata)| {
+            let mut metadata = metadata();
+            change(&mut metadata);
+            Metadata::from_json(&metadata.to_json())
+        };
+        assert!(matches!(
+            check(|m| m.version = 2),
+            Err(MetadataError::UnsupportedVersion(2))
+        ));
+        for change in [
+            (|m| m.layout = "spiral".to_string()) as fn(&mut Metadata),
+            |m| m.width = 0,
+            |m| m.bit_depth = 32,
+            |m| m.quantization = "log".to_string(),
+            |m| m.max.x = -2.0,
+            |m| m.attributes[0].max.pop().map_or((), drop),
+            |m| {
+                m.charts.push(RectMetadata {
+                    x: 60,
+                    y: 0,
+                    width: 10,
+                    height: 10,
+                })
+            },
+            |m| m.mipmaps[0].rect.x = 5,
+            |m| m.mipmaps[0].rect.height = 0,
+            |m| m.mipmaps[0].rect.width = 65,
+            |m| m.mipmaps = vec![m.mipmaps[0].clone(); 7],
+            |m| m.frames = Some(vec![]),
+            |m| m.frames = Some(vec!["bunny.png".to_string(), "bunny.0001.exr".to_string()]),
+        ] {
+            assert!(matches!(check(change), Err(MetadataError::Invalid(_))));
+        }
+
+        assert!(matches!(
+            Metadata::from_json(r#"{"min":{"x":0,"y":0,"z":0},"max":{"x":1,"y":1,"z":1}}"#),
+            Err(MetadataError::UnsupportedVersion(0))
+        ));
+        assert!(matches!(
+            Metadata::from_json("{"),
+            Err(MetadataError::Parse)
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+//! Mipmaps for octahedral geometry images.
+//!
+//! Every level samples the octahedron on the same kind of grid as the full image: the first and last rows and
+//! columns lie on the edges of the unfolded octahedron. A filter that reaches over an edge continues on the other
+//! side of the fold, instead of being clamped, so neighboring triangles of the octahedron are blended like they are
+//! on the sphere. The border pixels are mirrored again afterwards, so the edges and corners of every level match
+//! exactly and the coarse meshes have no cracks.
+
+use std::ops::{Add, Mul};
+
+use glam::Vec3;
+
+use crate::{parametrization::mirror_borders, Image};
+
+/// The size of the next coarser level. Sizes of the form 2^n + 1 and 2^n are halved exactly, levels never get
+/// smaller than 2x2.
+pub fn mip_size(size: (u32, u32)) -> (u32, u32) {
+    (size.0.div_ceil(2).max(2), size.1.div_ceil(2).max(2))
+}
+
+/// Every level coarser than the image, down to 2x2
+pub fn mip_chain(image: &Image) -> Vec<Image> {
+    let mut levels: Vec<Image> = Vec::new();
+    let mut size = (image.width, image.height);
+    while size != (2, 2) {
+        let next = downsample(levels.last().unwrap_or(image));
+        size = (next.width, next.height);
+        levels.push(next);
+    }
+    levels
+}
+
+/// Filters the image down to the next level
+pub fn downsample(image: &Image) -> Image {
+    let size = (image.width, image.height);
+    let next_size = mip_size(size);
+    let (pixels, valid) = filter(&image.pixels, &image.valid, size, next_size);
+    Image {
+        width: next_size.0,
+        height: next_size.1,
+        pixels,
+        valid,
+    }
+}
+
+/// Where a level is placed in a packed image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MipRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Packs the full image and its levels into one image. The full image is on the left, and the coarser levels are
+/// stacked on its right, top to bottom. Returns the packed image and where each level ended up, starting with the
+/// full image.
+pub fn pack_mip_chain(image: &Image, levels: &[Image]) -> (Image, Vec<MipRect>) {
+    let mut rects = vec![MipRect {
+        x: 0,
+        y: 0,
+        width: image.width,
+        height: image.height,
+    }];
+    let mut y = 0;
+    for level in levels {
+        rects.push(MipRect {
+            x: image.width,
+            y,
+            width: level.width,
+            height: level.height,
+        });
+        y += level.height;
+    }
+    let width = image.width + levels.first().map_or(0, |level| level.width);
+    let height = image.height.max(y);
+
+    // Fill the gaps with a pixel of the image, so they do not stretch the range of quantized formats
+    let mut packed = Image {
+        width,
+        height,
+        pixels: vec![
+            image.pixels.first().copied().unwrap_or(Vec3::ZERO);
+            (width * height) as usize
+        ],
+        valid: vec![false; (width * height) as usize],
+    };
+    for (level, rect) in std::iter::once(image).chain(levels).zip(&rects) {
+        for y in 0..rect.height {
+            for x in 0..rect.width {
+                let from = (y * level.width + x) as usize;
+                let to = ((rect.y + y) * width + rect.x + x) as usize;
+                packed.pixels[to] = level.pixels[from];
+                packed.valid[to] = level.valid[from];
+            }
+        }
+    }
+    (packed, rects)
+}
+
+/// Resamples the values with a tent filter that is as wide as the spacing of the coarse pixels. Filled in pixels
+/// only count where no valid pixel is in reach, so holes do not bleed into the surface.
+fn filter<T>(
+    values: &[T],
+    valid: &[bool],
+    size: (u32, u32),
+    next_size: (u32, u32),
+) -> (Vec<T>, Vec<bool>)
+where
+    T: Copy + Default + Add<Output = T> + Mul<f32, Output = T>,
+{
+    let (width, height) = (size.0 as i64, size.1 as i64);
+    let scale = |from: u32, to: u32| (from - 1) as f32 / (to - 1) as f32;
+    let (scale_x, scale_y) = (scale(size.0, next_size.0), scale(size.1, next_size.1));
+
+    let mut pixels = Vec::with_capacity((next_size.0 * next_size.1) as usize);
+    let mut next_valid = Vec::with_capacity(pixels.capacity());
+    for y in 0..next_size.1 {
+        for x in 0..next_size.0 {
+            let center = (x as f32 * scale_x, y as f32 * scale_y);
+            let radius = (scale_x.max(1.0), scale_y.max(1.0));
+            let mut sums = [(T::default(), 0.0f32); 2];
+            for j in (center.1 - radius.1).ceil() as i64..=(center.1 + radius.1).floor() as i64 {
+                for i in (center.0 - radius.0).ceil() as i64..=(center.0 + radius.0).floor() as i64
+                {
+                    let weight = (1.0 - (i as f32 - center.0).abs() / radius.0)
+                        * (1.0 - (j as f32 - center.1).abs() / radius.1);
+                    if weight <= 0.0 {
+                        continue;
+                    }
+                    let index = fold(i, j, width, height);
+                    let sum = &mut sums[valid[index] as usize];
+                    *sum = (sum.0 + values[index] * weight, sum.1 + weight);
+                }
+            }
+            let is_valid = sums[1].1 > 0.0;
+            let (sum, weight) = sums[is_valid as usize];
+            pixels.push(sum * (1.0 / weight));
+            next_valid.push(is_valid);
+        }
+    }
+    mirror_borders(&mut pixels, next_size);
+    mirror_borders(&mut next_valid, next_size);
+    (pixels, next_valid)
+}
+
+/// Maps a pixel outside of the image to the pixel on the other side of the fold of the octahedron. Crossing a left
+/// or right edge mirrors the row, crossing a top or bottom edge mirrors the column.
+fn fold(mut x: i64, mut y: i64, width: i64, height: i64) -> usize {
+    if x < 0 || x >= width {
+        x = if x < 0 { -x } else { 2 * (width - 1) - x };
+        y = height - 1 - y;
+    }
+    if y < 0 || y >= height {
+        y = if y < 0 { -y } else { 2 * (height - 1) - y };
+        x = width - 1 - x;
+    }
+    (y.clamp(0, height - 1) * width + x.clamp(0, width - 1)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        make_geometry_image, reconstruct::reconstruct_mesh, repair::Topology,
+        test_meshes::subdivided_octahedron,
+    };
+
+    #[test]
+    fn test_mip_sizes() {
+        assert_eq!(mip_size((257, 257)), (129, 129));
+        assert_eq!(mip_size((256, 128)), (128, 64));
+        assert_eq!(mip_size((3, 2)), (2, 2));
+        let image = make_geometry_image(&subdivided_octahedron(2, Vec3::ONE), (65, 33))
+            .unwrap()
+            .positions;
+        let sizes: Vec<_> = mip_chain(&image)
+            .iter()
+            .map(|level| (level.width, level.height))
+            .collect();
+        assert_eq!(sizes, [(33, 17), (17, 9), (9, 5), (5, 3), (3, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn test_levels_keep_the_border_symmetry() {
+        let sphere = subdivided_octahedron(3, Vec3::new(1.0, 2.0, 3.0));
+        for size in [(65, 65), (64, 64)] {
+            let image = make_geometry_image(&sphere, size).unwrap().positions;
+            for level in mip_chain(&image) {
+                let (width, height) = (level.width, level.height);
+                let pixel = |x: u32, y: u32| level.pixels[(y * width + x) as usize];
+                for y in 0..height {
+                    assert_eq!(pixel(0, y), pixel(0, height - 1 - y));
+                    assert_eq!(pixel(width - 1, y), pixel(width - 1, height - 1 - y));
+                }
+                for x in 0..width {
+                    assert_eq!(pixel(x, 0), pixel(width - 1 - x, 0));
+                }
+                assert!(level.valid.iter().all(|&valid| valid));
+                if width > 4 {
+                    // Coarse levels still close into a sphere without cracks
+                    let topology = Topology::of(&reconstruct_mesh(&level));
+                    assert_eq!(topology.boundary_edges, 0);
+                    assert_eq!(topology.genus, Some(0));
+                    // Pixels sampled right on the border are smoothed like the ones inside
+                    for point in &level.pixels {
+                        let radius = (*point / Vec3::new(1.0, 2.0, 3.0)).length();
+                        assert!(radius > 0.8 && radius < 1.05, "{size:?} {width}: {radius}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_mip_chain() {
+        let image = make_geometry_image(&subdivided_octahedron(2, Vec3::ONE), (33, 33))
+            .unwrap()
+            .positions;
+        let levels = mip_chain(&image);
+        let (packed, rects) = pack_mip_chain(&image, &levels);
+        assert_eq!(rects.len(), levels.len() + 1);
+        assert_eq!((packed.width, packed.height), (33 + 17, 33 + 3));
+        for (level, rect) in std::iter::once(&image).chain(&levels).zip(&rects) {
+            let last = (rect.y + rect.height - 1) * packed.width + rect.x + rect.width - 1;
+            assert_eq!(packed.pixels[last as usize], *level.pixels.last().unwrap());
+        }
+    }
+}
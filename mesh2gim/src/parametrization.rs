@@ -1,250 +1,769 @@
-use std::collections::HashMap;
+use std::{
+    fmt,
+    ops::{Add, Mul},
+    str::FromStr,
+};
 
-use crate::{Image, Mesh};
+use crate::{
+    adjacency::Adjacency,
+    atlas::ChartRect,
+    embedding::initial_embedding,
+    resampling::ResamplingReport,
+    sampling::{SphereSampler, SurfaceSample, SurfaceSampler},
+    Attribute, AttributeImage, AttributeValues, Image, Mesh, Mesh2GimError, Progress,
+};
 use glam::{FloatExt, Mat3, UVec2, UVec3, Vec2, Vec3};
+use rayon::prelude::*;
 
-pub struct TriangleGroups {
-    pub groups: [Vec<UVec3>; 8],
+/// How the neighbors of a vertex are weighted during the Laplacian smoothing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LaplacianWeights {
+    /// Every neighbor has the same weight, only the connectivity of the mesh matters
+    Uniform,
+    /// Cotangent weights of the original mesh. Negative weights from obtuse triangles are clamped to zero.
+    Cotangent,
+    /// Floater's mean value weights of the original mesh. Always positive.
+    MeanValue,
 }
 
-/// Parametrizes the received mesh into a sphere and return the new set of vertices
-pub fn spherical_parametrization(mesh: &Mesh, iterations: u32) -> Vec<Vec3> {
-    let number_of_faces = mesh.faces_count();
-    let number_of_vertices = mesh.positions.len();
-    // E = [faces([1 2],:) faces([2 3],:) faces([3 1],:)];
-    let mut edges = mesh.edges();
-    assert!(edges.len() == number_of_faces * 3);
+impl fmt::Display for LaplacianWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LaplacianWeights::Uniform => write!(f, "uniform"),
+            LaplacianWeights::Cotangent => write!(f, "cotangent"),
+            LaplacianWeights::MeanValue => write!(f, "mean_value"),
+        }
+    }
+}
 
-    // E = [E E(2:-1:1,:)]
-    let edges_count = edges.len();
-    for i in 0..edges_count {
-        let current = edges[i];
-        edges.push(UVec2::new(current.y, current.x));
+impl FromStr for LaplacianWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(LaplacianWeights::Uniform),
+            "cotangent" => Ok(LaplacianWeights::Cotangent),
+            "mean_value" => Ok(LaplacianWeights::MeanValue),
+            _ => Err(format!(
+                "unknown weights {s}, expected uniform, cotangent or mean_value"
+            )),
+        }
     }
+}
 
-    let to_key = |x: u32, y: u32| -> u64 { (y as u64) * (number_of_vertices as u64) + (x as u64) };
+/// Where the vertices start on the sphere, before the smoothing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Initialization {
+    /// The direction of every vertex from the centroid. Only fold free for star-shaped meshes.
+    #[default]
+    Centroid,
+    /// Tutte's embedding into a disk, lifted onto the sphere between the two vertices that are farthest apart.
+    /// Fold free for any closed mesh of genus 0.
+    Tutte,
+    /// The smoothest eigenvectors of the Laplacian, which spread the vertices more evenly than Tutte's embedding.
+    /// Falls back to Tutte's embedding where they fold.
+    Spectral,
+}
 
-    // W = make_sparse( E(1,:), E(2,:), ones(size(E,2),1) );
-    let weights: HashMap<u64, u32> = edges
-        .into_iter()
-        .map(|edge| (to_key(edge.x, edge.y), 1))
-        .collect::<HashMap<u64, u32>>();
-
-    // d = full( sum(W,1) );
-    let mut duplicates: Vec<u64> = Vec::with_capacity(number_of_vertices);
-    for i in 0..number_of_vertices {
-        let mut current_sum = 0;
-        for j in 0..number_of_vertices {
-            let key = to_key(j as u32, i as u32);
-            if weights.get(&key) == Some(&1) {
-                current_sum += 1;
-            }
+impl fmt::Display for Initialization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Initialization::Centroid => write!(f, "centroid"),
+            Initialization::Tutte => write!(f, "tutte"),
+            Initialization::Spectral => write!(f, "spectral"),
         }
-        duplicates.push(current_sum);
     }
+}
 
-    // tW = iD * W;
-    let mut t_weights = Vec::new();
-    let mut t_weights_indexes = Vec::new();
-    for i in 0..number_of_vertices {
-        for j in 0..number_of_vertices {
-            let key = to_key(j as u32, i as u32);
-            if weights.get(&key) == Some(&1) {
-                t_weights_indexes.push(UVec2::new(i as u32, j as u32));
-                t_weights.push(1.0f32 / (duplicates[i] as f32));
-            }
+impl FromStr for Initialization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "centroid" => Ok(Initialization::Centroid),
+            "tutte" => Ok(Initialization::Tutte),
+            "spectral" => Ok(Initialization::Spectral),
+            _ => Err(format!(
+                "unknown initialization {s}, expected centroid, tutte or spectral"
+            )),
         }
     }
+}
+
+#[derive(Clone)]
+pub struct ParametrizationOptions {
+    pub initialization: Initialization,
+    pub weights: LaplacianWeights,
+    /// Stop smoothing once no vertex moves further than this on the sphere
+    pub tolerance: f32,
+    pub max_iterations: u32,
+}
+
+impl Default for ParametrizationOptions {
+    fn default() -> Self {
+        Self {
+            initialization: Initialization::Centroid,
+            weights: LaplacianWeights::Uniform,
+            tolerance: 1e-6,
+            max_iterations: 500,
+        }
+    }
+}
+
+pub struct Parametrization {
+    /// The mesh vertices, mapped onto the unit sphere
+    pub vertices: Vec<Vec3>,
+    /// Where the vertices started on the sphere. Differs from the requested initialization if that one folded.
+    pub initialization: Initialization,
+    /// How many smoothing iterations were actually done
+    pub iterations: u32,
+    /// Whether the smoothing stopped because the vertices moved less than the tolerance
+    pub converged: bool,
+    /// Indices of the triangles that ended up with the opposite orientation of the majority of the triangles
+    pub flipped_triangles: Vec<u32>,
+    /// How the area resampling changed the vertices, if it ran
+    pub resampling: Option<ResamplingReport>,
+}
+
+/// Parametrizes the received mesh into a sphere and return the new set of vertices
+pub fn spherical_parametrization(mesh: &Mesh, options: &ParametrizationOptions) -> Parametrization {
+    spherical_parametrization_with_progress(mesh, options, &|_| {})
+}
+
+/// [`spherical_parametrization`], reporting the adjacency build and every smoothing iteration
+pub(crate) fn spherical_parametrization_with_progress(
+    mesh: &Mesh,
+    options: &ParametrizationOptions,
+    progress: &(dyn Fn(Progress) + Sync),
+) -> Parametrization {
+    let number_of_vertices = mesh.positions.len();
+    progress(Progress::Adjacency);
+    // E = [faces([1 2],:) faces([2 3],:) faces([3 1],:)];
+    // W = make_sparse( E(1,:), E(2,:), ones(size(E,2),1) ), with E = [E E(2:-1:1,:)]
+    let adjacency = Adjacency::from_edges(number_of_vertices, &mesh.edges());
+    // tW = iD * W, with d = full( sum(W,1) );
+    let weights = laplacian_weights(mesh, &adjacency, options.weights);
 
     /*
         Perform Smoothing and Projection
     */
-    let mut parametrized_vertices = mesh.positions.clone();
-    // vertex1 = vertex1 - repmat( mean(vertex1,2), [1 n] );
-    let mean = parametrized_vertices.iter().sum::<Vec3>() / (number_of_vertices as f32);
-    for vertex in &mut parametrized_vertices {
-        *vertex -= mean;
-    }
-    // vertex1 = vertex1 ./ repmat( sqrt(sum(vertex1.^2,1)), [3 1] );
-    for vertex in &mut parametrized_vertices {
-        *vertex = vertex.normalize();
-    }
+    let (mut parametrized_vertices, initialization) =
+        initial_embedding(mesh, &adjacency, options.initialization);
 
-    println!("Spherical Parametrization Iterations {iterations}");
     let mut result = vec![Vec3::ZERO; number_of_vertices];
-    for i in 0..iterations {
-        for pos in t_weights_indexes.iter() {
-            result[pos.x as usize] += parametrized_vertices[pos.y as usize] * t_weights[i as usize];
-        }
-        for i in 0..number_of_vertices {
-            // @TODO: check this...
-            parametrized_vertices[i] = result[i].normalize_or(Vec3::ONE);
-        }
-        for item in &mut result {
-            *item = Vec3::ZERO;
-        }
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < options.max_iterations && !converged {
+        // vertex1 = vertex1*tW'
+        // Every vertex only reads the previous positions, so they are smoothed in parallel
+        result.par_iter_mut().enumerate().for_each(|(i, smoothed)| {
+            *smoothed = adjacency
+                .neighbors(i)
+                .iter()
+                .zip(&weights[adjacency.edge_range(i)])
+                .map(|(&j, &weight)| parametrized_vertices[j as usize] * weight)
+                .sum();
+        });
+        let max_movement = parametrized_vertices
+            .par_iter_mut()
+            .zip(&result)
+            .map(|(vertex, smoothed)| {
+                // @TODO: check this...
+                let projected = smoothed.normalize_or(Vec3::ONE);
+                let movement = vertex.distance(projected);
+                *vertex = projected;
+                movement
+            })
+            .reduce(|| 0.0f32, f32::max);
+        iterations += 1;
+        converged = max_movement < options.tolerance;
+        progress(Progress::Smoothing {
+            iteration: iterations,
+            max_iterations: options.max_iterations,
+        });
     }
 
-    parametrized_vertices
+    let flipped_triangles = find_flipped_triangles(mesh, &parametrized_vertices);
+    Parametrization {
+        vertices: parametrized_vertices,
+        initialization,
+        iterations,
+        converged,
+        flipped_triangles,
+        resampling: None,
+    }
 }
 
-/// This separates the triangles defined in the 'indexes' array in 8 groups, depending on where the triangle is located in the
-/// parametrized sphere.
-/// To avoid bugs later, when the triangle has vertices in multiple groups, we add it to all groups.
-pub fn separate_triangle_groups(mesh: &Mesh, parametrization: &[Vec3]) -> TriangleGroups {
-    let mut triangle_groups = {
-        const ARRAY_REPEAT_VALUE: Vec<UVec3> = Vec::new();
-        [ARRAY_REPEAT_VALUE; 8]
-    };
-
-    for triangle in mesh.triangles() {
-        let p1 = parametrization[triangle.x as usize];
-        let p2 = parametrization[triangle.y as usize];
-        let p3 = parametrization[triangle.z as usize];
-        let mut found_group = false;
-
-        let px_positive = p1.x >= 0.0 && p2.x >= 0.0 && p3.x >= 0.0;
-        let px_negative = p1.x <= 0.0 && p2.x <= 0.0 && p3.x <= 0.0;
-        let py_positive = p1.y >= 0.0 && p2.y >= 0.0 && p3.y >= 0.0;
-        let py_negative = p1.y <= 0.0 && p2.y <= 0.0 && p3.y <= 0.0;
-        let pz_positive = p1.z >= 0.0 && p2.z >= 0.0 && p3.z >= 0.0;
-        let pz_negative = p1.z <= 0.0 && p2.z <= 0.0 && p3.z <= 0.0;
-
-        if px_positive {
-            if py_positive {
-                if pz_positive {
-                    triangle_groups[0].push(triangle);
-                    found_group = true;
-                }
-                if pz_negative {
-                    triangle_groups[1].push(triangle);
-                    found_group = true;
-                }
+/// Computes the row-normalized weights of every directed edge in the adjacency
+pub(crate) fn laplacian_weights(
+    mesh: &Mesh,
+    adjacency: &Adjacency,
+    kind: LaplacianWeights,
+) -> Vec<f32> {
+    let positions = &mesh.positions;
+    let mut weights = vec![0.0f32; adjacency.edge_count()];
+    let mut add_weight = |from: u32, to: u32, weight: f32| {
+        if let Some(index) = adjacency.edge_index(from as usize, to as usize) {
+            if weight.is_finite() {
+                weights[index] += weight;
             }
-            if py_negative {
-                if pz_positive {
-                    triangle_groups[2].push(triangle);
-                    found_group = true;
-                }
-                if pz_negative {
-                    triangle_groups[3].push(triangle);
-                    found_group = true;
+        }
+    };
+    match kind {
+        LaplacianWeights::Uniform => {
+            // Left at zero, the normalization below then falls back to 1 / degree
+        }
+        LaplacianWeights::Cotangent => {
+            for triangle in mesh.triangles() {
+                // The angle at c is opposite to the edge a-b
+                for (a, b, c) in triangle_corners(triangle) {
+                    let ca = positions[a as usize] - positions[c as usize];
+                    let cb = positions[b as usize] - positions[c as usize];
+                    let cotangent = ca.dot(cb) / ca.cross(cb).length();
+                    add_weight(a, b, 0.5 * cotangent);
+                    add_weight(b, a, 0.5 * cotangent);
                 }
             }
         }
-        if px_negative {
-            if py_positive {
-                if pz_positive {
-                    triangle_groups[4].push(triangle);
-                    found_group = true;
-                }
-                if pz_negative {
-                    triangle_groups[5].push(triangle);
-                    found_group = true;
+        LaplacianWeights::MeanValue => {
+            for triangle in mesh.triangles() {
+                // The angle at a is next to the edges a-b and a-c
+                for (a, b, c) in triangle_corners(triangle) {
+                    let ab = positions[b as usize] - positions[a as usize];
+                    let ac = positions[c as usize] - positions[a as usize];
+                    let half_tangent = (ab.angle_between(ac) * 0.5).tan();
+                    add_weight(a, b, half_tangent / ab.length());
+                    add_weight(a, c, half_tangent / ac.length());
                 }
             }
-            if py_negative {
-                if pz_positive {
-                    triangle_groups[6].push(triangle);
-                    found_group = true;
-                }
-                if pz_negative {
-                    triangle_groups[7].push(triangle);
-                    found_group = true;
+        }
+    }
+
+    for i in 0..adjacency.vertex_count() {
+        let row = &mut weights[adjacency.edge_range(i)];
+        for weight in row.iter_mut() {
+            *weight = weight.max(0.0);
+        }
+        let sum: f32 = row.iter().sum();
+        if sum > 0.0 && sum.is_finite() {
+            row.iter_mut().for_each(|weight| *weight /= sum);
+        } else {
+            // Fall back to uniform weights when the geometry is too degenerate
+            let uniform = 1.0 / (row.len() as f32);
+            row.fill(uniform);
+        }
+    }
+    weights
+}
+
+/// The three corners of a triangle, each as (vertex, next vertex, previous vertex)
+fn triangle_corners(triangle: UVec3) -> [(u32, u32, u32); 3] {
+    [
+        (triangle.x, triangle.y, triangle.z),
+        (triangle.y, triangle.z, triangle.x),
+        (triangle.z, triangle.x, triangle.y),
+    ]
+}
+
+/// Finds the triangles whose orientation on the sphere disagrees with the majority of the triangles
+pub(crate) fn find_flipped_triangles(mesh: &Mesh, parametrization: &[Vec3]) -> Vec<u32> {
+    let orientations: Vec<f32> = mesh
+        .triangles()
+        .map(|triangle| {
+            let p1 = parametrization[triangle.x as usize];
+            let p2 = parametrization[triangle.y as usize];
+            let p3 = parametrization[triangle.z as usize];
+            (p2 - p1).cross(p3 - p1).dot(p1 + p2 + p3)
+        })
+        .collect();
+    let positive = orientations.iter().filter(|&&v| v > 0.0).count();
+    let negative = orientations.iter().filter(|&&v| v < 0.0).count();
+    let majority_sign = if positive >= negative { 1.0 } else { -1.0 };
+    orientations
+        .iter()
+        .enumerate()
+        .filter(|(_, &orientation)| orientation * majority_sign < 0.0)
+        .map(|(i, _)| i as u32)
+        .collect()
+}
+
+/// What to do with the pixels where the ray from the center of the sphere does not hit the mesh
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HoleFilling {
+    /// Use the closest point on the nearest triangle of the parametrized mesh
+    #[default]
+    NearestTriangle,
+    /// Smoothly interpolate the hole from the surrounding pixels
+    Diffusion,
+}
+
+impl fmt::Display for HoleFilling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HoleFilling::NearestTriangle => write!(f, "nearest_triangle"),
+            HoleFilling::Diffusion => write!(f, "diffusion"),
+        }
+    }
+}
+
+impl FromStr for HoleFilling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest_triangle" => Ok(HoleFilling::NearestTriangle),
+            "diffusion" => Ok(HoleFilling::Diffusion),
+            _ => Err(format!(
+                "unknown hole filling {s}, expected nearest_triangle or diffusion"
+            )),
+        }
+    }
+}
+
+/// Where every pixel of a geometry image lands on the mesh. Any per-vertex value can be baked into an image with it.
+pub struct SampleGrid {
+    pub width: u32,
+    pub height: u32,
+    samples: Vec<Option<SurfaceSample>>,
+    /// Whether the ray of the pixel hit the mesh, before the border pixels are mirrored
+    hits: Vec<bool>,
+    hole_filling: HoleFilling,
+    /// Whether the borders follow the octahedral layout, and get mirrored
+    octahedral: bool,
+}
+
+/// Checks that the image is at least [`Layout::min_size`]. Any larger size works, including even and non-square
+/// ones.
+pub fn check_size(size: (u32, u32), layout: &dyn Layout) -> Result<(), Mesh2GimError> {
+    let (min_width, min_height) = layout.min_size();
+    if size.0 >= min_width && size.1 >= min_height {
+        Ok(())
+    } else {
+        Err(Mesh2GimError::InvalidSize {
+            width: size.0,
+            height: size.1,
+            min_width,
+            min_height,
+        })
+    }
+}
+
+/// How the sphere is unfolded into the rectangle of a geometry image
+pub trait Layout: Sync {
+    /// The name that is written into the metadata, so the image can be folded back
+    fn name(&self) -> &'static str;
+
+    /// The direction from the center of the sphere that a pixel samples. Does not need to be normalized.
+    fn direction(&self, pixel: UVec2, size: (u32, u32)) -> Vec3;
+
+    /// Whether the image folds like an octahedron: the left and right columns are mirrored top to bottom, the top
+    /// and bottom rows are mirrored left to right, and the four corners are the same point
+    fn folds_borders(&self) -> bool {
+        false
+    }
+
+    /// The smallest image the layout fits in. The octahedral layouts need 2 pixels in each direction, so that the
+    /// border pixels can lie on the edges of the octahedron.
+    fn min_size(&self) -> (u32, u32) {
+        (2, 2)
+    }
+
+    /// The parts of the image that are not stitched to each other, empty if the whole image is one piece
+    fn charts(&self, _size: (u32, u32)) -> Vec<ChartRect> {
+        vec![]
+    }
+}
+
+/// The 8 triangles of an octahedron unfolded into the square, with the top half of the sphere in the diamond in the
+/// middle. Cheap to fold back, but the pixels bunch up near the corners of the octants.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Octahedral;
+
+impl Layout for Octahedral {
+    fn name(&self) -> &'static str {
+        "octahedral"
+    }
+
+    fn direction(&self, pixel: UVec2, size: (u32, u32)) -> Vec3 {
+        unfold_octahedron(octahedral_point(pixel, size))
+    }
+
+    fn folds_borders(&self) -> bool {
+        true
+    }
+}
+
+/// Clarberg's equal-area octahedral map. Folds like [`Octahedral`], but every pixel covers the same area of the
+/// sphere.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EqualAreaOctahedral;
+
+impl Layout for EqualAreaOctahedral {
+    fn name(&self) -> &'static str {
+        "equal_area_octahedral"
+    }
+
+    fn direction(&self, pixel: UVec2, size: (u32, u32)) -> Vec3 {
+        let point = octahedral_point(pixel, size);
+        let (u, v) = (point.x.abs(), point.y.abs());
+        // The distance from the diamond decides the height, the position along the diamond decides the angle
+        let signed_distance = 1.0 - (u + v);
+        let radius = 1.0 - signed_distance.abs();
+        let phi = if radius == 0.0 {
+            std::f32::consts::FRAC_PI_4
+        } else {
+            ((v - u) / radius + 1.0) * std::f32::consts::FRAC_PI_4
+        };
+        let z = (1.0 - radius * radius).copysign(signed_distance);
+        let scale = radius * (2.0 - radius * radius).max(0.0).sqrt();
+        Vec3::new(
+            phi.cos().copysign(point.x) * scale,
+            phi.sin().copysign(point.y) * scale,
+            z,
+        )
+    }
+
+    fn folds_borders(&self) -> bool {
+        true
+    }
+}
+
+/// The six faces of a cube in a 3 by 2 grid: +x, -x and +y in the top row, -y, +z and -z in the bottom row. Each
+/// face is a separate chart, so the mesh has seams along the edges of the cube.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CubeMap;
+
+impl CubeMap {
+    /// The pixels `[start, end)` of a face, out of `faces` faces along a side that is `size` pixels long. Never
+    /// empty for sizes of at least [`Layout::min_size`].
+    fn face_range(face: u32, faces: u32, size: u32) -> (u32, u32) {
+        (
+            (face * size).div_ceil(faces),
+            ((face + 1) * size).div_ceil(faces),
+        )
+    }
+}
+
+impl Layout for CubeMap {
+    fn name(&self) -> &'static str {
+        "cube_map"
+    }
+
+    fn direction(&self, pixel: UVec2, size: (u32, u32)) -> Vec3 {
+        let column = pixel.x * 3 / size.0;
+        let row = pixel.y * 2 / size.1;
+        let (x0, x1) = Self::face_range(column, 3, size.0);
+        let (y0, y1) = Self::face_range(row, 2, size.1);
+        // Pixel centers, in [-1, 1] across the face
+        let s = ((pixel.x - x0) as f32 + 0.5) / (x1 - x0) as f32 * 2.0 - 1.0;
+        let t = ((pixel.y - y0) as f32 + 0.5) / (y1 - y0) as f32 * 2.0 - 1.0;
+        match row * 3 + column {
+            0 => Vec3::new(1.0, -t, -s),
+            1 => Vec3::new(-1.0, -t, s),
+            2 => Vec3::new(s, 1.0, t),
+            3 => Vec3::new(s, -1.0, -t),
+            4 => Vec3::new(s, -t, 1.0),
+            _ => Vec3::new(-s, -t, -1.0),
+        }
+    }
+
+    fn charts(&self, size: (u32, u32)) -> Vec<ChartRect> {
+        (0..6)
+            .map(|face| {
+                let (x0, x1) = Self::face_range(face % 3, 3, size.0);
+                let (y0, y1) = Self::face_range(face / 3, 2, size.1);
+                ChartRect {
+                    x: x0,
+                    y: y0,
+                    width: x1 - x0,
+                    height: y1 - y0,
                 }
-            }
+            })
+            .collect()
+    }
+
+    /// At least one pixel for every face
+    fn min_size(&self) -> (u32, u32) {
+        (3, 2)
+    }
+}
+
+/// Every layout, for looking them up by name
+pub const LAYOUTS: [&dyn Layout; 3] = [&Octahedral, &EqualAreaOctahedral, &CubeMap];
+
+/// Finds the layout that has the given [`Layout::name`]
+pub fn layout_by_name(name: &str) -> Option<&'static dyn Layout> {
+    LAYOUTS.into_iter().find(|layout| layout.name() == name)
+}
+
+/// The position of a pixel in the [-1, 1]² square of the octahedral maps. The first and last rows and columns are
+/// right on the edges of the unfolded octahedron, so every border pixel has a twin on the other side of the fold,
+/// whatever the size is.
+fn octahedral_point(pixel: UVec2, size: (u32, u32)) -> Vec2 {
+    let y_normalized = scale_to_range(pixel.x as f32, 0.0, size.0 as f32 - 1.0, -1.0, 1.0);
+    let x_normalized = scale_to_range(pixel.y as f32, 0.0, size.1 as f32 - 1.0, -1.0, 1.0);
+    Vec2::new(x_normalized, y_normalized)
+}
+
+pub fn sample_grid(
+    mesh: &Mesh,
+    parametrization: &[Vec3],
+    size: (u32, u32),
+    hole_filling: HoleFilling,
+    layout: &dyn Layout,
+) -> Result<SampleGrid, Mesh2GimError> {
+    sample_grid_with_progress(mesh, parametrization, size, hole_filling, layout, &|_| {})
+}
+
+/// [`sample_grid`], reporting when the triangles are grouped and every finished row of pixels
+pub(crate) fn sample_grid_with_progress(
+    mesh: &Mesh,
+    parametrization: &[Vec3],
+    size: (u32, u32),
+    hole_filling: HoleFilling,
+    layout: &dyn Layout,
+    progress: &(dyn Fn(Progress) + Sync),
+) -> Result<SampleGrid, Mesh2GimError> {
+    check_size(size, layout)?;
+    progress(Progress::Grouping);
+    let sampler = SphereSampler::new(mesh.triangles().collect(), parametrization);
+
+    // Every pixel is independent, so the rows are sampled in parallel, a few per thread at a time. The border
+    // pixels are only mirrored once all of them are done, which keeps the result the same for any number of threads.
+    let rows_per_chunk = rayon::current_num_threads() as u32 * 2;
+    let mut samples = Vec::with_capacity((size.0 * size.1) as usize);
+    let mut hits = Vec::with_capacity((size.0 * size.1) as usize);
+    let mut start = 0;
+    while start < size.1 {
+        let end = (start + rows_per_chunk).min(size.1);
+        let chunk: Vec<(Option<SurfaceSample>, bool)> = (start..end)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                let sampler = &sampler;
+                (0..size.0).map(move |x| {
+                    let point_in_space = layout.direction(UVec2::new(x, y), size);
+                    let sample = sampler.hit(point_in_space);
+                    let hit = sample.is_some();
+                    let sample = match hole_filling {
+                        HoleFilling::NearestTriangle => {
+                            sample.or_else(|| sampler.nearest(point_in_space))
+                        }
+                        HoleFilling::Diffusion => sample,
+                    };
+                    (sample, hit)
+                })
+            })
+            .collect();
+        for (sample, hit) in chunk {
+            samples.push(sample);
+            hits.push(hit);
         }
-        if !found_group {
-            triangle_groups[0].push(triangle);
-            triangle_groups[1].push(triangle);
-            triangle_groups[2].push(triangle);
-            triangle_groups[3].push(triangle);
-            triangle_groups[4].push(triangle);
-            triangle_groups[5].push(triangle);
-            triangle_groups[6].push(triangle);
-            triangle_groups[7].push(triangle);
+        progress(Progress::Rasterization {
+            row: end,
+            rows: size.1,
+        });
+        start = end;
+    }
+
+    Ok(SampleGrid {
+        width: size.0,
+        height: size.1,
+        samples,
+        hits,
+        hole_filling,
+        octahedral: layout.folds_borders(),
+    })
+}
+
+impl SampleGrid {
+    /// A grid with any layout. Pixels without a sample are left at zero.
+    pub(crate) fn from_samples(
+        size: (u32, u32),
+        samples: Vec<Option<SurfaceSample>>,
+        hits: Vec<bool>,
+    ) -> Self {
+        SampleGrid {
+            width: size.0,
+            height: size.1,
+            samples,
+            hits,
+            hole_filling: HoleFilling::NearestTriangle,
+            octahedral: false,
+        }
+    }
+
+    /// Moves every sample to the closest point on another mesh, which has the same shape but other triangles. Lets
+    /// a decimated mesh be parametrized, while the pixels still sample the full mesh it was made from.
+    pub(crate) fn project_onto(&self, mesh: &Mesh, target: &Mesh) -> SampleGrid {
+        let sampler = SurfaceSampler::new(target.triangles().collect(), &target.positions);
+        let samples = self
+            .samples
+            .par_iter()
+            .map(|sample| {
+                sample.and_then(|sample| sampler.nearest(sample.interpolate(&mesh.positions)))
+            })
+            .collect();
+        SampleGrid {
+            samples,
+            hits: self.hits.clone(),
+            ..*self
         }
     }
-    TriangleGroups {
-        groups: triangle_groups,
+
+    /// Interpolates the per-vertex values at every pixel, and fills the holes
+    pub fn bake<T>(&self, values: &[T]) -> Vec<T>
+    where
+        T: Copy + Default + Add<Output = T> + Mul<f32, Output = T>,
+    {
+        let size = (self.width, self.height);
+        let mut pixels: Vec<T> = self
+            .samples
+            .iter()
+            .map(|sample| {
+                sample
+                    .map(|sample| sample.interpolate(values))
+                    .unwrap_or_default()
+            })
+            .collect();
+        if self.hole_filling == HoleFilling::Diffusion {
+            diffuse_holes(&mut pixels, &self.hits, size);
+        }
+        if self.octahedral {
+            mirror_borders(&mut pixels, size);
+        }
+        pixels
+    }
+
+    /// Whether each pixel was sampled from the mesh, or filled in because the mesh has a hole there
+    pub fn valid(&self) -> Vec<bool> {
+        let mut valid = self.hits.clone();
+        if self.octahedral {
+            mirror_borders(&mut valid, (self.width, self.height));
+        }
+        valid
+    }
+
+    pub fn to_image(&self, positions: &[Vec3]) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self.bake(positions),
+            valid: self.valid(),
+        }
+    }
+
+    pub fn to_attribute_image(&self, attribute: &Attribute) -> AttributeImage {
+        let values = match &attribute.values {
+            AttributeValues::Floats(values) => AttributeValues::Floats(self.bake(values)),
+            AttributeValues::Vec2s(values) => AttributeValues::Vec2s(self.bake(values)),
+            AttributeValues::Vec3s(values) => AttributeValues::Vec3s(self.bake(values)),
+            AttributeValues::Vec4s(values) => AttributeValues::Vec4s(self.bake(values)),
+        };
+        AttributeImage {
+            name: attribute.name.clone(),
+            width: self.width,
+            height: self.height,
+            values,
+        }
     }
 }
 
 pub fn to_image(
     mesh: &Mesh,
     parametrization: &[Vec3],
-    groups: TriangleGroups,
     size: (u32, u32),
-) -> Image {
-    // must be greater than 1 and odd
-    assert!(size.0 > 1 && size.1 > 1 && size.0 % 2 == 1 && size.1 % 2 == 1);
-    let mut gim_data = vec![Vec3::ZERO; (size.0 * size.1) as usize];
-
-    let mut pixel_color = Vec3::ZERO;
-
-    let mut error_positions = Vec::new();
-    for y in 0..size.1 {
-        for x in 0..size.0 {
-            let y_normalized = scale_to_range(x as f32, 0.0, size.0 as f32 - 1.0, -1.0, 1.0);
-            let x_normalized = scale_to_range(y as f32, 0.0, size.1 as f32 - 1.0, -1.0, 1.0);
-            let (point_in_space, selected_triangle_group) =
-                select_triangle_group(Vec2::new(x_normalized, y_normalized), &groups);
-
-            pixel_color = match get_gim_pixel_by_sampling_mesh(
-                &mesh.positions,
-                parametrization,
-                selected_triangle_group,
-                point_in_space,
-            ) {
-                Some(color) => color,
-                None => {
-                    error_positions.push((x, y));
-                    // @TODO: What to do when we have a hole in the mesh?
-                    // For now, let's keep the same color as the previous pixel... this way we may visually 'hide' the holes
-                    pixel_color
-                }
-            };
+    hole_filling: HoleFilling,
+    layout: &dyn Layout,
+) -> Result<Image, Mesh2GimError> {
+    Ok(sample_grid(mesh, parametrization, size, hole_filling, layout)?.to_image(&mesh.positions))
+}
 
-            // Here, if we are dealing with a border pixel, we manually copy it to all its matches.
-            // Theoretically, the algorithm above already takes care of it, but we need to recopy here to avoid
-            // problems because of floating-point precision (xNormalized and yNormalized varies a bit and it causes
-            // inconsistency in the sampling)
-            let gim_size = size.0;
-            assert!(gim_size == size.1, "GIM size must be a square");
-            if x == 0 || x == size.0 - 1 {
-                gim_data[((gim_size - y - 1) * gim_size + x) as usize] = pixel_color;
+/// Here, if we are dealing with a border pixel, we manually copy it to all its matches.
+/// Theoretically, the sampling already takes care of it, but we need to recopy here to avoid
+/// problems because of floating-point precision (xNormalized and yNormalized varies a bit and it causes
+/// inconsistency in the sampling)
+pub(crate) fn mirror_borders<T: Copy>(data: &mut [T], size: (u32, u32)) {
+    let (width, height) = size;
+    let index = |x: u32, y: u32| (y * width + x) as usize;
+    let samples = data.to_vec();
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = samples[index(x, y)];
+            if x == 0 || x == width - 1 {
+                data[index(x, height - y - 1)] = pixel;
             }
-            if y == 0 || y == size.1 - 1 {
-                gim_data[(y * gim_size + (gim_size - x - 1)) as usize] = pixel_color;
+            if y == 0 || y == height - 1 {
+                data[index(width - x - 1, y)] = pixel;
             }
-            if (x == 0 && y == 0)
-                || (x == size.0 - 1 && y == size.1 - 1)
-                || (x == 0 && y == size.1 - 1)
-                || (x == size.0 - 1 && y == 0)
-            {
-                gim_data[((gim_size - y - 1) * gim_size + (gim_size - x - 1)) as usize] =
-                    pixel_color;
+            if (x == 0 || x == width - 1) && (y == 0 || y == height - 1) {
+                data[index(width - x - 1, height - y - 1)] = pixel;
             }
-
-            gim_data[(y * gim_size + x) as usize] = pixel_color;
+            data[index(x, y)] = pixel;
         }
     }
+}
+
+/// How often the filled in holes are smoothed after growing into them
+const DIFFUSION_ITERATIONS: u32 = 64;
 
-    if !error_positions.is_empty() {
-        eprintln!(
-            "There are {} error positions in the GIM where we couldn't sample the mesh.",
-            error_positions.len()
-        );
+/// Fills the invalid pixels by growing the valid pixels into the holes, and then smoothing the holes
+fn diffuse_holes<T>(pixels: &mut [T], valid: &[bool], size: (u32, u32))
+where
+    T: Copy + Default + Add<Output = T> + Mul<f32, Output = T>,
+{
+    if !valid.contains(&true) {
+        return;
     }
+    let (width, height) = (size.0 as usize, size.1 as usize);
+    let neighbors = |i: usize| {
+        let (x, y) = (i % width, i / width);
+        [
+            (x > 0).then(|| i - 1),
+            (x + 1 < width).then(|| i + 1),
+            (y > 0).then(|| i - width),
+            (y + 1 < height).then(|| i + width),
+        ]
+        .into_iter()
+        .flatten()
+    };
 
-    Image {
-        width: size.0,
-        height: size.1,
-        pixels: gim_data,
+    // Every pass fills the hole pixels that are next to an already known pixel
+    let mut known = valid.to_vec();
+    loop {
+        let mut next_known = known.clone();
+        let mut changed = false;
+        for i in (0..pixels.len()).filter(|&i| !known[i]) {
+            let (sum, count) = neighbors(i)
+                .filter(|&j| known[j])
+                .fold((T::default(), 0), |(sum, count), j| {
+                    (sum + pixels[j], count + 1)
+                });
+            if count > 0 {
+                pixels[i] = sum * (1.0 / count as f32);
+                next_known[i] = true;
+                changed = true;
+            }
+        }
+        known = next_known;
+        if !changed {
+            break;
+        }
+    }
+
+    // Relax the holes, while keeping the valid pixels fixed
+    let holes: Vec<usize> = (0..pixels.len()).filter(|&i| !valid[i]).collect();
+    for _ in 0..DIFFUSION_ITERATIONS {
+        for &i in &holes {
+            let (sum, count) = neighbors(i).fold((T::default(), 0), |(sum, count), j| {
+                (sum + pixels[j], count + 1)
+            });
+            pixels[i] = sum * (1.0 / count as f32);
+        }
     }
 }
 
-fn select_triangle_group(point: Vec2, groups: &TriangleGroups) -> (Vec3, &Vec<UVec3>) {
+/// Maps a point in the [-1, 1]² square onto the octahedron, by unfolding the 8 faces of the octahedron into the square
+fn unfold_octahedron(point: Vec2) -> Vec3 {
     if point.y >= 0.0 && point.x >= 0.0 {
         if point.x + point.y >= 1.0 {
             // TOP-RIGHT BLUE (1)
@@ -254,14 +773,11 @@ fn select_triangle_group(point: Vec2, groups: &TriangleGroups) -> (Vec3, &Vec<UV
                 Vec2::new(1.0, 1.0),
                 point,
             );
-            (
-                barycentric_to_euler(
-                    Vec3::new(0.0, 1.0, 0.0),
-                    Vec3::new(1.0, 0.0, 0.0),
-                    Vec3::new(0.0, 0.0, -1.0),
-                    barycentric,
-                ),
-                &groups.groups[1],
+            barycentric_to_euler(
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                barycentric,
             )
         } else {
             // TOP-RIGHT RED (0)
@@ -271,14 +787,11 @@ fn select_triangle_group(point: Vec2, groups: &TriangleGroups) -> (Vec3, &Vec<UV
                 Vec2::new(0.0, 0.0),
                 point,
             );
-            (
-                barycentric_to_euler(
-                    Vec3::new(1.0, 0.0, 0.0),
-                    Vec3::new(0.0, 1.0, 0.0),
-                    Vec3::new(0.0, 0.0, 1.0),
-                    barycentric,
-                ),
-                &groups.groups[0],
+            barycentric_to_euler(
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                barycentric,
             )
         }
     } else if point.y >= 0.0 && point.x <= 0.0 {
@@ -290,14 +803,11 @@ fn select_triangle_group(point: Vec2, groups: &TriangleGroups) -> (Vec3, &Vec<UV
                 Vec2::new(-1.0, 1.0),
                 point,
             );
-            (
-                barycentric_to_euler(
-                    Vec3::new(0.0, 1.0, 0.0),
-                    Vec3::new(-1.0, 0.0, 0.0),
-                    Vec3::new(0.0, 0.0, -1.0),
-                    barycentric,
-                ),
-                &groups.groups[5],
+            barycentric_to_euler(
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                barycentric,
             )
         } else {
             // TOP-LEFT GREEN (4)
@@ -307,14 +817,11 @@ fn select_triangle_group(point: Vec2, groups: &TriangleGroups) -> (Vec3, &Vec<UV
                 Vec2::new(0.0, 0.0),
                 point,
             );
-            (
-                barycentric_to_euler(
-                    Vec3::new(-1.0, 0.0, 0.0),
-                    Vec3::new(0.0, 1.0, 0.0),
-                    Vec3::new(0.0, 0.0, 1.0),
-                    barycentric,
-                ),
-                &groups.groups[4],
+            barycentric_to_euler(
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                barycentric,
             )
         }
     } else if point.y <= 0.0 && point.x >= 0.0 {
@@ -326,14 +833,11 @@ fn select_triangle_group(point: Vec2, groups: &TriangleGroups) -> (Vec3, &Vec<UV
                 Vec2::new(0.0, 0.0),
                 point,
             );
-            (
-                barycentric_to_euler(
-                    Vec3::new(1.0, 0.0, 0.0),
-                    Vec3::new(0.0, -1.0, 0.0),
-                    Vec3::new(0.0, 0.0, 1.0),
-                    barycentric,
-                ),
-                &groups.groups[2],
+            barycentric_to_euler(
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                barycentric,
             )
         } else {
             // BOTTOM-RIGHT GREEN (3)
@@ -343,14 +847,11 @@ fn select_triangle_group(point: Vec2, groups: &TriangleGroups) -> (Vec3, &Vec<UV
                 Vec2::new(1.0, -1.0),
                 point,
             );
-            (
-                barycentric_to_euler(
-                    Vec3::new(0.0, -1.0, 0.0),
-                    Vec3::new(1.0, 0.0, 0.0),
-                    Vec3::new(0.0, 0.0, -1.0),
-                    barycentric,
-                ),
-                &groups.groups[3],
+            barycentric_to_euler(
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                barycentric,
             )
         }
     } else {
@@ -362,14 +863,11 @@ fn select_triangle_group(point: Vec2, groups: &TriangleGroups) -> (Vec3, &Vec<UV
                 Vec2::new(0.0, 0.0),
                 point,
             );
-            (
-                barycentric_to_euler(
-                    Vec3::new(-1.0, 0.0, 0.0),
-                    Vec3::new(0.0, -1.0, 0.0),
-                    Vec3::new(0.0, 0.0, 1.0),
-                    barycentric,
-                ),
-                &groups.groups[6],
+            barycentric_to_euler(
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                barycentric,
             )
         } else {
             // BOTTOM-LEFT RED (7)
@@ -379,35 +877,22 @@ fn select_triangle_group(point: Vec2, groups: &TriangleGroups) -> (Vec3, &Vec<UV
                 Vec2::new(-1.0, -1.0),
                 point,
             );
-            (
-                barycentric_to_euler(
-                    Vec3::new(0.0, -1.0, 0.0),
-                    Vec3::new(-1.0, 0.0, 0.0),
-                    Vec3::new(0.0, 0.0, -1.0),
-                    barycentric,
-                ),
-                &groups.groups[7],
+            barycentric_to_euler(
+                Vec3::new(0.0, -1.0, 0.0),
+                Vec3::new(-1.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                barycentric,
             )
         }
     }
 }
 
-fn convert_to_barycentric_3d(a: Vec3, b: Vec3, c: Vec3, p: Vec3) -> Vec3 {
-    let v0 = b - a;
-    let v1 = c - a;
-    let v2 = p - a;
-    let d00 = v0.dot(v0);
-    let d01 = v0.dot(v1);
-    let d11 = v1.dot(v1);
-    let d20 = v2.dot(v0);
-    let d21 = v2.dot(v1);
-    let denom = d00 * d11 - d01 * d01;
-
-    let mut result = Vec3::ZERO;
-    result.y = (d11 * d20 - d01 * d21) / denom;
-    result.z = (d00 * d21 - d01 * d20) / denom;
-    result.x = 1.0 - result.y - result.z;
-    result
+/// The number of the triangle of the octahedral image that a direction on the sphere lands in, as numbered in
+/// [`unfold_octahedron`]: 4 for a negative x, plus 2 for a negative y, plus 1 for a negative z
+pub(crate) fn octant_group(direction: Vec3) -> usize {
+    4 * (direction.x < 0.0) as usize
+        + 2 * (direction.y < 0.0) as usize
+        + (direction.z < 0.0) as usize
 }
 
 fn convert_to_barycentric_2d(a: Vec2, b: Vec2, c: Vec2, p: Vec2) -> Vec3 {
@@ -436,77 +921,345 @@ fn scale_to_range(value: f32, in_max: f32, in_min: f32, out_min: f32, out_max: f
     value.remap(in_min, in_max, out_min, out_max)
 }
 
-fn intersection_ray_triangle(
-    ray_origin: Vec3,
-    ray_vector: Vec3,
-    triangle: (Vec3, Vec3, Vec3),
-) -> Option<Vec3> {
-    const EPSILON: f32 = 0.0000001;
-    let (vertex0, vertex1, vertex2) = triangle;
-    let edge1 = vertex1 - vertex0;
-    let edge2 = vertex2 - vertex0;
-    let h = ray_vector.cross(edge2);
-    let a = edge1.dot(h);
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::test_meshes::{horseshoe, subdivided_octahedron};
+
+    /// The original dense implementation, kept as a reference for the sparse one
+    fn dense_spherical_parametrization(mesh: &Mesh, iterations: u32) -> Vec<Vec3> {
+        let number_of_vertices = mesh.positions.len();
+        let mut edges = mesh.edges();
+        let edges_count = edges.len();
+        for i in 0..edges_count {
+            let current = edges[i];
+            edges.push(UVec2::new(current.y, current.x));
+        }
+        let to_key =
+            |x: u32, y: u32| -> u64 { (y as u64) * (number_of_vertices as u64) + (x as u64) };
+        let weights: HashMap<u64, u32> = edges
+            .into_iter()
+            .map(|edge| (to_key(edge.x, edge.y), 1))
+            .collect();
+
+        let mut duplicates = Vec::with_capacity(number_of_vertices);
+        for i in 0..number_of_vertices {
+            let mut current_sum = 0;
+            for j in 0..number_of_vertices {
+                if weights.get(&to_key(j as u32, i as u32)) == Some(&1) {
+                    current_sum += 1;
+                }
+            }
+            duplicates.push(current_sum);
+        }
+
+        let mut t_weights = Vec::new();
+        let mut t_weights_indexes = Vec::new();
+        for (i, duplicate) in duplicates.iter().enumerate() {
+            for j in 0..number_of_vertices {
+                if weights.get(&to_key(j as u32, i as u32)) == Some(&1) {
+                    t_weights_indexes.push(UVec2::new(i as u32, j as u32));
+                    t_weights.push(1.0f32 / (*duplicate as f32));
+                }
+            }
+        }
 
-    if a > -EPSILON && a < EPSILON {
-        return None;
+        let mut parametrized_vertices = mesh.positions.clone();
+        let mean = parametrized_vertices.iter().sum::<Vec3>() / (number_of_vertices as f32);
+        for vertex in &mut parametrized_vertices {
+            *vertex = (*vertex - mean).normalize();
+        }
+        let mut result = vec![Vec3::ZERO; number_of_vertices];
+        for i in 0..iterations {
+            for pos in t_weights_indexes.iter() {
+                result[pos.x as usize] +=
+                    parametrized_vertices[pos.y as usize] * t_weights[i as usize];
+            }
+            for (vertex, item) in parametrized_vertices.iter_mut().zip(&result) {
+                *vertex = item.normalize_or(Vec3::ONE);
+            }
+            result.fill(Vec3::ZERO);
+        }
+        parametrized_vertices
     }
 
-    let f = 1.0 / a;
-    let s = ray_origin - vertex0;
-    let u = f * s.dot(h);
+    #[test]
+    fn test_sparse_matches_dense_parametrization() {
+        let mut mesh = subdivided_octahedron(2, Vec3::new(1.0, 2.0, 3.0));
+        // Move the mesh off-center, so that the centering step matters
+        for (i, position) in mesh.positions.iter_mut().enumerate() {
+            *position += Vec3::new(0.5, -0.25, (i % 3) as f32 * 0.1);
+        }
 
-    if u < 0.0 || u > 1.0 {
-        return None;
+        let options = ParametrizationOptions {
+            tolerance: 0.0,
+            max_iterations: 50,
+            ..Default::default()
+        };
+        let sparse = spherical_parametrization(&mesh, &options).vertices;
+        let dense = dense_spherical_parametrization(&mesh, 50);
+        assert_eq!(sparse.len(), dense.len());
+        for (a, b) in sparse.iter().zip(&dense) {
+            assert!(a.abs_diff_eq(*b, 1e-5), "{a} != {b}");
+        }
     }
 
-    let q = s.cross(edge1);
-    let v = f * ray_vector.dot(q);
+    #[test]
+    fn test_initializations_of_a_bent_mesh() {
+        let mesh = horseshoe(4);
+        let flipped = |initialization| {
+            let options = ParametrizationOptions {
+                initialization,
+                ..Default::default()
+            };
+            spherical_parametrization(&mesh, &options)
+                .flipped_triangles
+                .len()
+        };
+        assert!(flipped(Initialization::Centroid) > 0);
+        assert_eq!(flipped(Initialization::Tutte), 0);
+        assert_eq!(flipped(Initialization::Spectral), 0);
+    }
 
-    if v < 0.0 || u + v > 1.0 {
-        return None;
+    #[test]
+    fn test_parametrization_options() {
+        let mesh = subdivided_octahedron(3, Vec3::new(1.0, 0.5, 2.0));
+        for weights in [
+            LaplacianWeights::Uniform,
+            LaplacianWeights::Cotangent,
+            LaplacianWeights::MeanValue,
+        ] {
+            let options = ParametrizationOptions {
+                weights,
+                tolerance: 1e-4,
+                max_iterations: 1000,
+                ..Default::default()
+            };
+            let parametrization = spherical_parametrization(&mesh, &options);
+            assert!(parametrization.converged, "{weights:?} did not converge");
+            assert!(parametrization.iterations < options.max_iterations);
+            assert!(
+                parametrization.flipped_triangles.is_empty(),
+                "{weights:?} flipped triangles"
+            );
+            for vertex in &parametrization.vertices {
+                assert!((vertex.length() - 1.0).abs() < 1e-4);
+            }
+        }
     }
 
-    // At this stage we can compute t to find out where the intersection point is on the line.
-    let t = f * edge2.dot(q);
+    #[test]
+    fn test_flipped_triangles() {
+        let mut mesh = subdivided_octahedron(1, Vec3::ONE);
+        let parametrization = spherical_parametrization(&mesh, &Default::default());
+        assert!(parametrization.flipped_triangles.is_empty());
 
-    if t > EPSILON {
-        Some(ray_origin + ray_vector * t)
-    } else {
-        None
+        // Reverse the winding of a single triangle
+        mesh.indices.swap(3, 4);
+        let flipped = find_flipped_triangles(&mesh, &parametrization.vertices);
+        assert_eq!(flipped, vec![1]);
     }
-}
 
-fn get_gim_pixel_by_sampling_mesh(
-    original_vertices: &[Vec3],
-    parametrized_vertices: &[Vec3],
-    indices: &Vec<UVec3>,
-    point_in_space: Vec3,
-) -> Option<Vec3> {
-    let ray_vector = match point_in_space.try_normalize() {
-        Some(v) => v,
-        None => {
-            eprintln!("Point in space is the zero vector");
-            Vec3::ZERO
+    #[test]
+    fn test_hole_filling() {
+        let sphere = subdivided_octahedron(2, Vec3::ONE);
+        let parametrization = spherical_parametrization(&sphere, &Default::default()).vertices;
+        let mut mesh = subdivided_octahedron(2, Vec3::ONE);
+        // Cut a hole into the mesh
+        mesh.indices.drain(0..3 * 8);
+
+        for hole_filling in [HoleFilling::NearestTriangle, HoleFilling::Diffusion] {
+            let image =
+                to_image(&mesh, &parametrization, (33, 33), hole_filling, &Octahedral).unwrap();
+            let invalid = image.valid.iter().filter(|valid| !**valid).count();
+            assert!(invalid > 0, "{hole_filling:?} has no holes");
+            assert!(invalid < image.pixels.len() / 4);
+            for (pixel, valid) in image.pixels.iter().zip(&image.valid) {
+                assert!(pixel.is_finite());
+                if !valid {
+                    // Holes are filled with something close to the surrounding surface
+                    assert!(pixel.length() > 0.5, "{hole_filling:?} left {pixel}");
+                }
+            }
         }
-    };
-    for triangle in indices {
-        let v1 = parametrized_vertices[triangle.x as usize];
-        let v2 = parametrized_vertices[triangle.y as usize];
-        let v3 = parametrized_vertices[triangle.z as usize];
-
-        if let Some(intersection_point) =
-            intersection_ray_triangle(Vec3::ZERO, ray_vector, (v1, v2, v3))
-        {
-            let barycentric_coordinates = convert_to_barycentric_3d(v1, v2, v3, intersection_point);
-            let original_vertices = Mat3::from_cols(
-                original_vertices[triangle.x as usize],
-                original_vertices[triangle.y as usize],
-                original_vertices[triangle.z as usize],
+    }
+
+    #[test]
+    fn test_border_symmetry() {
+        let mesh = subdivided_octahedron(3, Vec3::new(1.0, 2.0, 3.0));
+        let parametrization = spherical_parametrization(&mesh, &Default::default()).vertices;
+        for (width, height) in [(17, 17), (16, 16), (24, 9)] {
+            let image = to_image(
+                &mesh,
+                &parametrization,
+                (width, height),
+                HoleFilling::default(),
+                &Octahedral,
+            )
+            .unwrap();
+            let pixel = |x: u32, y: u32| image.pixels[(y * width + x) as usize];
+            for y in 0..height {
+                assert_eq!(pixel(0, y), pixel(0, height - 1 - y));
+                assert_eq!(pixel(width - 1, y), pixel(width - 1, height - 1 - y));
+            }
+            for x in 0..width {
+                assert_eq!(pixel(x, 0), pixel(width - 1 - x, 0));
+                assert_eq!(pixel(x, height - 1), pixel(width - 1 - x, height - 1));
+            }
+            assert_eq!(pixel(0, 0), pixel(width - 1, height - 1));
+            // The center of the image is the pole of the octahedron at +z
+            if width % 2 == 1 && height % 2 == 1 {
+                let center = pixel(width / 2, height / 2);
+                assert!(
+                    center.abs_diff_eq(Vec3::new(0.0, 0.0, 3.0), 1e-3),
+                    "{center}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_layouts() {
+        let size = (65, 65);
+        let directions = |layout: &dyn Layout| -> Vec<Vec3> {
+            (0..size.1)
+                .flat_map(|y| (0..size.0).map(move |x| UVec2::new(x, y)))
+                .map(|pixel| layout.direction(pixel, size).normalize())
+                .collect()
+        };
+        // 5% of the sphere is above z = 0.9, the octahedral map crowds more pixels into it
+        let cap = |directions: &[Vec3]| {
+            directions.iter().filter(|d| d.z > 0.9).count() as f32 / directions.len() as f32
+        };
+        let octahedral = directions(&Octahedral);
+        let equal_area = directions(&EqualAreaOctahedral);
+        assert!((cap(&equal_area) - 0.05).abs() < 0.005);
+        assert!((cap(&octahedral) - 0.05).abs() > 0.01);
+        for directions in [&octahedral, &equal_area] {
+            let direction = |x: u32, y: u32| directions[(y * size.0 + x) as usize];
+            for i in 0..size.0 {
+                assert!(direction(0, i).abs_diff_eq(direction(0, size.1 - 1 - i), 1e-5));
+                assert!(direction(i, 0).abs_diff_eq(direction(size.0 - 1 - i, 0), 1e-5));
+            }
+            assert!(direction(32, 32).abs_diff_eq(Vec3::Z, 1e-5));
+            assert!(direction(0, 0).abs_diff_eq(Vec3::NEG_Z, 1e-5));
+        }
+
+        let cube = (96, 64);
+        let charts = CubeMap.charts(cube);
+        assert_eq!(charts.len(), 6);
+        let axes = [
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ];
+        for (chart, axis) in charts.iter().zip(axes) {
+            assert_eq!((chart.width, chart.height), (32, 32));
+            let center = UVec2::new(chart.x + chart.width / 2, chart.y + chart.height / 2);
+            assert!(CubeMap.direction(center, cube).normalize().dot(axis) > 0.99);
+        }
+        for layout in LAYOUTS {
+            assert_eq!(layout_by_name(layout.name()).unwrap().name(), layout.name());
+        }
+    }
+
+    #[test]
+    fn test_octant_groups() {
+        // A point inside each triangle of the unfolded square, in the order of their numbers
+        let points = [
+            Vec2::new(0.25, 0.25),
+            Vec2::new(0.75, 0.75),
+            Vec2::new(0.25, -0.25),
+            Vec2::new(0.75, -0.75),
+            Vec2::new(-0.25, 0.25),
+            Vec2::new(-0.75, 0.75),
+            Vec2::new(-0.25, -0.25),
+            Vec2::new(-0.75, -0.75),
+        ];
+        for (group, point) in points.into_iter().enumerate() {
+            assert_eq!(octant_group(unfold_octahedron(point)), group);
+        }
+    }
+
+    #[test]
+    fn test_cube_map_min_size() {
+        let min_size = CubeMap.min_size();
+        assert!(check_size(min_size, &CubeMap).is_ok());
+        assert!(check_size((2, 2), &CubeMap).is_err());
+        assert!(check_size((2, 2), &Octahedral).is_ok());
+        for size in [min_size, (4, 3), (5, 2)] {
+            let charts = CubeMap.charts(size);
+            assert!(charts
+                .iter()
+                .all(|chart| chart.width > 0 && chart.height > 0));
+            // The faces cover the image without gaps
+            let area: u32 = charts.iter().map(|chart| chart.width * chart.height).sum();
+            assert_eq!(area, size.0 * size.1);
+        }
+
+        let mut sphere = subdivided_octahedron(2, Vec3::ONE);
+        // Keep the vertices off the axes, which the 1x1 faces sample exactly
+        let rotation = glam::Quat::from_euler(glam::EulerRot::XYZ, 0.2, 0.3, 0.1);
+        for position in &mut sphere.positions {
+            *position = rotation * *position;
+        }
+        let grid = sample_grid(
+            &sphere,
+            &sphere.positions,
+            min_size,
+            HoleFilling::Diffusion,
+            &CubeMap,
+        )
+        .unwrap();
+        // Every face samples the side of the sphere it looks at
+        let image = grid.to_image(&sphere.positions);
+        for (pixel, face) in image.pixels.iter().zip(0..) {
+            let direction = CubeMap.direction(UVec2::new(face % 3, face / 3), min_size);
+            assert!(
+                pixel.normalize().dot(direction.normalize()) > 0.95,
+                "{pixel}"
             );
-            // Scale each vertex by its barycentric coordinate and sum them
-            return Some(original_vertices * barycentric_coordinates);
         }
     }
-    None
+
+    #[test]
+    fn test_attribute_images_are_aligned() {
+        let mut mesh = subdivided_octahedron(2, Vec3::new(1.0, 2.0, 3.0));
+        let normals = mesh.positions.iter().map(|p| *p * 2.0).collect();
+        let heights = mesh.positions.iter().map(|p| p.y).collect();
+        mesh.attributes = vec![
+            Attribute {
+                name: Attribute::NORMAL.to_string(),
+                values: AttributeValues::Vec3s(normals),
+            },
+            Attribute {
+                name: "height".to_string(),
+                values: AttributeValues::Floats(heights),
+            },
+        ];
+        let parametrization = spherical_parametrization(&mesh, &Default::default()).vertices;
+        for hole_filling in [HoleFilling::NearestTriangle, HoleFilling::Diffusion] {
+            let grid =
+                sample_grid(&mesh, &parametrization, (17, 17), hole_filling, &Octahedral).unwrap();
+            let image = grid.to_image(&mesh.positions);
+            let normal_image = grid.to_attribute_image(&mesh.attributes[0]);
+            let height_image = grid.to_attribute_image(&mesh.attributes[1]);
+            assert_eq!(normal_image.name, Attribute::NORMAL);
+            assert_eq!((normal_image.width, normal_image.height), (17, 17));
+            let (AttributeValues::Vec3s(normals), AttributeValues::Floats(heights)) =
+                (&normal_image.values, &height_image.values)
+            else {
+                panic!("Attribute images changed their type");
+            };
+            for ((position, normal), height) in image.pixels.iter().zip(normals).zip(heights) {
+                assert!((*position * 2.0).abs_diff_eq(*normal, 1e-5));
+                assert!((position.y - height).abs() < 1e-5);
+            }
+        }
+    }
 }
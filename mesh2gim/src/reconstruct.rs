@@ -0,0 +1,309 @@
+//! Turns geometry images back into triangle meshes, and measures how far they are from the original mesh
+
+use glam::{Mat3, Vec3};
+
+use crate::{atlas::ChartRect, bvh::Bvh, sampling::closest_point_on_triangle, Image, Mesh};
+
+/// Builds a mesh with a vertex for every pixel of an octahedral geometry image. The mirrored border pixels are
+/// welded together, so the mesh is closed like the sphere it was sampled from.
+pub fn reconstruct_mesh(image: &Image) -> Mesh {
+    let (width, height) = (image.width, image.height);
+    // Every border pixel has a twin on the other half of the border, the four corners are all the same
+    let canonical = |x: u32, y: u32| {
+        let on_column = x == 0 || x == width - 1;
+        let on_row = y == 0 || y == height - 1;
+        let mut index = y * width + x;
+        if on_column {
+            index = index.min((height - 1 - y) * width + x);
+        }
+        if on_row {
+            index = index.min(y * width + (width - 1 - x));
+        }
+        if on_column && on_row {
+            index = index.min((height - 1 - y) * width + (width - 1 - x));
+        }
+        index
+    };
+
+    let mut vertices = vec![u32::MAX; image.pixels.len()];
+    let mut positions = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let index = canonical(x, y);
+            if index == y * width + x {
+                vertices[index as usize] = positions.len() as u32;
+                positions.push(image.pixels[index as usize]);
+            }
+        }
+    }
+
+    let center = (Vec3::new(width as f32, height as f32, 0.0) - 1.0) * 0.5;
+    let mut indices = Vec::new();
+    for y in 0..height.saturating_sub(1) {
+        for x in 0..width.saturating_sub(1) {
+            let [a, b, c, d] = [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)]
+                .map(|(x, y)| vertices[canonical(x, y) as usize]);
+            // Split the quads along the edges of the octahedron, which are diagonal in opposite directions in
+            // neighboring quadrants
+            let offset = Vec3::new(x as f32 + 0.5, y as f32 + 0.5, 0.0) - center;
+            let triangles = if offset.x * offset.y > 0.0 {
+                [[a, b, d], [b, c, d]]
+            } else {
+                [[a, b, c], [a, c, d]]
+            };
+            for [a, b, c] in triangles {
+                if a != b && b != c && c != a {
+                    indices.extend_from_slice(&[a, b, c]);
+                }
+            }
+        }
+    }
+
+    let mut mesh = Mesh {
+        positions,
+        attributes: vec![],
+        indices,
+    };
+    orient_outwards(&mut mesh);
+    mesh
+}
+
+/// Builds a mesh out of every chart of an atlas. The charts are not stitched together, so the seams stay open.
+pub fn reconstruct_atlas(image: &Image, charts: &[ChartRect]) -> Mesh {
+    let mut positions = Vec::new();
+    let mut indices = Vec::new();
+    for chart in charts {
+        let first = positions.len() as u32;
+        for y in chart.y..chart.y + chart.height {
+            for x in chart.x..chart.x + chart.width {
+                positions.push(image.pixels[(y * image.width + x) as usize]);
+            }
+        }
+        let vertex = |x: u32, y: u32| first + y * chart.width + x;
+        for y in 0..chart.height.saturating_sub(1) {
+            for x in 0..chart.width.saturating_sub(1) {
+                let [a, b, c, d] = [
+                    vertex(x, y),
+                    vertex(x + 1, y),
+                    vertex(x + 1, y + 1),
+                    vertex(x, y + 1),
+                ];
+                indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+    }
+    Mesh {
+        positions,
+        attributes: vec![],
+        indices,
+    }
+}
+
+/// Flips every triangle if the mesh encloses a negative volume
+fn orient_outwards(mesh: &mut Mesh) {
+    let volume: f32 = mesh
+        .triangles()
+        .map(|triangle| {
+            let [a, b, c] = triangle.to_array().map(|i| mesh.positions[i as usize]);
+            a.dot(b.cross(c))
+        })
+        .sum();
+    if volume < 0.0 {
+        for triangle in mesh.indices.as_chunks_mut::<3>().0 {
+            triangle.swap(1, 2);
+        }
+    }
+}
+
+/// Distances between the surfaces of two meshes, in the units of the meshes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshDistance {
+    /// The largest distance from a point on either surface to the other surface
+    pub hausdorff: f32,
+    /// Root mean square of the distances, sampled evenly over both surfaces
+    pub rms: f32,
+}
+
+/// Compares two meshes in both directions. Every vertex is measured, plus `samples` points spread over each
+/// surface by area.
+pub fn compare_meshes(a: &Mesh, b: &Mesh, samples: usize) -> MeshDistance {
+    let mut max = 0.0f32;
+    let mut sum_squared = 0.0f64;
+    let mut count = 0;
+    for (from, to) in [(a, b), (b, a)] {
+        let surface = SurfaceDistance::new(to);
+        for point in sample_surface(from, samples) {
+            let distance = surface.distance(point);
+            max = max.max(distance);
+            sum_squared += (distance * distance) as f64;
+            count += 1;
+        }
+    }
+    MeshDistance {
+        hausdorff: max,
+        rms: (sum_squared / count.max(1) as f64).sqrt() as f32,
+    }
+}
+
+/// The vertices of the mesh, plus points on every triangle in proportion to its area
+fn sample_surface(mesh: &Mesh, samples: usize) -> Vec<Vec3> {
+    let triangles: Vec<[Vec3; 3]> = mesh
+        .triangles()
+        .map(|triangle| triangle.to_array().map(|i| mesh.positions[i as usize]))
+        .collect();
+    let area = |[a, b, c]: &[Vec3; 3]| (*b - *a).cross(*c - *a).length() * 0.5;
+    let total_area: f32 = triangles.iter().map(area).sum();
+
+    let mut points = mesh.positions.clone();
+    if total_area <= 0.0 {
+        return points;
+    }
+    // The R2 low discrepancy sequence spreads the points evenly without randomness
+    const R2: (f32, f32) = (0.754_877_7, 0.569_840_3);
+    let mut sequence = 0.0f32;
+    for triangle in &triangles {
+        let count = (area(triangle) / total_area * samples as f32).round() as usize;
+        for _ in 0..count {
+            sequence += 1.0;
+            let (mut u, mut v) = (
+                (0.5 + R2.0 * sequence).fract(),
+                (0.5 + R2.1 * sequence).fract(),
+            );
+            if u + v > 1.0 {
+                (u, v) = (1.0 - u, 1.0 - v);
+            }
+            let [a, b, c] = *triangle;
+            points.push(a + (b - a) * u + (c - a) * v);
+        }
+    }
+    points
+}
+
+/// Finds the distance from a point to the closest triangle of a mesh
+struct SurfaceDistance {
+    triangles: Vec<[Vec3; 3]>,
+    bvh: Bvh,
+}
+
+impl SurfaceDistance {
+    fn new(mesh: &Mesh) -> Self {
+        let triangles: Vec<[Vec3; 3]> = mesh
+            .triangles()
+            .map(|triangle| triangle.to_array().map(|i| mesh.positions[i as usize]))
+            .collect();
+        let bvh = Bvh::new(&triangles);
+        Self { triangles, bvh }
+    }
+
+    fn distance(&self, point: Vec3) -> f32 {
+        let nearest = self.bvh.nearest(point, |i| {
+            let [a, b, c] = self.triangles[i as usize];
+            let closest = Mat3::from_cols(a, b, c) * closest_point_on_triangle(point, a, b, c);
+            closest.distance_squared(point)
+        });
+        nearest.map_or(f32::INFINITY, |(_, distance_squared)| {
+            distance_squared.sqrt()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        atlas::make_geometry_image_atlas,
+        make_geometry_image,
+        parametrization::{
+            spherical_parametrization, to_image, CubeMap, EqualAreaOctahedral, HoleFilling, Layout,
+        },
+        repair::Topology,
+        test_meshes::{subdivided_octahedron, torus},
+    };
+
+    #[test]
+    fn test_reconstruct_sphere() {
+        let sphere = subdivided_octahedron(3, Vec3::ONE);
+        let image = make_geometry_image(&sphere, (33, 33)).unwrap().positions;
+        let reconstructed = reconstruct_mesh(&image);
+        // Every side of the border folds in half around its middle pixel, and the four corners become one
+        assert_eq!(reconstructed.positions.len(), 33 * 33 - 4 * 15 - 3);
+
+        let topology = Topology::of(&reconstructed);
+        assert_eq!(topology.boundary_edges, 0);
+        assert_eq!(topology.non_manifold_edges, 0);
+        assert_eq!(topology.genus, Some(0));
+        assert!(topology.check().is_ok());
+
+        let distance = compare_meshes(&sphere, &reconstructed, 10_000);
+        assert!(distance.hausdorff < 0.1, "{distance:?}");
+        assert!(distance.rms < distance.hausdorff);
+        // The normals point outwards
+        let triangle = reconstructed.triangles().next().unwrap();
+        let [a, b, c] = triangle
+            .to_array()
+            .map(|i| reconstructed.positions[i as usize]);
+        assert!((b - a).cross(c - a).dot(a) > 0.0);
+    }
+
+    #[test]
+    fn test_reconstruct_any_size() {
+        let sphere = subdivided_octahedron(3, Vec3::ONE);
+        for size in [(32, 32), (48, 24)] {
+            let image = make_geometry_image(&sphere, size).unwrap().positions;
+            let reconstructed = reconstruct_mesh(&image);
+            let topology = Topology::of(&reconstructed);
+            assert_eq!(topology.boundary_edges, 0, "{size:?}");
+            assert_eq!(topology.genus, Some(0), "{size:?}");
+            let distance = compare_meshes(&sphere, &reconstructed, 10_000);
+            assert!(distance.hausdorff < 0.15, "{size:?} {distance:?}");
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_layouts() {
+        let sphere = subdivided_octahedron(3, Vec3::ONE);
+        let parametrization = spherical_parametrization(&sphere, &Default::default()).vertices;
+        let image = |size, layout: &dyn Layout| {
+            to_image(
+                &sphere,
+                &parametrization,
+                size,
+                HoleFilling::default(),
+                layout,
+            )
+            .unwrap()
+        };
+        let equal_area = reconstruct_mesh(&image((33, 33), &EqualAreaOctahedral));
+        assert_eq!(Topology::of(&equal_area).genus, Some(0));
+        let distance = compare_meshes(&sphere, &equal_area, 10_000);
+        assert!(distance.hausdorff < 0.1, "{distance:?}");
+
+        let cube = reconstruct_atlas(&image((96, 64), &CubeMap), &CubeMap.charts((96, 64)));
+        let distance = compare_meshes(&sphere, &cube, 10_000);
+        assert!(distance.hausdorff < 0.1, "{distance:?}");
+    }
+
+    #[test]
+    fn test_reconstruct_atlas() {
+        let mesh = torus(24, 12);
+        let atlas = make_geometry_image_atlas(&mesh, (128, 128), &Default::default()).unwrap();
+        let reconstructed = reconstruct_atlas(&atlas.image.positions, &atlas.charts);
+        let vertices: u32 = atlas.charts.iter().map(|c| c.width * c.height).sum();
+        assert_eq!(reconstructed.positions.len(), vertices as usize);
+        let distance = compare_meshes(&mesh, &reconstructed, 10_000);
+        assert!(distance.hausdorff < 0.2, "{distance:?}");
+    }
+
+    #[test]
+    fn test_compare_meshes() {
+        let sphere = subdivided_octahedron(2, Vec3::ONE);
+        let same = compare_meshes(&sphere, &sphere, 1000);
+        assert!(same.hausdorff < 1e-5);
+        assert!(same.rms < 1e-5);
+
+        let larger = subdivided_octahedron(2, Vec3::splat(1.5));
+        let distance = compare_meshes(&sphere, &larger, 1000);
+        assert!((distance.hausdorff - 0.5).abs() < 0.1, "{distance:?}");
+        assert!(distance.rms > 0.3);
+    }
+}
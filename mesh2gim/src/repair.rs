@@ -0,0 +1,444 @@
+//! Cleans up meshes before the parametrization, and checks if they can be mapped to a sphere
+
+use std::collections::{HashMap, HashSet};
+
+use glam::Vec3;
+
+use crate::{check_mesh, Attribute, AttributeValues, Mesh, Mesh2GimError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RepairOptions {
+    /// Vertices closer than this are welded together, relative to the diagonal of the bounding box.
+    /// Zero only welds vertices at exactly the same position.
+    pub weld_tolerance: f32,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        RepairOptions {
+            weld_tolerance: 1e-6,
+        }
+    }
+}
+
+/// The shape of the surface, which decides if it can be mapped to a sphere
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    /// Only the vertices used by a triangle
+    pub vertices: usize,
+    pub edges: usize,
+    pub faces: usize,
+    /// Edges with a single triangle
+    pub boundary_edges: usize,
+    /// Loops of boundary edges, every one is a hole in the surface
+    pub boundary_loops: usize,
+    /// Edges shared by more than two triangles
+    pub non_manifold_edges: usize,
+    /// Vertices where two holes touch, so the surface is not a manifold there
+    pub pinched_vertices: usize,
+    /// Connected pieces of the mesh
+    pub components: usize,
+    /// V - E + F, which is 2 for a sphere
+    pub euler_characteristic: i64,
+    /// Number of handles of the surface. Only known for manifold meshes.
+    pub genus: Option<u32>,
+}
+
+impl Topology {
+    pub fn of(mesh: &Mesh) -> Self {
+        let mut edge_faces: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut used = vec![false; mesh.positions.len()];
+        let mut components = DisjointSets::new(mesh.positions.len());
+        for edge in mesh.edges() {
+            *edge_faces
+                .entry((edge.x.min(edge.y), edge.x.max(edge.y)))
+                .or_default() += 1;
+            used[edge.x as usize] = true;
+            components.union(edge.x, edge.y);
+        }
+
+        let mut boundaries = DisjointSets::new(mesh.positions.len());
+        let mut boundary_degrees = vec![0u32; mesh.positions.len()];
+        let mut boundary_edges = 0;
+        let mut non_manifold_edges = 0;
+        for (&(a, b), &faces) in &edge_faces {
+            if faces == 1 {
+                boundary_edges += 1;
+                boundaries.union(a, b);
+                boundary_degrees[a as usize] += 1;
+                boundary_degrees[b as usize] += 1;
+            } else if faces > 2 {
+                non_manifold_edges += 1;
+            }
+        }
+        let on_boundary: Vec<bool> = boundary_degrees.iter().map(|&degree| degree > 0).collect();
+        let pinched_vertices = boundary_degrees
+            .iter()
+            .filter(|&&degree| degree > 2)
+            .count();
+        let count_roots = |sets: &mut DisjointSets, include: &[bool]| {
+            (0..include.len() as u32)
+                .filter(|&i| include[i as usize] && sets.find(i) == i)
+                .count()
+        };
+        // Independent cycles of the boundary edges, so holes that touch at a vertex are still counted separately
+        let boundary_vertices = on_boundary.iter().filter(|on| **on).count();
+        let boundary_loops =
+            boundary_edges + count_roots(&mut boundaries, &on_boundary) - boundary_vertices;
+        let components = count_roots(&mut components, &used);
+
+        let vertices = used.iter().filter(|used| **used).count();
+        let edges = edge_faces.len();
+        let faces = mesh.faces_count();
+        let euler_characteristic = vertices as i64 - edges as i64 + faces as i64;
+        // Every orientable surface satisfies V - E + F = 2 * components - 2 * genus - boundary loops
+        let twice_genus = 2 * components as i64 - euler_characteristic - boundary_loops as i64;
+        let is_manifold = non_manifold_edges == 0 && pinched_vertices == 0;
+        let genus = (is_manifold && twice_genus >= 0 && twice_genus % 2 == 0)
+            .then_some((twice_genus / 2) as u32);
+        Topology {
+            vertices,
+            edges,
+            faces,
+            boundary_edges,
+            boundary_loops,
+            non_manifold_edges,
+            pinched_vertices,
+            components,
+            euler_characteristic,
+            genus,
+        }
+    }
+
+    /// Reasons why the mesh can not be mapped to a sphere without tearing or overlapping
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.non_manifold_edges > 0 {
+            problems.push(format!(
+                "{} edges are shared by more than two triangles",
+                self.non_manifold_edges
+            ));
+        }
+        if self.pinched_vertices > 0 {
+            problems.push(format!(
+                "{} vertices are shared by separate holes",
+                self.pinched_vertices
+            ));
+        }
+        if self.components > 1 {
+            problems.push(format!("the mesh has {} separate pieces", self.components));
+        }
+        match self.genus {
+            Some(0) => {}
+            Some(genus) => problems.push(format!("the mesh has genus {genus}, not 0")),
+            None if self.non_manifold_edges == 0 && self.pinched_vertices == 0 => {
+                problems.push(format!(
+                    "the Euler characteristic {} does not match an orientable surface",
+                    self.euler_characteristic
+                ))
+            }
+            None => {}
+        }
+        problems
+    }
+
+    /// Issues that still give a usable geometry image. Holes are filled when sampling the image.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.boundary_loops > 0 {
+            warnings.push(format!(
+                "the mesh has {} holes with {} boundary edges",
+                self.boundary_loops, self.boundary_edges
+            ));
+        }
+        warnings
+    }
+
+    /// Fails if the mesh has any of the [problems](Self::problems)
+    pub fn check(&self) -> Result<(), Mesh2GimError> {
+        let problems = self.problems();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Mesh2GimError::UnsupportedTopology(problems.join(", ")))
+        }
+    }
+}
+
+/// What [`repair_mesh`] changed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Vertices that were merged into another vertex
+    pub welded_vertices: usize,
+    /// Triangles with repeated vertices or without area
+    pub degenerate_triangles: usize,
+    /// Triangles with the same vertices as an earlier triangle, in any order
+    pub duplicate_triangles: usize,
+    /// Vertices that are not used by any triangle after the repair
+    pub unused_vertices: usize,
+    /// Topology of the repaired mesh
+    pub topology: Topology,
+}
+
+/// Welds coincident vertices, removes degenerate and duplicate triangles, and drops unused vertices.
+/// Welded vertices keep the attributes of the first vertex, so seams in the texture coordinates are lost.
+pub fn repair_mesh(
+    mesh: &Mesh,
+    options: &RepairOptions,
+) -> Result<(Mesh, RepairReport), Mesh2GimError> {
+    let (repaired, report, _) = repair_mesh_with_origins(mesh, options)?;
+    Ok((repaired, report))
+}
+
+/// [`repair_mesh`], also returning the vertex of the input that every vertex of the repaired mesh came from
+pub(crate) fn repair_mesh_with_origins(
+    mesh: &Mesh,
+    options: &RepairOptions,
+) -> Result<(Mesh, RepairReport, Vec<usize>), Mesh2GimError> {
+    check_mesh(mesh)?;
+    let bounds = mesh.get_bounds();
+    let tolerance = options.weld_tolerance * (bounds.max - bounds.min).length();
+    let representatives = weld_vertices(&mesh.positions, tolerance);
+    let welded_vertices = representatives
+        .iter()
+        .enumerate()
+        .filter(|&(i, &representative)| representative as usize != i)
+        .count();
+
+    let mut report = RepairReport {
+        welded_vertices,
+        ..Default::default()
+    };
+    let mut seen = HashSet::new();
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for triangle in mesh.triangles() {
+        let triangle = triangle.to_array().map(|i| representatives[i as usize]);
+        let [a, b, c] = triangle.map(|i| mesh.positions[i as usize]);
+        if triangle[0] == triangle[1]
+            || triangle[1] == triangle[2]
+            || triangle[2] == triangle[0]
+            || (b - a).cross(c - a) == Vec3::ZERO
+        {
+            report.degenerate_triangles += 1;
+            continue;
+        }
+        let mut key = triangle;
+        key.sort_unstable();
+        if !seen.insert(key) {
+            report.duplicate_triangles += 1;
+            continue;
+        }
+        indices.extend_from_slice(&triangle);
+    }
+
+    // Keep the used vertices in their original order
+    let mut new_indices = vec![u32::MAX; mesh.positions.len()];
+    for &index in &indices {
+        new_indices[index as usize] = 0;
+    }
+    let mut kept = Vec::new();
+    for (i, new_index) in new_indices.iter_mut().enumerate() {
+        if *new_index == 0 {
+            *new_index = kept.len() as u32;
+            kept.push(i);
+        }
+    }
+    let representative_count = representatives.len() - welded_vertices;
+    report.unused_vertices = representative_count - kept.len();
+    for index in &mut indices {
+        *index = new_indices[*index as usize];
+    }
+
+    let repaired = Mesh {
+        positions: kept.iter().map(|&i| mesh.positions[i]).collect(),
+        attributes: mesh
+            .attributes
+            .iter()
+            .map(|attribute| Attribute {
+                name: attribute.name.clone(),
+                values: gather(&attribute.values, &kept),
+            })
+            .collect(),
+        indices,
+    };
+    report.topology = Topology::of(&repaired);
+    Ok((repaired, report, kept))
+}
+
+/// Returns the index of the vertex that every vertex gets merged into
+fn weld_vertices(positions: &[Vec3], tolerance: f32) -> Vec<u32> {
+    let mut representatives = Vec::with_capacity(positions.len());
+    if tolerance > 0.0 {
+        // Grid cells as large as the tolerance, so close vertices are always in neighboring cells
+        let mut cells: HashMap<[i32; 3], Vec<u32>> = HashMap::new();
+        for (i, position) in positions.iter().enumerate() {
+            let cell = (*position / tolerance).floor().as_ivec3();
+            let mut found = None;
+            'search: for z in -1..=1 {
+                for y in -1..=1 {
+                    for x in -1..=1 {
+                        let neighbor = (cell + glam::IVec3::new(x, y, z)).to_array();
+                        for &candidate in cells.get(&neighbor).into_iter().flatten() {
+                            if positions[candidate as usize].distance(*position) <= tolerance {
+                                found = Some(candidate);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+            representatives.push(found.unwrap_or_else(|| {
+                cells.entry(cell.to_array()).or_default().push(i as u32);
+                i as u32
+            }));
+        }
+    } else {
+        let mut exact: HashMap<[u32; 3], u32> = HashMap::new();
+        for (i, position) in positions.iter().enumerate() {
+            let key = position.to_array().map(f32::to_bits);
+            representatives.push(*exact.entry(key).or_insert(i as u32));
+        }
+    }
+    representatives
+}
+
+fn gather(values: &AttributeValues, indices: &[usize]) -> AttributeValues {
+    fn pick<T: Copy>(values: &[T], indices: &[usize]) -> Vec<T> {
+        indices.iter().map(|&i| values[i]).collect()
+    }
+    match values {
+        AttributeValues::Floats(values) => AttributeValues::Floats(pick(values, indices)),
+        AttributeValues::Vec2s(values) => AttributeValues::Vec2s(pick(values, indices)),
+        AttributeValues::Vec3s(values) => AttributeValues::Vec3s(pick(values, indices)),
+        AttributeValues::Vec4s(values) => AttributeValues::Vec4s(pick(values, indices)),
+    }
+}
+
+/// Union-find over vertex indices
+struct DisjointSets {
+    parents: Vec<u32>,
+}
+
+impl DisjointSets {
+    fn new(count: usize) -> Self {
+        DisjointSets {
+            parents: (0..count as u32).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: u32) -> u32 {
+        while self.parents[i as usize] != i {
+            // Path halving
+            let grandparent = self.parents[self.parents[i as usize] as usize];
+            self.parents[i as usize] = grandparent;
+            i = grandparent;
+        }
+        i
+    }
+
+    fn union(&mut self, a: u32, b: u32) {
+        let a = self.find(a);
+        let b = self.find(b);
+        if a != b {
+            self.parents[a as usize] = b;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::{octahedron, subdivided_octahedron, torus};
+
+    /// Splits every triangle off into its own vertices, like an OBJ file with per-face normals
+    fn split_triangles(mesh: &Mesh) -> Mesh {
+        let positions = mesh
+            .indices
+            .iter()
+            .map(|&i| mesh.positions[i as usize])
+            .collect();
+        Mesh {
+            positions,
+            attributes: vec![Attribute {
+                name: Attribute::NORMAL.to_string(),
+                values: AttributeValues::Floats(
+                    (0..mesh.indices.len()).map(|i| i as f32).collect(),
+                ),
+            }],
+            indices: (0..mesh.indices.len() as u32).collect(),
+        }
+    }
+
+    #[test]
+    fn test_weld_split_mesh() {
+        let mesh = split_triangles(&subdivided_octahedron(1, Vec3::ONE));
+        let topology = Topology::of(&mesh);
+        assert_eq!(topology.components, 32);
+        assert!(topology.check().is_err());
+
+        let (repaired, report) = repair_mesh(&mesh, &Default::default()).unwrap();
+        assert_eq!(repaired.positions.len(), 18);
+        assert_eq!(report.welded_vertices, 96 - 18);
+        assert_eq!(repaired.attributes[0].values.len(), 18);
+        assert_eq!(report.topology.euler_characteristic, 2);
+        assert_eq!(report.topology.genus, Some(0));
+        assert!(report.topology.check().is_ok());
+        assert!(report.topology.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_remove_degenerate_and_duplicate_triangles() {
+        let mut mesh = octahedron();
+        mesh.indices.extend_from_slice(&[0, 0, 1]); // repeated vertex
+        mesh.positions.push(Vec3::ZERO);
+        mesh.indices.extend_from_slice(&[0, 1, 6]); // no area, the center is between 0 and 1
+        mesh.indices.extend_from_slice(&[4, 0, 2]); // the first triangle again
+        mesh.indices.extend_from_slice(&[2, 0, 4]); // and flipped
+        mesh.positions.push(Vec3::splat(0.5)); // never used
+        let (repaired, report) = repair_mesh(&mesh, &Default::default()).unwrap();
+        assert_eq!(report.degenerate_triangles, 2);
+        assert_eq!(report.duplicate_triangles, 2);
+        assert_eq!(report.unused_vertices, 2);
+        assert_eq!(repaired.indices, octahedron().indices);
+        assert_eq!(repaired.positions, octahedron().positions);
+    }
+
+    #[test]
+    fn test_topology() {
+        let sphere = Topology::of(&octahedron());
+        assert_eq!((sphere.vertices, sphere.edges, sphere.faces), (6, 12, 8));
+        assert_eq!(sphere.genus, Some(0));
+
+        let torus = Topology::of(&torus(8, 6));
+        assert_eq!(torus.euler_characteristic, 0);
+        assert_eq!(torus.genus, Some(1));
+        assert!(torus.check().is_err());
+
+        // One hole
+        let mut open = octahedron();
+        open.indices.truncate(21);
+        let open = Topology::of(&open);
+        assert_eq!((open.boundary_edges, open.boundary_loops), (3, 1));
+        assert_eq!(open.genus, Some(0));
+        assert!(open.check().is_ok());
+        assert_eq!(open.warnings().len(), 1);
+
+        // Two holes that touch at vertex 4
+        let mut pinched = octahedron();
+        pinched.indices.drain(0..3);
+        pinched.indices.drain(3..6);
+        let pinched = Topology::of(&pinched);
+        assert_eq!((pinched.boundary_loops, pinched.pinched_vertices), (2, 1));
+        assert_eq!(pinched.genus, None);
+        assert!(pinched.check().is_err());
+
+        // Three triangles on the same edge
+        let mut fin = octahedron();
+        fin.positions.push(Vec3::new(0.5, 0.5, 2.0));
+        fin.indices.extend_from_slice(&[0, 2, 6]);
+        let fin = Topology::of(&fin);
+        assert_eq!(fin.non_manifold_edges, 1);
+        assert_eq!(fin.genus, None);
+        assert!(fin.check().is_err());
+    }
+}
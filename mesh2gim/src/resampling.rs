@@ -0,0 +1,205 @@
+//! Area-aware resampling, which evens out how many pixels every part of the mesh gets.
+//!
+//! The pixels of a geometry image are spread evenly over the sphere, so a part of the mesh gets as many pixels as
+//! the area it covers on the sphere. The Laplacian smoothing squeezes protruding details like fingers or ears into
+//! tiny spots, and they come out blocky. This pass moves the vertices over the sphere, away from the triangles that
+//! are too small for their size on the mesh and towards the ones that are too large, until the share of the sphere
+//! of every triangle is closer to its share of the mesh surface.
+
+use glam::Vec3;
+
+use crate::{distortion::analyze_distortion, Mesh, Progress};
+
+/// The most times a move is halved before the vertex is left where it is
+const MAX_HALVINGS: u32 = 4;
+/// The area ratios are clamped to this range, so a single degenerate triangle can not pull its vertices around
+const MAX_RATIO: f32 = 16.0;
+
+/// How the sampling of the mesh changed, measured with the area distortion of
+/// [`DistortionReport`](crate::distortion::DistortionReport)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResamplingReport {
+    pub iterations: u32,
+    /// The mean area stretch before the resampling, 1 is perfect
+    pub mean_area_stretch_before: f32,
+    pub mean_area_stretch_after: f32,
+    pub max_area_stretch_before: f32,
+    pub max_area_stretch_after: f32,
+}
+
+/// Moves the vertices of a spherical parametrization so that every triangle covers a share of the sphere closer to
+/// its share of the mesh surface. Triangles are never turned over, and the parametrization is left as it was if the
+/// resampling does not lower the mean area stretch.
+pub fn resample_by_area(
+    mesh: &Mesh,
+    parametrization: &mut [Vec3],
+    iterations: u32,
+) -> ResamplingReport {
+    resample_by_area_with_progress(mesh, parametrization, iterations, &|_| {})
+}
+
+/// [`resample_by_area`], reporting every iteration
+pub(crate) fn resample_by_area_with_progress(
+    mesh: &Mesh,
+    parametrization: &mut [Vec3],
+    iterations: u32,
+    progress: &(dyn Fn(Progress) + Sync),
+) -> ResamplingReport {
+    let before = analyze_distortion(mesh, parametrization);
+    let triangles: Vec<[usize; 3]> = mesh
+        .triangles()
+        .map(|triangle| triangle.to_array().map(|i| i as usize))
+        .collect();
+    let mesh_areas: Vec<f32> = triangles
+        .iter()
+        .map(|triangle| area(&triangle.map(|i| mesh.positions[i])))
+        .collect();
+    let mesh_total: f32 = mesh_areas.iter().sum();
+    let vertex_triangles = vertex_triangles(parametrization.len(), &triangles);
+    // Triangles that are already turned over are free to move, the others have to keep their orientation
+    let volumes: Vec<f32> = triangles
+        .iter()
+        .map(|triangle| signed_volume(&triangle.map(|i| parametrization[i])))
+        .collect();
+    let positive = volumes.iter().filter(|&&volume| volume > 0.0).count();
+    let sign = if 2 * positive >= volumes.len() {
+        1.0
+    } else {
+        -1.0
+    };
+    let was_valid: Vec<bool> = volumes.iter().map(|volume| volume * sign > 0.0).collect();
+
+    let mut resampled = parametrization.to_vec();
+    for iteration in 0..iterations {
+        // The ratios are updated once per iteration, the vertices in place, so every move sees the moves before it
+        let sphere_areas: Vec<f32> = triangles
+            .iter()
+            .map(|triangle| area(&triangle.map(|i| resampled[i])))
+            .collect();
+        let sphere_total: f32 = sphere_areas.iter().sum();
+        let ratios: Vec<f32> = sphere_areas
+            .iter()
+            .zip(&mesh_areas)
+            .map(|(&sphere_area, &mesh_area)| {
+                let ratio = (sphere_area / sphere_total) / (mesh_area / mesh_total);
+                if ratio.is_finite() {
+                    ratio.clamp(1.0 / MAX_RATIO, MAX_RATIO)
+                } else {
+                    1.0
+                }
+            })
+            .collect();
+
+        for (vertex, around) in vertex_triangles.iter().enumerate() {
+            if around.is_empty() {
+                continue;
+            }
+            // The centroid of the triangles around the vertex, weighted by how much too large they are, minus the
+            // plain centroid. Evenly sampled triangles cancel out, and the vertex stays put.
+            let mut weighted = Vec3::ZERO;
+            let mut plain = Vec3::ZERO;
+            let mut total_ratio = 0.0;
+            for &t in around {
+                let center = triangles[t].map(|i| resampled[i]).iter().sum::<Vec3>() / 3.0;
+                weighted += center * ratios[t];
+                plain += center;
+                total_ratio += ratios[t];
+            }
+            let mut step = weighted / total_ratio - plain / around.len() as f32;
+            let original = resampled[vertex];
+            for _ in 0..=MAX_HALVINGS {
+                resampled[vertex] = (original + step).normalize_or(original);
+                let folds = around.iter().any(|&t| {
+                    was_valid[t] && signed_volume(&triangles[t].map(|i| resampled[i])) * sign <= 0.0
+                });
+                if !folds {
+                    break;
+                }
+                resampled[vertex] = original;
+                step *= 0.5;
+            }
+        }
+        progress(Progress::Resampling {
+            iteration: iteration + 1,
+            max_iterations: iterations,
+        });
+    }
+
+    let after = analyze_distortion(mesh, &resampled);
+    let improved = after.mean_area_stretch < before.mean_area_stretch;
+    if improved {
+        parametrization.copy_from_slice(&resampled);
+    }
+    let kept = if improved { &after } else { &before };
+    ResamplingReport {
+        iterations,
+        mean_area_stretch_before: before.mean_area_stretch,
+        mean_area_stretch_after: kept.mean_area_stretch,
+        max_area_stretch_before: before.max_area_stretch,
+        max_area_stretch_after: kept.max_area_stretch,
+    }
+}
+
+/// The triangles around every vertex
+fn vertex_triangles(vertex_count: usize, triangles: &[[usize; 3]]) -> Vec<Vec<usize>> {
+    let mut around = vec![Vec::new(); vertex_count];
+    for (t, triangle) in triangles.iter().enumerate() {
+        for &vertex in triangle {
+            around[vertex].push(t);
+        }
+    }
+    around
+}
+
+fn area([a, b, c]: &[Vec3; 3]) -> f32 {
+    (*b - *a).cross(*c - *a).length() * 0.5
+}
+
+/// Positive when the triangle is counter-clockwise seen from outside the sphere
+fn signed_volume([a, b, c]: &[Vec3; 3]) -> f32 {
+    (*b - *a).cross(*c - *a).dot(*a + *b + *c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parametrization::{
+            find_flipped_triangles, spherical_parametrization, Initialization,
+            ParametrizationOptions,
+        },
+        test_meshes::subdivided_octahedron,
+    };
+
+    #[test]
+    fn test_resampling_evens_out_a_stretched_mesh() {
+        let ellipsoid = subdivided_octahedron(3, Vec3::new(6.0, 1.0, 1.0));
+        let options = ParametrizationOptions {
+            initialization: Initialization::Tutte,
+            ..Default::default()
+        };
+        let mut vertices = spherical_parametrization(&ellipsoid, &options).vertices;
+        let report = resample_by_area(&ellipsoid, &mut vertices, 100);
+        assert!(
+            report.mean_area_stretch_after < 0.7 * report.mean_area_stretch_before,
+            "{report:?}"
+        );
+        assert!(report.max_area_stretch_after < report.max_area_stretch_before);
+        let after = analyze_distortion(&ellipsoid, &vertices);
+        assert_eq!(after.mean_area_stretch, report.mean_area_stretch_after);
+        assert!(find_flipped_triangles(&ellipsoid, &vertices).is_empty());
+        assert!(vertices.iter().all(|v| (v.length() - 1.0).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_even_sampling_is_kept() {
+        let sphere = subdivided_octahedron(2, Vec3::ONE);
+        let mut vertices = sphere.positions.clone();
+        let report = resample_by_area(&sphere, &mut vertices, 10);
+        assert_eq!(vertices, sphere.positions);
+        assert_eq!(
+            report.mean_area_stretch_after,
+            report.mean_area_stretch_before
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use std::ops::{Add, Mul};
+
+use glam::{Mat3, UVec3, Vec2, Vec3};
+
+use crate::bvh::Bvh;
+
+/// A point on the surface of a mesh
+#[derive(Clone, Copy, Debug)]
+pub struct SurfaceSample {
+    pub triangle: UVec3,
+    pub barycentric: Vec3,
+}
+
+impl SurfaceSample {
+    /// Interpolates a per-vertex value at the sample
+    pub fn interpolate<T>(&self, values: &[T]) -> T
+    where
+        T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+    {
+        // Scale each vertex by its barycentric coordinate and sum them
+        values[self.triangle.x as usize] * self.barycentric.x
+            + values[self.triangle.y as usize] * self.barycentric.y
+            + values[self.triangle.z as usize] * self.barycentric.z
+    }
+}
+
+/// Finds the triangles of a spherical parametrization in a given direction
+pub struct SphereSampler<'a> {
+    parametrized_vertices: &'a [Vec3],
+    triangles: Vec<UVec3>,
+    bvh: Bvh,
+}
+
+impl<'a> SphereSampler<'a> {
+    pub fn new(triangles: Vec<UVec3>, parametrized_vertices: &'a [Vec3]) -> Self {
+        let bvh = sphere_bvh(&triangles, parametrized_vertices);
+        Self {
+            parametrized_vertices,
+            triangles,
+            bvh,
+        }
+    }
+
+    fn parametrized_triangle(&self, triangle: UVec3) -> (Vec3, Vec3, Vec3) {
+        (
+            self.parametrized_vertices[triangle.x as usize],
+            self.parametrized_vertices[triangle.y as usize],
+            self.parametrized_vertices[triangle.z as usize],
+        )
+    }
+
+    /// Shoots a ray from the center of the sphere, and returns the triangle that it hits
+    pub fn hit(&self, point_in_space: Vec3) -> Option<SurfaceSample> {
+        // The zero vector points nowhere, so it can not hit anything
+        let ray_vector = point_in_space.try_normalize()?;
+        let (hit, t) = self.bvh.closest_hit(Vec3::ZERO, ray_vector, |i| {
+            intersection_ray_triangle(
+                Vec3::ZERO,
+                ray_vector,
+                self.parametrized_triangle(self.triangles[i as usize]),
+            )
+        })?;
+
+        let triangle = self.triangles[hit as usize];
+        let (v1, v2, v3) = self.parametrized_triangle(triangle);
+        Some(SurfaceSample {
+            triangle,
+            barycentric: convert_to_barycentric_3d(v1, v2, v3, ray_vector * t),
+        })
+    }
+
+    /// Projects the point onto the sphere, and returns the closest point on any triangle.
+    /// Used when the ray does not hit anything, for example because the mesh has a hole.
+    pub fn nearest(&self, point_in_space: Vec3) -> Option<SurfaceSample> {
+        let point = point_in_space.normalize_or_zero();
+        let (nearest, _) = self.bvh.nearest(point, |i| {
+            let (a, b, c) = self.parametrized_triangle(self.triangles[i as usize]);
+            let barycentric = closest_point_on_triangle(point, a, b, c);
+            let closest = Mat3::from_cols(a, b, c) * barycentric;
+            closest.distance_squared(point)
+        })?;
+
+        let triangle = self.triangles[nearest as usize];
+        let (a, b, c) = self.parametrized_triangle(triangle);
+        Some(SurfaceSample {
+            triangle,
+            barycentric: closest_point_on_triangle(point, a, b, c),
+        })
+    }
+}
+
+/// Finds the triangles of a flat parametrization, where every vertex is a 2D point
+pub struct PlanarSampler<'a> {
+    parametrized_vertices: &'a [Vec2],
+    triangles: Vec<UVec3>,
+    bvh: Bvh,
+}
+
+impl<'a> PlanarSampler<'a> {
+    pub fn new(triangles: Vec<UVec3>, parametrized_vertices: &'a [Vec2]) -> Self {
+        let flat: Vec<[Vec3; 3]> = triangles
+            .iter()
+            .map(|triangle| {
+                triangle
+                    .to_array()
+                    .map(|i| parametrized_vertices[i as usize].extend(0.0))
+            })
+            .collect();
+        Self {
+            parametrized_vertices,
+            triangles,
+            bvh: Bvh::new(&flat),
+        }
+    }
+
+    fn parametrized_triangle(&self, triangle: UVec3) -> (Vec3, Vec3, Vec3) {
+        let [a, b, c] = triangle
+            .to_array()
+            .map(|i| self.parametrized_vertices[i as usize].extend(0.0));
+        (a, b, c)
+    }
+
+    /// Returns the closest point on any triangle, and its distance to the point. Points inside a triangle have
+    /// a distance of zero.
+    pub fn nearest(&self, point: Vec2) -> Option<(SurfaceSample, f32)> {
+        let point = point.extend(0.0);
+        let (nearest, distance_squared) = self.bvh.nearest(point, |i| {
+            let (a, b, c) = self.parametrized_triangle(self.triangles[i as usize]);
+            let barycentric = closest_point_on_triangle(point, a, b, c);
+            let closest = Mat3::from_cols(a, b, c) * barycentric;
+            closest.distance_squared(point)
+        })?;
+
+        let triangle = self.triangles[nearest as usize];
+        let (a, b, c) = self.parametrized_triangle(triangle);
+        let sample = SurfaceSample {
+            triangle,
+            barycentric: closest_point_on_triangle(point, a, b, c),
+        };
+        Some((sample, distance_squared.sqrt()))
+    }
+}
+
+/// Finds the closest point on the surface of a mesh, in space
+pub struct SurfaceSampler<'a> {
+    positions: &'a [Vec3],
+    triangles: Vec<UVec3>,
+    bvh: Bvh,
+}
+
+impl<'a> SurfaceSampler<'a> {
+    pub fn new(triangles: Vec<UVec3>, positions: &'a [Vec3]) -> Self {
+        let bvh = sphere_bvh(&triangles, positions);
+        Self {
+            positions,
+            triangles,
+            bvh,
+        }
+    }
+
+    fn triangle(&self, triangle: UVec3) -> (Vec3, Vec3, Vec3) {
+        (
+            self.positions[triangle.x as usize],
+            self.positions[triangle.y as usize],
+            self.positions[triangle.z as usize],
+        )
+    }
+
+    pub fn nearest(&self, point: Vec3) -> Option<SurfaceSample> {
+        let (nearest, _) = self.bvh.nearest(point, |i| {
+            let (a, b, c) = self.triangle(self.triangles[i as usize]);
+            let barycentric = closest_point_on_triangle(point, a, b, c);
+            let closest = Mat3::from_cols(a, b, c) * barycentric;
+            closest.distance_squared(point)
+        })?;
+
+        let triangle = self.triangles[nearest as usize];
+        let (a, b, c) = self.triangle(triangle);
+        Some(SurfaceSample {
+            triangle,
+            barycentric: closest_point_on_triangle(point, a, b, c),
+        })
+    }
+}
+
+/// Builds a bounding volume hierarchy over the triangles of the parametrized mesh
+fn sphere_bvh(triangles: &[UVec3], parametrized_vertices: &[Vec3]) -> Bvh {
+    let triangles: Vec<[Vec3; 3]> = triangles
+        .iter()
+        .map(|triangle| {
+            [
+                parametrized_vertices[triangle.x as usize],
+                parametrized_vertices[triangle.y as usize],
+                parametrized_vertices[triangle.z as usize],
+            ]
+        })
+        .collect();
+    Bvh::new(&triangles)
+}
+
+fn convert_to_barycentric_3d(a: Vec3, b: Vec3, c: Vec3, p: Vec3) -> Vec3 {
+    let v0 = b - a;
+    let v1 = c - a;
+    let v2 = p - a;
+    let d00 = v0.dot(v0);
+    let d01 = v0.dot(v1);
+    let d11 = v1.dot(v1);
+    let d20 = v2.dot(v0);
+    let d21 = v2.dot(v1);
+    let denom = d00 * d11 - d01 * d01;
+
+    let mut result = Vec3::ZERO;
+    result.y = (d11 * d20 - d01 * d21) / denom;
+    result.z = (d00 * d21 - d01 * d20) / denom;
+    result.x = 1.0 - result.y - result.z;
+    result
+}
+
+/// Returns the barycentric coordinates of the point on the triangle that is closest to p.
+/// From "Real-Time Collision Detection" by Christer Ericson
+pub(crate) fn closest_point_on_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return Vec3::X;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return Vec3::Y;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return Vec3::new(1.0 - v, v, 0.0);
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return Vec3::Z;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return Vec3::new(1.0 - w, 0.0, w);
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return Vec3::new(0.0, 1.0 - w, w);
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    let v = vb * denom;
+    let w = vc * denom;
+    Vec3::new(1.0 - v - w, v, w)
+}
+
+/// Möller–Trumbore intersection, returns the distance along the ray
+fn intersection_ray_triangle(
+    ray_origin: Vec3,
+    ray_vector: Vec3,
+    triangle: (Vec3, Vec3, Vec3),
+) -> Option<f32> {
+    const EPSILON: f32 = 0.0000001;
+    let (vertex0, vertex1, vertex2) = triangle;
+    let edge1 = vertex1 - vertex0;
+    let edge2 = vertex2 - vertex0;
+    let h = ray_vector.cross(edge2);
+    let a = edge1.dot(h);
+
+    if a > -EPSILON && a < EPSILON {
+        return None;
+    }
+
+    let f = 1.0 / a;
+    let s = ray_origin - vertex0;
+    let u = f * s.dot(h);
+
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = f * ray_vector.dot(q);
+
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    // At this stage we can compute t to find out where the intersection point is on the line.
+    let t = f * edge2.dot(q);
+
+    if t > EPSILON {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_point_on_triangle() {
+        let (a, b, c) = (Vec3::ZERO, Vec3::X, Vec3::Y);
+        let closest = |p: Vec3| Mat3::from_cols(a, b, c) * closest_point_on_triangle(p, a, b, c);
+        assert!(closest(Vec3::new(0.25, 0.25, 1.0)).abs_diff_eq(Vec3::new(0.25, 0.25, 0.0), 1e-6));
+        assert!(closest(Vec3::new(-1.0, -1.0, 0.0)).abs_diff_eq(a, 1e-6));
+        assert!(closest(Vec3::new(2.0, -0.5, 0.0)).abs_diff_eq(b, 1e-6));
+        assert!(closest(Vec3::new(0.5, -1.0, 0.0)).abs_diff_eq(Vec3::new(0.5, 0.0, 0.0), 1e-6));
+        assert!(closest(Vec3::new(1.0, 1.0, 0.0)).abs_diff_eq(Vec3::new(0.5, 0.5, 0.0), 1e-6));
+    }
+}
@@ -0,0 +1,103 @@
+//! Small closed meshes for the tests
+
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use crate::Mesh;
+
+/// A unit octahedron with counter-clockwise triangles when viewed from the outside
+pub fn octahedron() -> Mesh {
+    Mesh {
+        positions: vec![
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ],
+        attributes: vec![],
+        indices: vec![
+            0, 2, 4, //
+            2, 1, 4, //
+            1, 3, 4, //
+            3, 0, 4, //
+            2, 0, 5, //
+            1, 2, 5, //
+            3, 1, 5, //
+            0, 3, 5, //
+        ],
+    }
+}
+
+/// An octahedron where every triangle is split into four, `levels` times, and scaled by `scale`.
+/// The vertices are projected onto the ellipsoid with the given radii.
+pub fn subdivided_octahedron(levels: u32, scale: Vec3) -> Mesh {
+    let mut mesh = octahedron();
+    for _ in 0..levels {
+        let mut midpoints = HashMap::new();
+        let mut indices = Vec::with_capacity(mesh.indices.len() * 4);
+        let mut midpoint = |a: u32, b: u32, positions: &mut Vec<Vec3>| -> u32 {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a as usize] + positions[b as usize]) * 0.5);
+                (positions.len() - 1) as u32
+            })
+        };
+        let triangles: Vec<_> = mesh.triangles().collect();
+        for triangle in triangles {
+            let ab = midpoint(triangle.x, triangle.y, &mut mesh.positions);
+            let bc = midpoint(triangle.y, triangle.z, &mut mesh.positions);
+            let ca = midpoint(triangle.z, triangle.x, &mut mesh.positions);
+            indices.extend_from_slice(&[triangle.x, ab, ca]);
+            indices.extend_from_slice(&[ab, triangle.y, bc]);
+            indices.extend_from_slice(&[ca, bc, triangle.z]);
+            indices.extend_from_slice(&[ab, bc, ca]);
+        }
+        mesh.indices = indices;
+    }
+    for position in &mut mesh.positions {
+        *position = position.normalize() * scale;
+    }
+    mesh
+}
+
+/// A torus with genus 1, made out of a grid of quads
+pub fn torus(rings: u32, segments: u32) -> Mesh {
+    let mut positions = Vec::new();
+    let mut indices = Vec::new();
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let u = ring as f32 / rings as f32 * std::f32::consts::TAU;
+            let v = segment as f32 / segments as f32 * std::f32::consts::TAU;
+            let radius = 2.0 + v.cos();
+            positions.push(Vec3::new(radius * u.cos(), v.sin(), radius * u.sin()));
+            let index = |r: u32, s: u32| (r % rings) * segments + s % segments;
+            let quad = [
+                index(ring, segment),
+                index(ring + 1, segment),
+                index(ring + 1, segment + 1),
+                index(ring, segment + 1),
+            ];
+            indices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+        }
+    }
+    Mesh {
+        positions,
+        attributes: vec![],
+        indices,
+    }
+}
+
+/// A long ellipsoid bent into a U, which is not star-shaped around its centroid
+pub fn horseshoe(levels: u32) -> Mesh {
+    let mut mesh = subdivided_octahedron(levels, Vec3::new(4.0, 0.5, 0.5));
+    // Wrap the long axis around a circle, most of the way
+    let radius = 1.6;
+    for position in &mut mesh.positions {
+        let angle = position.x / radius;
+        let distance = radius + position.y;
+        *position = Vec3::new(distance * angle.sin(), -distance * angle.cos(), position.z);
+    }
+    mesh
+}
@@ -0,0 +1,161 @@
+//! Geometry videos, which store an animated mesh as a sequence of geometry images.
+//!
+//! The frames of the animation share their triangles and only move the vertices. The sphere is parametrized once,
+//! from a reference frame, and every pixel keeps its triangle and barycentric coordinates in all frames. A pixel
+//! therefore follows the same point of the surface through the whole animation, so frames can be blended pixel by
+//! pixel.
+
+use glam::Vec3;
+
+use crate::{
+    decimate_for_parametrization,
+    parametrization::{check_size, Parametrization},
+    parametrize_mesh,
+    repair::{repair_mesh_with_origins, RepairOptions, RepairReport},
+    sample_full_mesh, sample_mesh, AttributeImage, GimOptions, Image, Mesh, Mesh2GimError, AABB,
+};
+
+pub struct GeometryVideo {
+    /// The positions of every frame, in order
+    pub frames: Vec<Image>,
+    /// The attributes of the reference frame, which are the same for every frame
+    pub attributes: Vec<AttributeImage>,
+    /// The range of the positions over all frames. Quantizing every frame with it keeps the frames comparable.
+    pub bounds: AABB,
+    /// The parametrization of the reference frame, or of its decimated mesh if the options decimate
+    pub parametrization: Parametrization,
+    /// What was repaired in the reference frame. The same vertices are welded in every frame.
+    pub repair: RepairReport,
+}
+
+/// Makes a geometry image of every frame with the parametrization of the reference frame. Pick a reference frame
+/// where the mesh is not folded or squeezed, like a rest pose.
+pub fn make_geometry_video(
+    frames: &[Mesh],
+    reference: usize,
+    repair: &RepairOptions,
+    options: &GimOptions,
+) -> Result<GeometryVideo, Mesh2GimError> {
+    check_size(options.size, options.layout)?;
+    let reference_frame = frames.get(reference).ok_or(Mesh2GimError::InvalidFrame {
+        frame: reference,
+        frames: frames.len(),
+    })?;
+    if let Some(frame) = frames.iter().position(|frame| {
+        frame.indices != reference_frame.indices
+            || frame.positions.len() != reference_frame.positions.len()
+    }) {
+        return Err(Mesh2GimError::FrameMismatch { frame });
+    }
+
+    let (mesh, report, origins) = repair_mesh_with_origins(reference_frame, repair)?;
+    let decimated = decimate_for_parametrization(&mesh, options)?;
+    let surface = decimated.as_ref().unwrap_or(&mesh);
+    let parametrization = parametrize_mesh(surface, options)?;
+    let mut grid = sample_mesh(surface, &parametrization.vertices, options)?;
+    if let Some(decimated) = &decimated {
+        grid = sample_full_mesh(&grid, decimated, &mesh, options)?;
+    }
+
+    let images: Vec<Image> = frames
+        .iter()
+        .map(|frame| {
+            let positions: Vec<Vec3> = origins.iter().map(|&i| frame.positions[i]).collect();
+            grid.to_image(&positions)
+        })
+        .collect();
+    let bounds = images.iter().flat_map(|image| &image.pixels).fold(
+        AABB {
+            min: Vec3::INFINITY,
+            max: Vec3::NEG_INFINITY,
+        },
+        |bounds, &pixel| AABB {
+            min: bounds.min.min(pixel),
+            max: bounds.max.max(pixel),
+        },
+    );
+    Ok(GeometryVideo {
+        frames: images,
+        attributes: mesh
+            .attributes
+            .iter()
+            .map(|attribute| grid.to_attribute_image(attribute))
+            .collect(),
+        bounds,
+        parametrization,
+        repair: report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_meshes::subdivided_octahedron;
+
+    #[test]
+    fn test_frames_share_the_mapping() {
+        let sphere = subdivided_octahedron(3, Vec3::ONE);
+        // Stretch the sphere more in every frame
+        let frames: Vec<Mesh> = (0..4)
+            .map(|i| {
+                let scale = Vec3::new(1.0 + i as f32, 1.0, 1.0);
+                Mesh {
+                    positions: sphere.positions.iter().map(|&p| p * scale).collect(),
+                    attributes: vec![],
+                    indices: sphere.indices.clone(),
+                }
+            })
+            .collect();
+        let video = make_geometry_video(
+            &frames,
+            0,
+            &RepairOptions::default(),
+            &GimOptions::new((33, 33)),
+        )
+        .unwrap();
+        assert_eq!(video.frames.len(), 4);
+        assert!(
+            (video.bounds.max.x - 4.0).abs() < 1e-3,
+            "{:?}",
+            video.bounds.max
+        );
+        assert!(
+            (video.bounds.min.y + 1.0).abs() < 1e-3,
+            "{:?}",
+            video.bounds.min
+        );
+        for (i, frame) in video.frames.iter().enumerate() {
+            let scale = Vec3::new(1.0 + i as f32, 1.0, 1.0);
+            for (pixel, first) in frame.pixels.iter().zip(&video.frames[0].pixels) {
+                assert!(pixel.distance(*first * scale) < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_frames() {
+        let sphere = subdivided_octahedron(1, Vec3::ONE);
+        let mut other = subdivided_octahedron(1, Vec3::ONE);
+        other.indices.swap(0, 1);
+        let options = GimOptions::new((9, 9));
+        let video = |frames: &[Mesh], reference| {
+            make_geometry_video(frames, reference, &RepairOptions::default(), &options)
+        };
+        assert!(matches!(
+            video(&[], 0),
+            Err(Mesh2GimError::InvalidFrame {
+                frame: 0,
+                frames: 0
+            })
+        ));
+        let frames = [sphere, other];
+        assert!(matches!(
+            video(&frames, 2),
+            Err(Mesh2GimError::InvalidFrame { frame: 2, .. })
+        ));
+        assert!(matches!(
+            video(&frames, 1),
+            Err(Mesh2GimError::FrameMismatch { frame: 0 })
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+import init, {
+  import_mesh,
+  type WasmMeshImportOptions,
+} from "../../math3render/pkg/web.js";
+
+export type MeshImportRequest = {
+  fileName: string;
+  bytes: Uint8Array;
+  options: WasmMeshImportOptions;
+};
+
+export type MeshImportResponse =
+  | { type: "progress"; phase: string; fraction: number }
+  | {
+      type: "finished";
+      png: Uint8Array;
+      boundsMin: [number, number, number];
+      boundsMax: [number, number, number];
+      metadata: string;
+    }
+  | { type: "error"; message: string };
+
+/**
+ * Converting a mesh blocks for seconds, so it runs here instead of on the main thread.
+ * The worker has its own instance of the module, without the renderer.
+ */
+const initialized = init();
+
+function respond(response: MeshImportResponse, transfer: Transferable[] = []) {
+  self.postMessage(response, { transfer });
+}
+
+self.onmessage = async (event: MessageEvent<MeshImportRequest>) => {
+  await initialized;
+  const { fileName, bytes, options } = event.data;
+  try {
+    const image = import_mesh(
+      fileName,
+      bytes,
+      options,
+      (phase: string, fraction: number) =>
+        respond({ type: "progress", phase, fraction })
+    );
+    const png = image.png;
+    respond(
+      {
+        type: "finished",
+        png,
+        boundsMin: Array.from(image.bounds_min) as [number, number, number],
+        boundsMax: Array.from(image.bounds_max) as [number, number, number],
+        metadata: image.metadata,
+      },
+      [png.buffer]
+    );
+    image.free();
+  } catch (error) {
+    respond({ type: "error", message: String(error) });
+  }
+};
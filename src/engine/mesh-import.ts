@@ -0,0 +1,80 @@
+import type { WasmMeshImportOptions } from "../../math3render/pkg/web.js";
+import MeshImportWorker from "./mesh-import-worker.ts?worker";
+import type {
+  MeshImportRequest,
+  MeshImportResponse,
+} from "./mesh-import-worker.ts";
+
+export type ImportedMesh = Extract<MeshImportResponse, { type: "finished" }>;
+
+/** Imported meshes are saved as 16 bit .png files with this suffix, so they can be told apart from 8 bit textures */
+export const GeometryImageSuffix = ".gim.png";
+
+/** The mesh2gim metadata of an imported mesh is saved next to its geometry image, with this suffix */
+export const GeometryImageMetadataSuffix = ".gim.json";
+
+/**
+ * The file extensions that mesh2gim can load. A .gltf file can point to buffers in other files,
+ * which the browser can not read, so only the self-contained .glb files are accepted.
+ */
+export const MeshFileExtensions = ["obj", "stl", "ply", "glb"];
+
+export const DefaultMeshImportOptions: WasmMeshImportOptions = {
+  width: 255,
+  height: 255,
+  // The renderer can only unfold the octahedral layout
+  layout: "octahedral",
+  initialization: "centroid",
+  iterations: 500,
+  // Unlike the command line tool, the browser decimates big meshes, since it has only one thread
+  decimate: 50000,
+  quantization: "bounds",
+};
+
+/**
+ * Turns a mesh file into a geometry image in a worker, so that big meshes don't freeze the page.
+ * Aborting the signal stops the worker.
+ */
+export async function importMesh(
+  file: File,
+  options: WasmMeshImportOptions,
+  onProgress: (phase: string, fraction: number) => void,
+  signal?: AbortSignal
+): Promise<ImportedMesh> {
+  const request: MeshImportRequest = {
+    fileName: file.name,
+    bytes: new Uint8Array(await file.arrayBuffer()),
+    options,
+  };
+  signal?.throwIfAborted();
+
+  const worker = new MeshImportWorker();
+  return new Promise<ImportedMesh>((resolve, reject) => {
+    const stop = () => {
+      worker.terminate();
+      reject(signal?.reason);
+    };
+    signal?.addEventListener("abort", stop, { once: true });
+
+    worker.onmessage = (event: MessageEvent<MeshImportResponse>) => {
+      const response = event.data;
+      if (response.type === "progress") {
+        onProgress(response.phase, response.fraction);
+        return;
+      }
+      signal?.removeEventListener("abort", stop);
+      worker.terminate();
+      if (response.type === "finished") {
+        resolve(response);
+      } else {
+        reject(new Error(response.message));
+      }
+    };
+    worker.onerror = (event) => {
+      signal?.removeEventListener("abort", stop);
+      worker.terminate();
+      reject(new Error(event.message));
+    };
+    worker.postMessage(request, [request.bytes.buffer]);
+  });
+}
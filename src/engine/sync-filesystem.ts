@@ -6,6 +6,7 @@ import {
 import type { WgpuEngine } from "./wgpu-engine.ts";
 import DefaultShaderCode from "../scenes/example-scene/default-shader.wgsl?raw";
 import type { ShallowRef } from "vue";
+import { GeometryImageSuffix } from "./mesh-import.ts";
 
 /**
  * Sync the filesystem with the Rust backend
@@ -67,6 +68,19 @@ export function syncFilesystem(
       } else if (change.type === "remove") {
         engine.value.removeShader(change.key);
       }
+    } else if (change.key.endsWith(GeometryImageSuffix)) {
+      stopPending(change.key);
+      if (change.type === "insert" || change.type === "update") {
+        const file = change.key;
+        const signal = addSignal(file);
+        fs.readFile(file, { signal })?.then(async (blob) => {
+          const png = new Uint8Array(await blob.arrayBuffer());
+          if (signal.aborted) return;
+          engine.value.updateGeometryImage(file, png);
+        });
+      } else if (change.type === "remove") {
+        engine.value.removeTexture(change.key);
+      }
     } else if (imageFileTypes.has(extension)) {
       stopPending(change.key);
       if (change.type === "insert" || change.type === "update") {
@@ -38,6 +38,10 @@ export class WgpuEngine {
   updateTexture(texture_info: { id: string; bitmap: ImageBitmap }) {
     this.engine.update_texture(texture_info.id, texture_info.bitmap);
   }
+  /** Takes a 16 bit .png file, since an ImageBitmap would only keep 8 bits of the positions. */
+  updateGeometryImage(id: string, png: Uint8Array) {
+    this.engine.update_geometry_image(id, png);
+  }
   removeTexture(id: string) {
     this.engine.remove_texture(id);
   }
@@ -18,6 +18,15 @@ export const MaterialParameterSchema = z.object({
   textureScale: z.tuple([z.number(), z.number()]).optional(),
 });
 
+export const GeometryImageSchema = z.object({
+  /** A 16 bit .png file from the mesh import */
+  texture: z.string(),
+  /** The mesh2gim layout, only the octahedral ones can be rendered */
+  layout: z.string(),
+  boundsMin: z.tuple([z.number(), z.number(), z.number()]),
+  boundsMax: z.tuple([z.number(), z.number(), z.number()]),
+});
+
 export const ModelSchema = z.object({
   type: z.literal("model"),
   id: z.string(),
@@ -28,6 +37,7 @@ export const ModelSchema = z.object({
   parametricShader: z.string(),
   material: MaterialParameterSchema,
   instanceCount: z.number().catch(() => 1),
+  geometryImage: GeometryImageSchema.optional(),
 });
 
 export type SerializedModel = z.infer<typeof ModelSchema>;
@@ -26,6 +26,7 @@ export function commonModelState(
       textureHeight: 1,
     },
     instanceCount: 1,
+    geometryImage: null,
   });
 }
 
@@ -82,6 +82,13 @@ export type MaterialParameter = {
   textureHeight: number;
 };
 
+export type GeometryImageSource = {
+  texture: FilePath;
+  layout: string;
+  boundsMin: ReadonlyVector3;
+  boundsMax: ReadonlyVector3;
+};
+
 export type VirtualModelState = {
   id: string;
   name: string;
@@ -91,6 +98,8 @@ export type VirtualModelState = {
   scale: number;
   material: MaterialParameter;
   instanceCount: number;
+  /** Rendered instead of the shader */
+  geometryImage: GeometryImageSource | null;
 };
 
 export interface VirtualSceneState {
@@ -188,6 +197,15 @@ function serializeModel(model: VirtualModelState): SerializedModel {
       textureScale: [model.material.textureWidth, model.material.textureHeight],
     },
     instanceCount: model.instanceCount,
+    geometryImage:
+      model.geometryImage === null
+        ? undefined
+        : {
+            texture: model.geometryImage.texture,
+            layout: model.geometryImage.layout,
+            boundsMin: model.geometryImage.boundsMin.serialize(),
+            boundsMax: model.geometryImage.boundsMax.serialize(),
+          },
   };
 }
 
@@ -210,5 +228,18 @@ function deserializeModel(data: SerializedModel): VirtualModelState {
       textureHeight: data.material.textureScale?.[1] ?? 1,
     },
     instanceCount: data.instanceCount,
+    geometryImage:
+      data.geometryImage === undefined
+        ? null
+        : {
+            texture: makeFilePath(data.geometryImage.texture),
+            layout: data.geometryImage.layout,
+            boundsMin: ReadonlyVector3.fromSerialized(
+              data.geometryImage.boundsMin
+            ),
+            boundsMax: ReadonlyVector3.fromSerialized(
+              data.geometryImage.boundsMax
+            ),
+          },
   };
 }
@@ -1,6 +1,7 @@
 import { computed, ref } from "vue";
 import { acceptHMRUpdate, defineStore } from "pinia";
 import {
+  getFileExtension,
   makeFilePath,
   type ReactiveFilesystem,
 } from "@/filesystem/reactive-files.ts";
@@ -12,8 +13,24 @@ import {
   ZipReader,
   ZipWriter,
 } from "@zip.js/zip.js";
-import { SceneFileName } from "@/filesystem/scene-file.ts";
+import {
+  deserializeScene,
+  SceneFileName,
+  SceneFileSchemaUrl,
+  serializeScene,
+  type SerializedModel,
+  type SerializedScene,
+} from "@/filesystem/scene-file.ts";
 import { assertUnreachable } from "@stefnotch/typestef/assert";
+import {
+  DefaultMeshImportOptions,
+  GeometryImageMetadataSuffix,
+  GeometryImageSuffix,
+  importMesh,
+  MeshFileExtensions,
+  type ImportedMesh,
+} from "@/engine/mesh-import.ts";
+import type { WasmMeshImportOptions } from "../../math3render/pkg/web.js";
 
 /**
  * Deals with the fact that the filesystem is created asynchronously.
@@ -39,6 +56,12 @@ export type ImportProjectDialog = {
   data: ImportFilesList;
 };
 
+export type ImportMeshDialog = {
+  file: File;
+  /** Set while the mesh is being converted */
+  progress: { phase: string; fraction: number } | null;
+};
+
 export type ImportFilesList =
   | {
       type: "files";
@@ -89,6 +112,9 @@ export const useFsStore = defineStore("fs-store", () => {
    */
   const importProjectDialog = ref<ImportProjectDialog | null>(null);
 
+  const importMeshDialog = ref<ImportMeshDialog | null>(null);
+  let importMeshAbort: AbortController | null = null;
+
   function hasProject(): Promise<boolean> {
     return filesystemCommands.add((sceneFiles) =>
       Promise.resolve(sceneFiles.hasFile(SceneFileName))
@@ -106,6 +132,8 @@ export const useFsStore = defineStore("fs-store", () => {
       if (noProject) {
         await finishImport("project");
       }
+    } else if (files.length === 1 && isMeshFile(files[0].name)) {
+      importMeshDialog.value = { file: files[0], progress: null };
     } else {
       addFiles({ type: "files", value: files });
     }
@@ -168,6 +196,108 @@ export const useFsStore = defineStore("fs-store", () => {
     }
   }
 
+  /**
+   * Converts the mesh of the dialog to a geometry image, and adds it to the scene.
+   * Cancelling stops a running conversion.
+   */
+  async function finishMeshImport(
+    importAs: "mesh" | "files" | "cancel",
+    options: WasmMeshImportOptions = DefaultMeshImportOptions
+  ) {
+    const dialog = importMeshDialog.value;
+    if (dialog === null) return;
+
+    if (importAs === "cancel") {
+      importMeshAbort?.abort();
+      importMeshDialog.value = null;
+    } else if (importAs === "files") {
+      importMeshDialog.value = null;
+      await addFiles({
+        type: "in-memory",
+        value: [
+          { name: dialog.file.name, value: await dialog.file.arrayBuffer() },
+        ],
+      });
+    } else if (importAs === "mesh") {
+      if (dialog.progress !== null) return;
+      const abort = new AbortController();
+      importMeshAbort = abort;
+      dialog.progress = { phase: "starting", fraction: 0 };
+      let mesh: ImportedMesh;
+      try {
+        mesh = await importMesh(
+          dialog.file,
+          options,
+          (phase, fraction) => {
+            dialog.progress = { phase, fraction };
+          },
+          abort.signal
+        );
+      } catch (error) {
+        if (abort.signal.aborted) return;
+        // Keep the dialog open, so that the import can be retried
+        dialog.progress = null;
+        throw error;
+      } finally {
+        if (importMeshAbort === abort) {
+          importMeshAbort = null;
+        }
+      }
+      importMeshDialog.value = null;
+      await addGeometryImage(dialog.file.name, options.layout, mesh);
+    } else {
+      assertUnreachable(importAs);
+    }
+  }
+
+  /** Saves the geometry image next to the scene, and adds a model that renders it */
+  function addGeometryImage(
+    meshFileName: string,
+    layout: string,
+    mesh: ImportedMesh
+  ) {
+    return filesystemCommands.add(async (sceneFiles) => {
+      const stem = meshFileName.replace(/\.[^.]*$/, "");
+      const texture = makeFilePath(stem + GeometryImageSuffix);
+      await sceneFiles.writeBinaryFile(texture, mesh.png);
+      await sceneFiles.writeTextFile(
+        makeFilePath(stem + GeometryImageMetadataSuffix),
+        mesh.metadata
+      );
+
+      const sceneFile = await sceneFiles.readTextFile(SceneFileName);
+      const scene: SerializedScene =
+        sceneFile !== null
+          ? deserializeScene(sceneFile)
+          : { $schema: SceneFileSchemaUrl, models: [] };
+      const model: SerializedModel = {
+        type: "model",
+        id: crypto.randomUUID(),
+        name: stem,
+        position: [0, 0, 0],
+        rotation: [0, 0, 0],
+        scale: 1,
+        // Not used, the geometry image replaces the shader
+        parametricShader: "",
+        material: {
+          color: [0.8, 0.8, 0.8],
+          roughness: 0.5,
+          metallic: 0,
+          emissive: [0, 0, 0],
+        },
+        instanceCount: 1,
+        geometryImage: {
+          texture,
+          layout,
+          boundsMin: mesh.boundsMin,
+          boundsMax: mesh.boundsMax,
+        },
+      };
+      scene.models.push(model);
+      await sceneFiles.writeTextFile(SceneFileName, serializeScene(scene, true));
+    });
+  }
+
   async function clearFiles() {
     await filesystemCommands.add((sceneFiles) =>
       Promise.all(
@@ -178,15 +308,22 @@ export const useFsStore = defineStore("fs-store", () => {
 
   return {
     importProjectDialog: computed(() => importProjectDialog.value),
+    importMeshDialog: computed(() => importMeshDialog.value),
     importFilesOrProject,
     importProject,
     addFiles,
     finishImport,
+    finishMeshImport,
     exportToZip,
     clearFiles,
   };
 });
 
+function isMeshFile(name: string): boolean {
+  const extension = getFileExtension(name)?.toLowerCase() ?? null;
+  return extension !== null && MeshFileExtensions.includes(extension);
+}
+
 async function tryImportProject(
   files: FileList
 ): Promise<ImportProjectDialog | null> {
@@ -38,6 +38,10 @@ const config: UserConfig = defineConfig({
   build: {
     target: "esnext",
   },
+  worker: {
+    // The mesh import worker loads the wasm module relative to import.meta.url
+    format: "es",
+  },
   server: {
     watch: {
       ignored: [